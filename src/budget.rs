@@ -0,0 +1,223 @@
+use miette::miette;
+
+use tokenator::{
+    errors::{AppError, BudgetError},
+    models::ResolvedModel,
+};
+
+use crate::output::{as_f64, format_number};
+
+/// Token budget prompts must fit within, set with the `--max-tokens` and `--max-percent` flags.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Budget {
+    /// Maximum number of prompt tokens.
+    pub max_tokens: Option<usize>,
+
+    /// Maximum share of the model context window, as a percentage.
+    pub max_percent: Option<f64>,
+}
+
+impl Budget {
+    /// Checks a token count for `model` against the budget.
+    ///
+    /// # Errors
+    /// Errors if:
+    /// - the token count is over either budget; or
+    /// - a percentage budget is set and the model context length is not known.
+    pub fn check(&self, model: &ResolvedModel, tokens: usize) -> miette::Result<()> {
+        if let Some(max_tokens) = self.max_tokens
+            && tokens > max_tokens
+        {
+            return Err(AppError::from(BudgetError {
+                advice: "Shorten the prompt, or raise the `--max-tokens` budget".to_owned(),
+                detail: format!(
+                    "Prompt uses {} tokens with `{}`, over the budget of {} tokens",
+                    format_number(tokens),
+                    model.name,
+                    format_number(max_tokens)
+                ),
+            })
+            .into());
+        }
+        if let Some(max_percent) = self.max_percent {
+            let Some(context_length) = model.context_length.filter(|length| *length > 0) else {
+                return Err(miette!(
+                    help = "Add a `context_length` for the model to the models file",
+                    "`--max-percent` needs the context length for `{}`, which is not known",
+                    model.name
+                ));
+            };
+            if exceeds_percent(tokens, context_length, max_percent) {
+                return Err(AppError::from(BudgetError {
+                    advice: "Shorten the prompt, or raise the `--max-percent` budget".to_owned(),
+                    detail: format!(
+                        "Prompt uses {} tokens with `{}`, over the budget of {max_percent}% of \
+                        the {} token context",
+                        format_number(tokens),
+                        model.name,
+                        format_number(context_length)
+                    ),
+                })
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the largest token count within the budget, taking the tighter of the two budgets
+    /// when both are set, or [`None`] if no budget is set.  A percentage budget is ignored when
+    /// `context_length` is not known.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn limit(&self, context_length: Option<usize>) -> Option<usize> {
+        let percent_limit =
+            self.max_percent
+                .zip(context_length)
+                .map(|(max_percent, context_length)| {
+                    (max_percent * as_f64(context_length) / 100.0).floor() as usize
+                });
+
+        match (self.max_tokens, percent_limit) {
+            (Some(max_tokens), Some(percent_limit)) => Some(max_tokens.min(percent_limit)),
+            (max_tokens, percent_limit) => max_tokens.or(percent_limit),
+        }
+    }
+}
+
+/// Returns `true` if `tokens` is more than `max_percent` of the context window.
+fn exceeds_percent(tokens: usize, context_length: usize, max_percent: f64) -> bool {
+    as_f64(tokens) * 100.0 > max_percent * as_f64(context_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokenator::{
+        errors::{AppError, EXIT_BUDGET_EXCEEDED, exit_code},
+        models::ResolvedModel,
+        token::{TokenizerPin, TokenizerSource},
+    };
+
+    use crate::budget::Budget;
+
+    fn get_model_fixture() -> ResolvedModel {
+        ResolvedModel {
+            name: "example:latest".to_owned(),
+            tokenizer: TokenizerSource::Hub("example/Example-1-M-state-of-the-art".to_owned()),
+            pin: TokenizerPin::default(),
+            context_length: Some(32_768),
+            max_output_tokens: None,
+        }
+    }
+
+    #[test]
+    fn check_accepts_prompts_within_budget() {
+        // arrange
+        let model = get_model_fixture();
+        let budget = Budget {
+            max_tokens: Some(1_000),
+            max_percent: Some(50.0),
+        };
+
+        // act
+        let outcome = [0, 1_000].map(|tokens| budget.check(&model, tokens).is_ok());
+
+        // assert
+        assert_eq!(outcome, [true, true]);
+        assert!(Budget::default().check(&model, 1_000_000).is_ok());
+    }
+
+    #[test]
+    fn check_returns_budget_error_when_over_max_tokens() {
+        // arrange
+        let model = get_model_fixture();
+        let budget = Budget {
+            max_tokens: Some(1_000),
+            max_percent: None,
+        };
+
+        // act
+        let outcome = budget.check(&model, 1_001).unwrap_err();
+
+        // assert
+        assert_eq!(
+            format!("{outcome}"),
+            "Prompt uses 1,001 tokens with `example:latest`, over the budget of 1,000 tokens"
+        );
+        assert!(matches!(
+            outcome.downcast_ref::<AppError>(),
+            Some(AppError::BudgetExceeded(_))
+        ));
+        assert_eq!(exit_code(&outcome), EXIT_BUDGET_EXCEEDED);
+    }
+
+    #[test]
+    fn check_returns_budget_error_when_over_max_percent() {
+        // arrange
+        let model = get_model_fixture();
+        let budget = Budget {
+            max_tokens: None,
+            max_percent: Some(80.0),
+        };
+
+        // act
+        let within = budget.check(&model, 26_214);
+        let outcome = budget.check(&model, 26_215).unwrap_err();
+
+        // assert
+        assert!(within.is_ok());
+        assert_eq!(
+            format!("{outcome}"),
+            "Prompt uses 26,215 tokens with `example:latest`, over the budget of 80% of the \
+                32,768 token context"
+        );
+        assert_eq!(exit_code(&outcome), EXIT_BUDGET_EXCEEDED);
+    }
+
+    #[test]
+    fn check_returns_error_for_max_percent_with_unknown_context_length() {
+        // arrange
+        let model = ResolvedModel {
+            context_length: None,
+            ..get_model_fixture()
+        };
+        let budget = Budget {
+            max_tokens: None,
+            max_percent: Some(80.0),
+        };
+
+        // act
+        let outcome = budget.check(&model, 1).unwrap_err();
+
+        // assert
+        assert_eq!(
+            format!("{outcome}"),
+            "`--max-percent` needs the context length for `example:latest`, which is not known"
+        );
+        assert_ne!(exit_code(&outcome), EXIT_BUDGET_EXCEEDED);
+    }
+
+    #[test]
+    fn limit_returns_the_tighter_budget() {
+        // arrange
+        let both = Budget {
+            max_tokens: Some(1_000),
+            max_percent: Some(80.0),
+        };
+        let percent = Budget {
+            max_tokens: None,
+            max_percent: Some(80.0),
+        };
+
+        // act
+        let outcome = [
+            both.limit(Some(32_768)),
+            both.limit(Some(1_000)),
+            percent.limit(Some(32_768)),
+            percent.limit(None),
+            Budget::default().limit(Some(32_768)),
+        ];
+
+        // assert
+        assert_eq!(outcome, [Some(1_000), Some(800), Some(26_214), None, None]);
+    }
+}
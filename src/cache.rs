@@ -0,0 +1,486 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use hf_hub::{Cache, Repo, RepoType};
+use miette::{Context, IntoDiagnostic};
+use tokenator::{
+    chat::{CHAT_TEMPLATE_FILENAME, TOKENIZER_CONFIG_FILENAME},
+    token::{CONFIG_FILENAME, TokenizerFormat},
+};
+
+/// A tokenizer file in the `hf_hub` cache.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedTokenizer {
+    /// Commit the cached revision points to.
+    pub commit: String,
+
+    /// Path of the tokenizer file, inside the snapshot for the commit.
+    pub path: PathBuf,
+
+    /// Size of the tokenizer file, in bytes.
+    pub size: u64,
+}
+
+/// Space freed by removing files from the cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Removed {
+    /// Number of snapshots, or repos tokenizer files were removed from.
+    pub count: usize,
+
+    /// Total size of the files removed, in bytes.
+    pub bytes: u64,
+}
+
+/// Returns the directory the `hf_hub` cache keeps files for a model repo in.
+pub fn repo_dir(cache: &Cache, repo_id: &str) -> PathBuf {
+    cache
+        .path()
+        .join(Repo::new(repo_id.to_owned(), RepoType::Model).folder_name())
+}
+
+/// Looks up the main tokenizer file for a repo revision in the cache, in any of the
+/// [`TokenizerFormat::ALL`] formats.
+///
+/// # Returns
+/// The cached tokenizer, or [`None`] if it was never downloaded.
+pub fn cached_tokenizer(cache: &Cache, repo_id: &str, revision: &str) -> Option<CachedTokenizer> {
+    let repo = Repo::with_revision(repo_id.to_owned(), RepoType::Model, revision.to_owned());
+    let path = TokenizerFormat::ALL
+        .iter()
+        .find_map(|format| cache.repo(repo.clone()).get(format.filenames()[0]))?;
+    let commit = fs::read_to_string(repo_dir(cache, repo_id).join("refs").join(revision)).ok()?;
+    let size = fs::metadata(&path).ok()?.len();
+
+    Some(CachedTokenizer {
+        commit: commit.trim().to_owned(),
+        path,
+        size,
+    })
+}
+
+/// Returns the total size of the regular files under `path`, without following symbolic links,
+/// so files shared between snapshots are only counted once, as blobs.
+fn size_on_disk(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if metadata.is_dir() {
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| size_on_disk(&entry.path()))
+            .sum()
+    } else if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
+
+/// Returns the names of the files tokenator downloads into a repo: the tokenizer files, and any
+/// added token files, in each of the [`TokenizerFormat::ALL`] formats, the model and tokenizer
+/// configuration files, and the chat template.
+fn tokenator_filenames() -> HashSet<&'static str> {
+    TokenizerFormat::ALL
+        .iter()
+        .flat_map(|format| {
+            format
+                .filenames()
+                .iter()
+                .chain(format.optional_filenames())
+                .copied()
+        })
+        .chain([
+            CONFIG_FILENAME,
+            TOKENIZER_CONFIG_FILENAME,
+            CHAT_TEMPLATE_FILENAME,
+        ])
+        .collect()
+}
+
+/// Returns `true` if `path` is a directory with nothing in it.
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Removes the tokenizer files tokenator downloads from a snapshot, adding the blobs they linked
+/// to to `unlinked_blobs`, then removes the snapshot directory if nothing else is left in it.
+///
+/// # Returns
+/// A `miette::Result` containing `true` if any files were removed.
+fn remove_snapshot_files(
+    snapshot: &Path,
+    removed: &mut Removed,
+    unlinked_blobs: &mut HashSet<String>,
+) -> miette::Result<bool> {
+    let mut any_removed = false;
+    for filename in tokenator_filenames() {
+        let path = snapshot.join(filename);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if metadata.is_symlink() {
+            if let Some(blob) = fs::read_link(&path)
+                .ok()
+                .and_then(|target| Some(target.file_name()?.to_string_lossy().into_owned()))
+            {
+                unlinked_blobs.insert(blob);
+            }
+        } else {
+            removed.bytes += metadata.len();
+        }
+        any_removed = true;
+        fs::remove_file(&path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Removing cached file `{}`", path.display()))?;
+    }
+    if is_empty_dir(snapshot) {
+        fs::remove_dir(snapshot)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Removing cached snapshot `{}`", snapshot.display()))?;
+    }
+
+    Ok(any_removed)
+}
+
+/// Removes the blobs in `unlinked_blobs` which no snapshot of the repo in `dir` links to any
+/// more.  Blobs may be shared with files other tools downloaded, when the content is the same, and
+/// `.incomplete` downloads, which another process may still be writing, are never removed.
+fn remove_unlinked_blobs(
+    dir: &Path,
+    unlinked_blobs: &HashSet<String>,
+    removed: &mut Removed,
+) -> miette::Result<()> {
+    let blobs_dir = dir.join("blobs");
+    let live_blobs = linked_blobs(&dir.join("snapshots"));
+    for blob in unlinked_blobs.difference(&live_blobs) {
+        let path = blobs_dir.join(blob);
+        if blob.ends_with(".incomplete") || !path.is_file() {
+            continue;
+        }
+        removed.bytes += size_on_disk(&path);
+        fs::remove_file(&path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Removing cached blob `{}`", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Removes the tokenizer files tokenator downloads for a repo from every snapshot, along with the
+/// blobs no remaining snapshot links to.  Other files in the repo, such as model weights other
+/// tools downloaded to the shared cache, are kept, and the repo directory is only removed once
+/// nothing else is left in it.
+///
+/// # Returns
+/// A `miette::Result` containing the space freed, which is empty if nothing was cached.
+///
+/// # Errors
+/// Errors if unable to read or remove files in the repo cache directory.
+pub fn remove_repo(cache: &Cache, repo_id: &str) -> miette::Result<Removed> {
+    let dir = repo_dir(cache, repo_id);
+    let snapshots_dir = dir.join("snapshots");
+    if !snapshots_dir.is_dir() {
+        return Ok(Removed::default());
+    }
+
+    let mut removed = Removed::default();
+    let mut unlinked_blobs = HashSet::new();
+    for snapshot in read_dir(&snapshots_dir)? {
+        if remove_snapshot_files(&snapshot.path(), &mut removed, &mut unlinked_blobs)? {
+            removed.count = 1;
+        }
+    }
+    remove_unlinked_blobs(&dir, &unlinked_blobs, &mut removed)?;
+
+    // Only refs, pointing to removed snapshots, are left
+    let blobs_dir = dir.join("blobs");
+    if is_empty_dir(&snapshots_dir) && (is_empty_dir(&blobs_dir) || !blobs_dir.exists()) {
+        fs::remove_dir_all(&dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Removing cache directory `{}`", dir.display()))?;
+    }
+
+    Ok(removed)
+}
+
+/// Removes the tokenizer files tokenator downloads from snapshots of a cached repo which no
+/// branch, tag or revision points to any more, along with the blobs only those files used.  These
+/// are left behind when a tokenizer is updated upstream and downloaded again.  As with
+/// [`remove_repo`], other files in stale snapshots are kept, since other tools may download a
+/// commit without writing a ref, and a snapshot directory is only removed once it is empty.
+///
+/// # Returns
+/// A `miette::Result` containing the space freed.
+///
+/// # Errors
+/// Errors if unable to read or remove files in the repo cache directory.
+pub fn prune_stale_snapshots(cache: &Cache, repo_id: &str) -> miette::Result<Removed> {
+    let dir = repo_dir(cache, repo_id);
+    let snapshots_dir = dir.join("snapshots");
+    if !snapshots_dir.is_dir() {
+        return Ok(Removed::default());
+    }
+    let live_commits = referenced_commits(&dir.join("refs"));
+
+    let mut removed = Removed::default();
+    let mut unlinked_blobs = HashSet::new();
+    for entry in read_dir(&snapshots_dir)? {
+        let commit = entry.file_name().to_string_lossy().into_owned();
+        if live_commits.contains(&commit) {
+            continue;
+        }
+        if remove_snapshot_files(&entry.path(), &mut removed, &mut unlinked_blobs)? {
+            removed.count += 1;
+        }
+    }
+    remove_unlinked_blobs(&dir, &unlinked_blobs, &mut removed)?;
+
+    Ok(removed)
+}
+
+/// Lists the entries in a cache directory.
+fn read_dir(dir: &Path) -> miette::Result<Vec<fs::DirEntry>> {
+    fs::read_dir(dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Reading cache directory `{}`", dir.display()))?
+        .collect::<Result<_, _>>()
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Reading cache directory `{}`", dir.display()))
+}
+
+/// Returns the commits pointed to by the files under `refs_dir`.  Revisions with a `/`, such as
+/// `refs/pr/1`, are kept in subdirectories.
+fn referenced_commits(refs_dir: &Path) -> HashSet<String> {
+    let Ok(entries) = fs::read_dir(refs_dir) else {
+        return HashSet::new();
+    };
+
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                referenced_commits(&path).into_iter().collect::<Vec<_>>()
+            } else {
+                fs::read_to_string(&path)
+                    .map(|commit| vec![commit.trim().to_owned()])
+                    .unwrap_or_default()
+            }
+        })
+        .collect()
+}
+
+/// Returns the names of the blobs linked to from the snapshots under `dir`.
+fn linked_blobs(dir: &Path) -> HashSet<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashSet::new();
+    };
+
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let path = entry.path();
+            if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink()) {
+                fs::read_link(&path)
+                    .ok()
+                    .and_then(|target| Some(target.file_name()?.to_string_lossy().into_owned()))
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            } else if path.is_dir() {
+                linked_blobs(&path).into_iter().collect()
+            } else {
+                Vec::new()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteStr, PathChild},
+    };
+    use hf_hub::Cache;
+
+    use crate::cache::{
+        CachedTokenizer, Removed, cached_tokenizer, prune_stale_snapshots, remove_repo,
+    };
+
+    /// Creates a cache holding two snapshots of `example/Example-7-B`, with `main` pointing to
+    /// the newer one.  Both also hold model weights another tool downloaded, which is still
+    /// downloading another blob.  Snapshot files link to blobs, as in caches written by `hf_hub`.
+    fn get_cache_fixture() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_dir = temp_dir.child("models--example--Example-7-B");
+        let _ = repo_dir.child("refs/main").write_str("bbbb");
+        let _ = repo_dir.child("blobs/old-tokenizer").write_str("{\"v\":1}");
+        let _ = repo_dir
+            .child("blobs/new-tokenizer")
+            .write_str("{\"v\":2}!");
+        let _ = repo_dir.child("blobs/config").write_str("{}");
+        let _ = repo_dir.child("blobs/weights").write_str("weights");
+        let _ = repo_dir.child("blobs/old-weights").write_str("old weights");
+        let _ = repo_dir
+            .child("blobs/partial.incomplete")
+            .write_str("partial");
+        for (commit, blob) in [("aaaa", "old-tokenizer"), ("bbbb", "new-tokenizer")] {
+            let snapshot = repo_dir.child("snapshots").child(commit);
+            fs::create_dir_all(snapshot.path()).unwrap();
+            std::os::unix::fs::symlink(
+                format!("../../blobs/{blob}"),
+                snapshot.path().join("tokenizer.json"),
+            )
+            .unwrap();
+            std::os::unix::fs::symlink("../../blobs/config", snapshot.path().join("config.json"))
+                .unwrap();
+        }
+        for (commit, blob) in [("aaaa", "old-weights"), ("bbbb", "weights")] {
+            std::os::unix::fs::symlink(
+                format!("../../blobs/{blob}"),
+                repo_dir
+                    .path()
+                    .join(format!("snapshots/{commit}/model.safetensors")),
+            )
+            .unwrap();
+        }
+
+        temp_dir
+    }
+
+    #[test]
+    fn cached_tokenizer_finds_the_revision_commit_and_size() {
+        // arrange
+        let temp_dir = get_cache_fixture();
+        let cache = Cache::new(temp_dir.path().to_path_buf());
+
+        // act
+        let cached = cached_tokenizer(&cache, "example/Example-7-B", "main");
+        let missing = cached_tokenizer(&cache, "example/Example-7-B", "v1.0");
+
+        // assert
+        assert_eq!(
+            cached,
+            Some(CachedTokenizer {
+                commit: "bbbb".to_owned(),
+                path: temp_dir
+                    .path()
+                    .join("models--example--Example-7-B/snapshots/bbbb/tokenizer.json"),
+                size: 8,
+            })
+        );
+        assert_eq!(missing, None);
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn prune_stale_snapshots_keeps_referenced_snapshots_and_shared_blobs() {
+        // arrange
+        let temp_dir = get_cache_fixture();
+        let cache = Cache::new(temp_dir.path().to_path_buf());
+        let repo_dir = temp_dir.path().join("models--example--Example-7-B");
+
+        // act
+        let removed = prune_stale_snapshots(&cache, "example/Example-7-B").unwrap();
+
+        // assert
+        assert_eq!(removed, Removed { count: 1, bytes: 7 });
+        assert!(!repo_dir.join("snapshots/aaaa/tokenizer.json").exists());
+        assert!(!repo_dir.join("snapshots/aaaa/config.json").exists());
+        assert!(!repo_dir.join("blobs/old-tokenizer").exists());
+        assert!(repo_dir.join("blobs/config").exists());
+        assert_eq!(
+            fs::read_to_string(repo_dir.join("snapshots/aaaa/model.safetensors")).unwrap(),
+            "old weights"
+        );
+        assert!(repo_dir.join("blobs/partial.incomplete").exists());
+        assert_eq!(
+            cached_tokenizer(&cache, "example/Example-7-B", "main").map(|cached| cached.commit),
+            Some("bbbb".to_owned())
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn remove_repo_removes_tokenizer_files_and_keeps_other_files() {
+        // arrange
+        let temp_dir = get_cache_fixture();
+        let cache = Cache::new(temp_dir.path().to_path_buf());
+        let repo_dir = temp_dir.path().join("models--example--Example-7-B");
+
+        // act
+        let removed = remove_repo(&cache, "example/Example-7-B").unwrap();
+        let nothing = remove_repo(&cache, "example/Example-7-B").unwrap();
+
+        // assert
+        assert_eq!(
+            removed,
+            Removed {
+                count: 1,
+                bytes: 17
+            }
+        );
+        assert_eq!(nothing, Removed::default());
+        assert!(!repo_dir.join("snapshots/aaaa/tokenizer.json").exists());
+        assert!(!repo_dir.join("snapshots/bbbb/tokenizer.json").exists());
+        assert!(!repo_dir.join("blobs/config").exists());
+        assert_eq!(
+            fs::read_to_string(repo_dir.join("snapshots/aaaa/model.safetensors")).unwrap(),
+            "old weights"
+        );
+        assert_eq!(
+            fs::read_to_string(repo_dir.join("snapshots/bbbb/model.safetensors")).unwrap(),
+            "weights"
+        );
+        assert!(repo_dir.join("blobs/partial.incomplete").exists());
+        assert_eq!(
+            cached_tokenizer(&cache, "example/Example-7-B", "main"),
+            None
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn remove_repo_removes_the_repo_directory_when_only_tokenizer_files_are_cached() {
+        // arrange
+        let temp_dir = get_cache_fixture();
+        let cache = Cache::new(temp_dir.path().to_path_buf());
+        let repo_dir = temp_dir.path().join("models--example--Example-7-B");
+        for commit in ["aaaa", "bbbb"] {
+            fs::remove_file(repo_dir.join(format!("snapshots/{commit}/model.safetensors")))
+                .unwrap();
+        }
+        for blob in ["weights", "old-weights", "partial.incomplete"] {
+            fs::remove_file(repo_dir.join("blobs").join(blob)).unwrap();
+        }
+
+        // act
+        let removed = remove_repo(&cache, "example/Example-7-B").unwrap();
+
+        // assert
+        assert_eq!(
+            removed,
+            Removed {
+                count: 1,
+                bytes: 17
+            }
+        );
+        assert!(!repo_dir.exists());
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,552 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use minijinja::{
+    Environment, Error, ErrorKind, Value, context, syntax::SyntaxConfig, value::Serde,
+};
+use serde::Deserialize;
+
+use crate::{
+    errors::{AppError, ChatError, ModelFileError},
+    token::{
+        HubOptions, TokenizerBackend, TokenizerSource, get_repo_file, is_missing_file,
+        local_model_file,
+    },
+};
+
+/// Name of the tokenizer configuration file, which holds the chat template for most models.
+pub const TOKENIZER_CONFIG_FILENAME: &str = "tokenizer_config.json";
+
+/// Name of the standalone chat template file, used by newer repos instead of the
+/// `chat_template` field of the tokenizer configuration.
+pub const CHAT_TEMPLATE_FILENAME: &str = "chat_template.jinja";
+
+/// Name the chat template is registered under in the template environment.
+const TEMPLATE_NAME: &str = "chat";
+
+/// A single message in a conversation, in the `OpenAI` chat format.
+pub struct Message {
+    pub role: String,
+
+    /// The whole message object, passed to the chat template as is, so templates can use fields
+    /// such as `tool_calls` along with `role` and `content`.
+    value: serde_json::Value,
+}
+
+/// Messages file content, which is either an array of messages, or a chat request body with a
+/// `messages` array.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessagesFile {
+    Messages(Vec<serde_json::Value>),
+    Request { messages: Vec<serde_json::Value> },
+}
+
+/// Parses the content of a messages file, checking each message has a `role`, and `content` which
+/// is a string, an array of content parts or `null`.
+fn parse_messages(content: &str) -> Result<Vec<Message>, String> {
+    let (MessagesFile::Messages(messages) | MessagesFile::Request { messages }) =
+        serde_json::from_str(content).map_err(|_| {
+            "Expected an array of messages, or an object with a `messages` array".to_owned()
+        })?;
+
+    messages
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let number = index + 1;
+            let role = value
+                .get("role")
+                .and_then(serde_json::Value::as_str)
+                .ok_or_else(|| format!("Message {number} has no `role` string"))?
+                .to_owned();
+            match value.get("content") {
+                None
+                | Some(
+                    serde_json::Value::Null
+                    | serde_json::Value::String(_)
+                    | serde_json::Value::Array(_),
+                ) => {}
+                Some(_) => {
+                    return Err(format!(
+                        "Message {number} `content` is not a string or an array of parts"
+                    ));
+                }
+            }
+
+            Ok(Message { role, value })
+        })
+        .collect()
+}
+
+/// Parses a conversation, in the `OpenAI` chat format, from JSON `content`.
+///
+/// # Errors
+/// Errors if `content` is not a valid messages array, or it has no messages.
+pub fn messages_from_json(content: &str) -> Result<Vec<Message>, AppError> {
+    let messages = parse_messages(content)
+        .map_err(|error| ChatError::messages(format!("Invalid messages: {error}")))?;
+    if messages.is_empty() {
+        return Err(ChatError::messages("There are no messages".to_owned()).into());
+    }
+
+    Ok(messages)
+}
+
+/// Parses the conversation read from the messages file at `path`.  The path is only used in
+/// error messages.
+///
+/// # Errors
+/// Errors if `content` is not a valid messages array, or it has no messages.
+pub fn messages_from_file(path: &Path, content: &str) -> Result<Vec<Message>, AppError> {
+    let messages = parse_messages(content).map_err(|error| {
+        ChatError::messages(format!(
+            "Invalid messages file `{}`: {error}",
+            path.display()
+        ))
+    })?;
+    if messages.is_empty() {
+        return Err(ChatError::messages(format!(
+            "Messages file `{}` has no messages",
+            path.display()
+        ))
+        .into());
+    }
+
+    Ok(messages)
+}
+
+/// A token string in the tokenizer configuration, which is either the token itself, or an added
+/// token object with the token as its `content`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigToken {
+    Text(String),
+    AddedToken { content: String },
+}
+
+/// A named template, for models with several chat templates, such as one for tool use.
+#[derive(Deserialize)]
+struct NamedTemplate {
+    name: String,
+    template: String,
+}
+
+/// The `chat_template` field of the tokenizer configuration.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigTemplate {
+    Single(String),
+    Named(Vec<NamedTemplate>),
+}
+
+/// A chat template, ready to render conversations with.
+pub struct ChatTemplate {
+    environment: Environment<'static>,
+
+    /// Special tokens from the tokenizer configuration, such as `bos_token`, which templates use
+    /// by name.
+    special_tokens: BTreeMap<String, String>,
+}
+
+/// Fails the render with `message`.  Templates call this to reject conversations they do not
+/// support, such as ones where roles do not alternate.
+#[allow(clippy::needless_pass_by_value)]
+fn raise_exception(message: String) -> Result<Value, Error> {
+    Err(Error::new(ErrorKind::InvalidOperation, message))
+}
+
+impl ChatTemplate {
+    /// Builds a chat template from the content of a tokenizer configuration file.  A template
+    /// in the configuration takes priority over `template_file`, the content of a standalone
+    /// `chat_template.jinja` file.  Templates are rendered the way `transformers` renders them,
+    /// with blocks trimmed, and with Python string, list and dict methods available.
+    ///
+    /// # Errors
+    /// Errors if there is no chat template, or it is not a valid template.
+    fn new(config: &str, template_file: Option<String>) -> Result<Self, AppError> {
+        let config: serde_json::Map<String, serde_json::Value> = serde_json::from_str(config)
+            .map_err(|error| {
+                ChatError::template(format!("Invalid tokenizer configuration: {error}"))
+            })?;
+        let template = match config.get("chat_template").cloned() {
+            Some(template) => match serde_json::from_value(template) {
+                Ok(ConfigTemplate::Single(template)) => template,
+                Ok(ConfigTemplate::Named(templates)) => templates
+                    .into_iter()
+                    .find(|template| template.name == "default")
+                    .map(|template| template.template)
+                    .ok_or_else(|| {
+                        ChatError::template(
+                            "The tokenizer configuration has no default chat template".to_owned(),
+                        )
+                    })?,
+                Err(_) => {
+                    return Err(ChatError::template(
+                        "The tokenizer configuration `chat_template` is not a template".to_owned(),
+                    )
+                    .into());
+                }
+            },
+            None => template_file.ok_or_else(|| ChatError {
+                advice: "Base models often have no chat template, try the instruction-tuned \
+                    model instead"
+                    .to_owned(),
+                detail: "The model has no chat template".to_owned(),
+            })?,
+        };
+        let special_tokens = config
+            .iter()
+            .filter(|(key, _)| key.ends_with("_token"))
+            .filter_map(|(key, value)| {
+                let token = match serde_json::from_value(value.clone()).ok()? {
+                    ConfigToken::Text(token) | ConfigToken::AddedToken { content: token } => token,
+                };
+                Some((key.clone(), token))
+            })
+            .collect();
+
+        let mut environment = Environment::new();
+        environment.set_syntax(
+            SyntaxConfig::builder()
+                .trim_blocks(true)
+                .lstrip_blocks(true)
+                .build()
+                .expect("default delimiters should be valid"),
+        );
+        environment
+            .set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+        environment.add_function("raise_exception", raise_exception);
+        environment
+            .add_template_owned(TEMPLATE_NAME, template)
+            .map_err(|error| ChatError::template(format!("Invalid chat template: {error:#}")))?;
+
+        Ok(Self {
+            environment,
+            special_tokens,
+        })
+    }
+
+    /// Renders the conversation as the prompt text the model sees.  With `add_generation_prompt`,
+    /// the text ends with the start of an assistant message, as when asking the model to reply.
+    ///
+    /// # Errors
+    /// Errors if the template fails, for example because it does not support the roles used.
+    ///
+    /// # Panics
+    /// Panics if the template was not added to the environment, which building the template
+    /// always does.
+    pub fn render(
+        &self,
+        messages: &[Message],
+        add_generation_prompt: bool,
+    ) -> Result<String, AppError> {
+        let messages: Vec<&serde_json::Value> =
+            messages.iter().map(|message| &message.value).collect();
+        let template = self
+            .environment
+            .get_template(TEMPLATE_NAME)
+            .expect("chat template should have been added");
+
+        template
+            .render(context! {
+                messages => Value::from(Serde(&messages)),
+                add_generation_prompt,
+                ..Value::from(Serde(&self.special_tokens))
+            })
+            .map_err(|error| {
+                ChatError {
+                    advice: "Check the conversation uses the roles, and role order, the model \
+                        expects"
+                        .to_owned(),
+                    detail: format!("Unable to render the chat template: {error:#}"),
+                }
+                .into()
+            })
+    }
+}
+
+/// Reads the content of a model file, returning [`None`] when the file does not exist.
+fn read_model_file(
+    source: &TokenizerSource,
+    revision: &str,
+    filename: &str,
+    hub: &HubOptions,
+) -> Result<Option<String>, AppError> {
+    let path = match source {
+        TokenizerSource::Hub(repo_id) => match get_repo_file(repo_id, revision, filename, hub) {
+            Ok(path) => path,
+            Err(error) if is_missing_file(&error) => return Ok(None),
+            Err(error) => return Err(error),
+        },
+        TokenizerSource::Path(path) => {
+            let path = local_model_file(path, filename);
+            if !path.is_file() {
+                return Ok(None);
+            }
+            path
+        }
+        TokenizerSource::Tiktoken(_) => return Ok(None),
+    };
+    let content =
+        fs::read_to_string(&path).map_err(|error| ModelFileError::unreadable(&path, error))?;
+
+    Ok(Some(content))
+}
+
+/// Loads the chat template for a model, from the repo, or from the directory holding a local
+/// tokenizer.  The template comes from the `tokenizer_config.json` file, falling back to a
+/// `chat_template.jinja` file.
+///
+/// # Errors
+/// Errors if the model has no tokenizer configuration or chat template, as for tiktoken
+/// encodings, or unable to download them.
+pub fn load_chat_template(
+    source: &TokenizerSource,
+    revision: &str,
+    hub: &HubOptions,
+) -> Result<ChatTemplate, AppError> {
+    if let TokenizerSource::Tiktoken(encoding) = source {
+        return Err(ChatError {
+            advice: "Count the conversation with a Hugging Face model, or a local tokenizer \
+                directory holding a `tokenizer_config.json` file"
+                .to_owned(),
+            detail: format!("The tiktoken encoding `{encoding}` has no chat template"),
+        }
+        .into());
+    }
+    let config =
+        read_model_file(source, revision, TOKENIZER_CONFIG_FILENAME, hub)?.ok_or_else(|| {
+            ChatError::template(format!(
+                "`{source}` has no `{TOKENIZER_CONFIG_FILENAME}` file"
+            ))
+        })?;
+    let template_file = if serde_json::from_str::<serde_json::Value>(&config)
+        .is_ok_and(|config| config.get("chat_template").is_some())
+    {
+        None
+    } else {
+        read_model_file(source, revision, CHAT_TEMPLATE_FILENAME, hub)?
+    };
+
+    ChatTemplate::new(&config, template_file)
+}
+
+/// Tokens a single message adds to the conversation.
+pub struct MessageCount {
+    pub role: String,
+    pub tokens: usize,
+}
+
+/// Token counts for a conversation rendered through a chat template.
+pub struct ChatCounts {
+    /// Number of messages in the conversation.
+    pub messages: usize,
+
+    /// Tokens each message adds, when a per-message breakdown was asked for.
+    pub breakdown: Option<Vec<MessageCount>>,
+
+    /// Tokens the generation prompt adds, when the breakdown was asked for and the conversation
+    /// ends with a generation prompt.
+    pub generation_prompt: Option<usize>,
+
+    /// Tokens in the whole rendered conversation.
+    pub tokens: usize,
+
+    /// Characters in the rendered conversation.
+    pub chars: usize,
+
+    /// Bytes in the rendered conversation.
+    pub bytes: usize,
+}
+
+/// Options for counting a conversation.
+#[derive(Clone, Copy, Default)]
+pub struct ChatOptions {
+    /// End the conversation with the start of an assistant message.
+    pub add_generation_prompt: bool,
+
+    /// Work out how many tokens each message adds.
+    pub per_message: bool,
+}
+
+/// Renders the conversation through the chat template and counts the tokens.  As with
+/// `apply_chat_template` in `transformers`, the rendered text is encoded without adding special
+/// tokens, since the template already includes them.
+///
+/// The per-message breakdown renders each leading run of messages in turn, and counts the tokens
+/// each message adds to the run before it.  Templates which render earlier messages differently
+/// depending on later ones, such as by dropping reasoning from past turns, can make the breakdown
+/// differ from the total.
+///
+/// # Errors
+/// Errors if the template fails, or unable to encode the rendered text.
+pub fn count_chat(
+    tokeniser: &dyn TokenizerBackend,
+    template: &ChatTemplate,
+    messages: &[Message],
+    options: ChatOptions,
+) -> Result<ChatCounts, AppError> {
+    let add_special_tokens = false;
+    let count = |messages: &[Message], add_generation_prompt: bool| -> Result<usize, AppError> {
+        let rendered = template.render(messages, add_generation_prompt)?;
+
+        Ok(tokeniser.count(&rendered, add_special_tokens)?.tokens)
+    };
+
+    let rendered = template.render(messages, options.add_generation_prompt)?;
+    let tokens = tokeniser.count(&rendered, add_special_tokens)?.tokens;
+    let (breakdown, generation_prompt) = if options.per_message {
+        let mut breakdown = Vec::with_capacity(messages.len());
+        let mut previous = 0;
+        for end in 1..=messages.len() {
+            let tokens = count(&messages[..end], false)?;
+            breakdown.push(MessageCount {
+                role: messages[end - 1].role.clone(),
+                tokens: tokens.saturating_sub(previous),
+            });
+            previous = tokens;
+        }
+        let generation_prompt = options
+            .add_generation_prompt
+            .then(|| tokens.saturating_sub(previous));
+
+        (Some(breakdown), generation_prompt)
+    } else {
+        (None, None)
+    };
+
+    Ok(ChatCounts {
+        messages: messages.len(),
+        breakdown,
+        generation_prompt,
+        tokens,
+        chars: rendered.chars().count(),
+        bytes: rendered.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokenizers::Tokenizer;
+
+    use crate::chat::{ChatOptions, ChatTemplate, count_chat, parse_messages};
+
+    const CONFIG: &str = r#"{
+        "bos_token": "[BOS]",
+        "eos_token": {"content": "[EOS]", "special": true},
+        "chat_template": "{{ bos_token }}{% for message in messages %}\n{% if message.role not in ['system', 'user', 'assistant'] %}{{ raise_exception('Unknown role ' + message.role) }}{% endif %}\n{{ message.role }}: {{ message.content.strip() }}{{ eos_token }}{% endfor %}\n{% if add_generation_prompt %}assistant:{% endif %}"
+    }"#;
+
+    #[test]
+    fn render_applies_the_template_with_special_tokens() {
+        // arrange
+        let template = ChatTemplate::new(CONFIG, None).unwrap();
+        let messages = parse_messages(
+            r#"[{"role": "system", "content": "Be brief. "}, {"role": "user", "content": "Why?"}]"#,
+        )
+        .unwrap();
+
+        // act
+        let outcome = template.render(&messages, true).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            "[BOS]system: Be brief.[EOS]user: Why?[EOS]assistant:"
+        );
+    }
+
+    #[test]
+    fn render_reports_template_exceptions() {
+        // arrange
+        let template = ChatTemplate::new(CONFIG, None).unwrap();
+        let messages =
+            parse_messages(r#"{"messages": [{"role": "tool", "content": "42"}]}"#).unwrap();
+
+        // act
+        let outcome = template.render(&messages, false).unwrap_err();
+
+        // assert
+        assert!(outcome.to_string().contains("Unknown role tool"));
+    }
+
+    #[test]
+    fn parse_messages_rejects_invalid_messages() {
+        // arrange
+        let inputs = [
+            r#"{"role": "user", "content": "Hello"}"#,
+            r#"[{"content": "Hello"}]"#,
+            r#"[{"role": "user", "content": "Hello"}, {"role": "user", "content": 42}]"#,
+        ];
+
+        // act
+        let outcome = inputs.map(|input| parse_messages(input).err().unwrap_or_default());
+
+        // assert
+        assert_eq!(
+            outcome,
+            [
+                "Expected an array of messages, or an object with a `messages` array",
+                "Message 1 has no `role` string",
+                "Message 2 `content` is not a string or an array of parts",
+            ]
+        );
+    }
+
+    #[test]
+    fn count_chat_breaks_down_tokens_by_message() {
+        // arrange
+        let tokeniser = Tokenizer::from_file("tests/fixtures/tokenizer.json").unwrap();
+        let template = ChatTemplate::new(CONFIG, None).unwrap();
+        let messages = parse_messages(
+            r#"[{"role": "system", "content": "A prompt."}, {"role": "user", "content": "Why is the sky blue?"}]"#,
+        )
+        .unwrap();
+        let options = ChatOptions {
+            add_generation_prompt: true,
+            per_message: true,
+        };
+
+        // act
+        let outcome = count_chat(&tokeniser, &template, &messages, options).unwrap();
+
+        // assert
+        let breakdown: Vec<(&str, usize)> = outcome
+            .breakdown
+            .as_deref()
+            .unwrap()
+            .iter()
+            .map(|message| (message.role.as_str(), message.tokens))
+            .collect();
+        // [BOS], then role, `:`, the content and [EOS] for each message, with no [BOS] added by
+        // the tokenizer post-processor
+        assert_eq!(breakdown, [("system", 7), ("user", 9)]);
+        assert_eq!(outcome.generation_prompt, Some(2));
+        assert_eq!(outcome.tokens, 18);
+    }
+
+    #[test]
+    fn new_picks_the_default_named_template_or_the_template_file() {
+        // arrange
+        let named = r#"{"chat_template": [
+            {"name": "tool_use", "template": "tools"},
+            {"name": "default", "template": "default"}
+        ]}"#;
+        let without_template = r#"{"bos_token": "<s>"}"#;
+
+        // act
+        let named_outcome = ChatTemplate::new(named, None).unwrap().render(&[], false);
+        let file_outcome = ChatTemplate::new(without_template, Some("{{ bos_token }}".to_owned()))
+            .unwrap()
+            .render(&[], false);
+        let missing_outcome = ChatTemplate::new(without_template, None).map(drop);
+
+        // assert
+        assert_eq!(named_outcome.unwrap(), "default");
+        assert_eq!(file_outcome.unwrap(), "<s>");
+        assert_eq!(
+            missing_outcome.unwrap_err().to_string(),
+            "The model has no chat template"
+        );
+    }
+}
@@ -2,36 +2,309 @@ use std::path::PathBuf;
 
 use clap_verbosity_flag::Verbosity;
 
+use crate::output::OutputFormat;
+
 /// Command-line interface configuration.
 ///
 /// This struct is generated by `clap` and includes all command-line options and flags defined in
 /// this module.
 #[derive(clap::Parser)]
 #[clap(author, version, about, long_about=None)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Cli {
     /// Verbosity level (-v for warnings, -vv for info, -vvv for debug, -vvvv for trace)
     #[clap(flatten)]
     pub verbose: Verbosity,
 
-    /// Optional file to use as a prompt
+    /// Optional file to use as a prompt, repeat for several files (`-` reads standard input)
     #[clap(short, long, value_parser)]
-    pub file: Option<PathBuf>,
+    pub file: Vec<PathBuf>,
+
+    /// Include hidden files and directories when walking directories
+    #[clap(long)]
+    pub hidden: bool,
+
+    /// Do not respect `.gitignore`, `.ignore` or `.tokenatorignore` files when walking directories
+    #[clap(long)]
+    pub no_ignore: bool,
+
+    /// Only count files matching this glob when walking directories (repeatable)
+    #[clap(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip files matching this glob when walking directories (repeatable)
+    #[clap(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Output format
+    #[clap(long, value_enum, default_value_t, global = true)]
+    pub format: OutputFormat,
+
+    /// Leave out the special tokens, such as a beginning of sequence marker, which the tokenizer
+    /// adds to each prompt or file, for counting fragments of a single prompt
+    #[clap(long, conflicts_with = "chat")]
+    pub no_special_tokens: bool,
+
+    /// Show each token's ID, string and offsets in the prompt, instead of just the count
+    #[clap(long)]
+    pub show_tokens: bool,
+
+    /// Reprint the prompt with each token boundary marked, using colours in a terminal
+    #[clap(long, conflicts_with = "show_tokens")]
+    pub visualise: bool,
+
+    /// `OpenAI`-style messages file, a JSON array of `role` and `content` objects, to render
+    /// through the model chat template and count as a conversation (`-` reads standard input)
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["file", "inputs", "show_tokens", "visualise", "all_models", "baseline"]
+    )]
+    pub chat: Option<PathBuf>,
+
+    /// End the conversation with the start of an assistant reply, as when asking the model to
+    /// respond
+    #[clap(long, requires = "chat")]
+    pub add_generation_prompt: bool,
 
-    /// LLM model to count tokens for
+    /// Show the tokens each message adds to the conversation
+    #[clap(long, requires = "chat")]
+    pub per_message: bool,
+
+    /// LLM model to count tokens for, repeat to compare several models
     #[clap(short, long, value_parser)]
-    pub model: Option<String>,
+    pub model: Vec<String>,
+
+    /// Compare token counts for every model in the models file
+    #[clap(long, conflicts_with = "model")]
+    pub all_models: bool,
+
+    /// Local `tokenizer.json`, `tokenizer.model` or `.tiktoken` rank file, or a directory holding
+    /// tokenizer files, to count tokens with instead of a named model
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["model", "all_models"])]
+    pub tokenizer: Option<PathBuf>,
+
+    /// Commit SHA, tag or branch to download tokenizers from, overriding the models file revision
+    #[clap(long, value_name = "REVISION", conflicts_with = "tokenizer")]
+    pub revision: Option<String>,
+
+    /// Model to compare other models' token counts to (defaults to the first model)
+    #[clap(long, value_name = "MODEL")]
+    pub baseline: Option<String>,
+
+    /// Models file, overriding built-in, user and project models with the same name
+    #[clap(long, value_name = "PATH", global = true)]
+    pub models_file: Option<PathBuf>,
+
+    /// Only use tokenizers already in the Hugging Face cache, without contacting Hugging Face (also
+    /// set by `TOKENATOR_OFFLINE` or `HF_HUB_OFFLINE`)
+    #[clap(long, global = true)]
+    pub offline: bool,
+
+    /// Hugging Face cache directory, instead of `$HF_HOME/hub` or `~/.cache/huggingface/hub`
+    #[clap(long, value_name = "PATH", global = true)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Fail with exit status 5 if a prompt uses more than this many tokens
+    #[clap(long, value_name = "N")]
+    pub max_tokens: Option<usize>,
+
+    /// Fail with exit status 5 if a prompt uses more than this percentage of the context window
+    #[clap(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub max_percent: Option<f64>,
+
+    /// Keep counting, reprinting the counts with the changes from the last count whenever a file
+    /// changes
+    #[clap(long, conflicts_with_all = ["chat", "show_tokens", "visualise"])]
+    pub watch: bool,
+
+    /// Prompt, or files, directories and glob patterns to count tokens for
+    pub inputs: Vec<String>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands, run instead of counting tokens.
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// List, add, remove and check models in the models registry
+    Models {
+        #[clap(subcommand)]
+        command: ModelsCommand,
+    },
+
+    /// Show, download and remove tokenizers in the Hugging Face cache
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+
+    /// Run an HTTP server which keeps tokenizers loaded between requests
+    Serve {
+        /// Port to listen on
+        #[clap(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to listen on, use `0.0.0.0` to accept connections from other hosts
+        #[clap(long, value_name = "ADDRESS", default_value = "127.0.0.1")]
+        host: String,
+
+        /// Most tokenizers to keep loaded, unloading the least recently used beyond this
+        #[clap(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+        max_tokenizers: u16,
+
+        /// Model to load when the server starts, rather than on its first request (repeatable)
+        #[clap(long, value_name = "MODEL")]
+        preload: Vec<String>,
+    },
+
+    /// Run an MCP server on standard input and output, for coding agents to count tokens with
+    Mcp {
+        /// Most tokenizers to keep loaded, unloading the least recently used beyond this
+        #[clap(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+        max_tokenizers: u16,
+    },
+
+    /// Run a language server on standard input and output, showing token counts in editors
+    Lsp {
+        /// Model to count tokens for, unless set in the editor settings
+        #[clap(short, long, value_name = "MODEL")]
+        model: Option<String>,
+
+        /// Warn when a document uses more than this many tokens
+        #[clap(long, value_name = "N")]
+        max_tokens: Option<usize>,
+
+        /// Warn when a document uses more than this percentage of the context window
+        #[clap(long, value_name = "PERCENT", value_parser = parse_percent)]
+        max_percent: Option<f64>,
+    },
+}
+
+/// Subcommands for managing the models registry.
+#[derive(clap::Subcommand)]
+pub enum ModelsCommand {
+    /// List every model, with its repo, context length and the layer it comes from
+    List,
+
+    /// Add a model to the user models file, or replace a model with the same name
+    Add {
+        /// Name to select the model by
+        name: String,
+
+        /// Hugging Face repo holding the model tokenizer, such as `Qwen/Qwen3-8B`
+        #[clap(required_unless_present_any = ["path", "tiktoken"])]
+        hf_repo: Option<String>,
+
+        /// Local `tokenizer.json` file, or a directory holding one, instead of a Hugging Face repo
+        #[clap(long, value_name = "PATH", conflicts_with_all = ["hf_repo", "tiktoken"])]
+        path: Option<PathBuf>,
+
+        /// Built-in tiktoken encoding, such as `o200k_base`, or a local `.tiktoken` rank file,
+        /// instead of a Hugging Face repo
+        #[clap(long, value_name = "ENCODING", conflicts_with = "hf_repo")]
+        tiktoken: Option<String>,
+
+        /// Commit SHA, tag or branch of the repo to download the tokenizer from
+        #[clap(long, value_name = "REVISION", requires = "hf_repo")]
+        revision: Option<String>,
 
-    /// Prompt
-    pub prompt: Option<String>,
+        /// Expected SHA-256 hash of the tokenizer file, as hex
+        #[clap(long, value_name = "HASH")]
+        sha256: Option<String>,
+
+        /// Maximum number of tokens the model accepts
+        #[clap(long, value_name = "N")]
+        context_length: Option<usize>,
+
+        /// Maximum number of tokens the model generates in a single response
+        #[clap(long, value_name = "N")]
+        max_output_tokens: Option<usize>,
+
+        /// Add the model to the project models file, `.tokenator/models.json`, instead
+        #[clap(long)]
+        project: bool,
+    },
+
+    /// Remove a model from the user models file
+    Remove {
+        /// Name of the model to remove
+        name: String,
+
+        /// Remove the model from the project models file, `.tokenator/models.json`, instead
+        #[clap(long)]
+        project: bool,
+    },
+
+    /// Show details for a single model
+    Show {
+        /// Name of the model to show
+        name: String,
+    },
+
+    /// Check models files for mistakes, and check each repo has a tokenizer
+    Validate {
+        /// Only check models file entries, without contacting Hugging Face
+        #[clap(long)]
+        local: bool,
+    },
+}
+
+/// Subcommands for managing cached tokenizer files.
+#[derive(clap::Subcommand)]
+pub enum CacheCommand {
+    /// List models in the registry, with the revision, commit and size of any cached tokenizer
+    List,
+
+    /// Download tokenizers for the named models, or every model, ahead of working offline
+    Prefetch {
+        /// Models to download tokenizers for (defaults to every model in the registry)
+        models: Vec<String>,
+    },
+
+    /// Remove cached tokenizers for the named models
+    Clear {
+        /// Models to remove cached tokenizers for
+        #[clap(required_unless_present_any = ["all", "stale"])]
+        models: Vec<String>,
+
+        /// Remove cached tokenizers for every model in the registry
+        #[clap(long, conflicts_with = "models")]
+        all: bool,
+
+        /// Only remove old snapshots, which no revision points to any more
+        #[clap(long)]
+        stale: bool,
+    },
+
+    /// Print the cache directory
+    Path,
+}
+
+/// Parses a percentage greater than zero, for the `--max-percent` flag.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))?;
+    if percent.is_finite() && percent > 0.0 {
+        Ok(percent)
+    } else {
+        Err(format!("`{value}` is not a percentage greater than zero"))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::ffi::OsString;
+    use std::{ffi::OsString, path::PathBuf};
 
     use clap::Parser;
 
-    use crate::cli::Cli;
+    use crate::{
+        cli::{CacheCommand, Cli, Command, ModelsCommand},
+        output::OutputFormat,
+    };
 
     /// Parses CLI arguments and returns a `Cli` instance.
     fn parse_args<I, T>(args: I) -> Cli
@@ -77,4 +350,324 @@ mod tests {
         // assert
         assert_eq!(cli.verbose.log_level(), Some(log::Level::Info));
     }
+
+    #[test]
+    fn test_multiple_files_and_inputs() {
+        // arrange
+        let args = vec![
+            "program",
+            "-f",
+            "a.md",
+            "--file",
+            "b.md",
+            "src/",
+            "docs/*.md",
+        ];
+
+        // act
+        let cli = parse_args(&args);
+
+        // assert
+        assert_eq!(cli.file, vec![PathBuf::from("a.md"), PathBuf::from("b.md")]);
+        assert_eq!(cli.inputs, vec!["src/".to_owned(), "docs/*.md".to_owned()]);
+    }
+
+    #[test]
+    fn test_multiple_models() {
+        // arrange
+        let args = vec!["program", "-m", "qwen3:8b", "--model", "phi4-mini:3.8b"];
+
+        // act
+        let cli = parse_args(&args);
+
+        // assert
+        assert_eq!(
+            cli.model,
+            vec!["qwen3:8b".to_owned(), "phi4-mini:3.8b".to_owned()]
+        );
+        assert!(!cli.all_models);
+    }
+
+    #[test]
+    fn test_all_models_conflicts_with_model() {
+        // arrange
+        let args = vec!["program", "-m", "qwen3:8b", "--all-models"];
+
+        // act
+        let outcome = Cli::try_parse_from(args);
+
+        // assert
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn test_budget_flags() {
+        // arrange
+        let args = vec!["program", "--max-tokens", "4000", "--max-percent", "80%"];
+
+        // act
+        let cli = parse_args(&args);
+
+        // assert
+        assert_eq!(cli.max_tokens, Some(4_000));
+        assert_eq!(cli.max_percent, Some(80.0));
+    }
+
+    #[test]
+    fn test_max_percent_must_be_greater_than_zero() {
+        // arrange
+        let invalid_values = ["0", "-5", "many", "NaN"];
+
+        // act
+        let outcome = invalid_values
+            .map(|value| Cli::try_parse_from(["program", "--max-percent", value]).is_err());
+
+        // assert
+        assert_eq!(outcome, [true, true, true, true]);
+    }
+
+    #[test]
+    fn test_models_subcommand() {
+        // arrange
+        let args = vec![
+            "program",
+            "models",
+            "add",
+            "internal:8b",
+            "example/Internal-8B",
+            "--context-length",
+            "8192",
+            "--format",
+            "json",
+        ];
+
+        // act
+        let cli = parse_args(&args);
+
+        // assert
+        let Some(Command::Models {
+            command:
+                ModelsCommand::Add {
+                    name,
+                    hf_repo,
+                    context_length,
+                    project,
+                    ..
+                },
+        }) = cli.command
+        else {
+            panic!("expected `models add` subcommand");
+        };
+        assert_eq!(
+            (name.as_str(), hf_repo.as_deref(), context_length, project),
+            (
+                "internal:8b",
+                Some("example/Internal-8B"),
+                Some(8_192),
+                false
+            )
+        );
+        assert_eq!(cli.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn test_models_add_needs_exactly_one_tokenizer_source() {
+        // arrange
+        let neither = ["program", "models", "add", "internal:8b"];
+        let both = [
+            "program",
+            "models",
+            "add",
+            "internal:8b",
+            "example/Internal-8B",
+            "--path",
+            "tokenizer.json",
+        ];
+        let path = [
+            "program",
+            "models",
+            "add",
+            "internal:8b",
+            "--path",
+            "tokenizer.json",
+        ];
+        let tiktoken = [
+            "program",
+            "models",
+            "add",
+            "gpt-4o",
+            "--tiktoken",
+            "o200k_base",
+        ];
+        let path_and_tiktoken = [
+            "program",
+            "models",
+            "add",
+            "gpt-4o",
+            "--path",
+            "tokenizer.json",
+            "--tiktoken",
+            "o200k_base",
+        ];
+
+        // act
+        let outcome = [
+            neither.as_slice(),
+            &both,
+            &path,
+            &tiktoken,
+            &path_and_tiktoken,
+        ]
+        .map(|args| Cli::try_parse_from(args).is_ok());
+
+        // assert
+        assert_eq!(outcome, [false, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_tokenizer_conflicts_with_model() {
+        // arrange
+        let args = vec!["program", "--tokenizer", "tokenizer.json", "-m", "qwen3:8b"];
+
+        // act
+        let outcome = Cli::try_parse_from(args);
+
+        // assert
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn test_chat_flags() {
+        // arrange
+        let args = [
+            "program",
+            "--chat",
+            "messages.json",
+            "--add-generation-prompt",
+            "--per-message",
+        ];
+
+        // act
+        let cli = parse_args(args);
+        let without_chat = Cli::try_parse_from(["program", "--per-message"]).is_err();
+        let with_prompt =
+            Cli::try_parse_from(["program", "--chat", "messages.json", "Why?"]).is_err();
+
+        let with_no_special_tokens =
+            Cli::try_parse_from(["program", "--chat", "messages.json", "--no-special-tokens"])
+                .is_err();
+
+        // assert
+        assert_eq!(cli.chat, Some(PathBuf::from("messages.json")));
+        assert!(cli.add_generation_prompt && cli.per_message);
+        assert!(without_chat);
+        assert!(with_prompt);
+        assert!(with_no_special_tokens);
+    }
+
+    #[test]
+    fn test_serve_subcommand() {
+        // arrange
+        let args = [
+            "program",
+            "serve",
+            "--port",
+            "9090",
+            "--preload",
+            "qwen3:8b",
+        ];
+        let zero_tokenizers = ["program", "serve", "--max-tokenizers", "0"];
+
+        // act
+        let cli = parse_args(args);
+        let outcome = Cli::try_parse_from(zero_tokenizers).is_err();
+
+        // assert
+        let Some(Command::Serve {
+            port,
+            host,
+            max_tokenizers,
+            preload,
+        }) = cli.command
+        else {
+            panic!("expected `serve` subcommand");
+        };
+        assert_eq!(port, 9090);
+        assert_eq!(host, "127.0.0.1");
+        assert_eq!(max_tokenizers, 8);
+        assert_eq!(preload, ["qwen3:8b"]);
+        assert!(outcome);
+    }
+
+    #[test]
+    fn test_mcp_subcommand() {
+        // arrange
+        let args = ["program", "mcp", "--max-tokenizers", "2"];
+
+        // act
+        let cli = parse_args(args);
+        let outcome = Cli::try_parse_from(["program", "mcp", "--max-tokenizers", "0"]).is_err();
+
+        // assert
+        let Some(Command::Mcp { max_tokenizers }) = cli.command else {
+            panic!("expected `mcp` subcommand");
+        };
+        assert_eq!(max_tokenizers, 2);
+        assert!(outcome);
+    }
+
+    #[test]
+    fn test_lsp_subcommand() {
+        // arrange
+        let args = ["program", "lsp", "-m", "qwen3:8b", "--max-percent", "75%"];
+
+        // act
+        let cli = parse_args(args);
+        let outcome = Cli::try_parse_from(["program", "lsp", "--max-percent", "0"]).is_err();
+
+        // assert
+        let Some(Command::Lsp {
+            model,
+            max_tokens,
+            max_percent,
+        }) = cli.command
+        else {
+            panic!("expected `lsp` subcommand");
+        };
+        assert_eq!(model.as_deref(), Some("qwen3:8b"));
+        assert_eq!(max_tokens, None);
+        assert_eq!(max_percent, Some(75.0));
+        assert!(outcome);
+    }
+
+    #[test]
+    fn test_cache_clear_needs_models_or_a_flag() {
+        // arrange
+        let args = ["program", "cache", "clear"];
+
+        // act
+        let outcome = Cli::try_parse_from(args).is_err();
+        let cli = parse_args([
+            "program",
+            "cache",
+            "clear",
+            "--stale",
+            "--cache-dir",
+            "/tmp/hf",
+        ]);
+
+        // assert
+        assert!(outcome);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Cache {
+                command: CacheCommand::Clear {
+                    all: false,
+                    stale: true,
+                    ..
+                }
+            })
+        ));
+        assert_eq!(cli.cache_dir, Some(PathBuf::from("/tmp/hf")));
+    }
 }
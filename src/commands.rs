@@ -0,0 +1,328 @@
+use std::path::PathBuf;
+
+use miette::{bail, miette};
+use tokenator::{
+    errors::UnknownModelError,
+    models::model_name_suggestion,
+    registry::{
+        ModelEntry, ModelSources, Problem, RegisteredModel, Registry, add_model, check_entries,
+        project_models_path, remove_model, user_models_path,
+    },
+    tiktoken::is_built_in,
+    token::{
+        CONFIG_FILENAME, DEFAULT_REVISION, HubOptions, TokenizerSource, check_tokeniser_available,
+        fetch_repo_tokenizer, get_repo_file,
+    },
+};
+
+use crate::{
+    cache::{Removed, cached_tokenizer, prune_stale_snapshots, remove_repo},
+    cli::{CacheCommand, ModelsCommand},
+    output::{
+        CacheEntry, OutputFormat, format_number, render_cache_list, render_model_details,
+        render_model_list, render_problems,
+    },
+};
+
+/// Returns the models file `models add` and `models remove` change: the `--models-file` file if
+/// given, otherwise the project models file in the working directory with `project`, or the user
+/// models file.
+fn target_models_file(models_file: Option<&PathBuf>, project: bool) -> miette::Result<PathBuf> {
+    if let Some(models_file) = models_file {
+        return Ok(models_file.clone());
+    }
+    if project {
+        let working_directory = std::env::current_dir()
+            .map_err(|error| miette!("Unable to find the working directory: {error}"))?;
+
+        return Ok(project_models_path(&working_directory));
+    }
+
+    user_models_path().ok_or_else(|| {
+        miette!(
+            help = "Set `XDG_CONFIG_HOME` or `HOME`, or pass `--models-file`",
+            "Unable to find the user configuration directory"
+        )
+    })
+}
+
+/// Finds the model named `name` in `models`, the deduplicated models from `registry`.
+///
+/// # Errors
+/// Errors if there is no model with the name, suggesting the closest match.
+fn find_registered_model<'a, 'b>(
+    registry: &Registry,
+    models: &'b [RegisteredModel<'a>],
+    name: &str,
+) -> miette::Result<&'b RegisteredModel<'a>> {
+    models
+        .iter()
+        .find(|model| model.entry.name == name)
+        .ok_or_else(|| {
+            let model_name_map = registry.model_name_map();
+            UnknownModelError::new(name, model_name_suggestion(&model_name_map, name)).into()
+        })
+}
+
+/// Returns the models named on the command line, or every model in the registry when none are
+/// named, along with the Hugging Face repo and revision for each.  Models named on the command
+/// line must use a Hugging Face repo, while local tokenizers are skipped when selecting every
+/// model, since they are never cached.
+///
+/// # Errors
+/// Errors if a named model is not in the registry or uses a local tokenizer.
+fn select_hub_models<'a, 'b>(
+    registry: &Registry,
+    models: &'b [RegisteredModel<'a>],
+    names: &[String],
+) -> miette::Result<Vec<(&'b RegisteredModel<'a>, String, &'b str)>> {
+    let selected: Vec<&RegisteredModel> = if names.is_empty() {
+        models.iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| find_registered_model(registry, models, name))
+            .collect::<miette::Result<_>>()?
+    };
+
+    let mut hub_models = Vec::with_capacity(selected.len());
+    for model in selected {
+        match model.entry.tokenizer_source()? {
+            TokenizerSource::Hub(repo_id) => {
+                let revision = model.entry.revision.as_deref().unwrap_or(DEFAULT_REVISION);
+                hub_models.push((model, repo_id, revision));
+            }
+            TokenizerSource::Path(_) | TokenizerSource::Tiktoken(_) if names.is_empty() => {}
+            TokenizerSource::Path(path) => bail!(
+                "`{}` uses the local tokenizer `{}`, which is not cached",
+                model.entry.name,
+                path.display()
+            ),
+            TokenizerSource::Tiktoken(encoding) => bail!(
+                "`{}` uses the tiktoken encoding `{encoding}`, which is not cached",
+                model.entry.name
+            ),
+        }
+    }
+
+    Ok(hub_models)
+}
+
+/// Runs a `cache` subcommand.
+///
+/// # Errors
+/// Errors if unable to load the registry, or to download or remove cached files.
+pub fn run_cache_command(
+    command: &CacheCommand,
+    models_file: Option<&PathBuf>,
+    format: OutputFormat,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let cache = hub.cache();
+    if let CacheCommand::Path = command {
+        println!("{}", cache.path().display());
+        return Ok(());
+    }
+    let registry = Registry::load(&ModelSources::new(models_file.cloned()))?;
+    let models = registry.models();
+
+    match command {
+        CacheCommand::List => {
+            let entries: Vec<CacheEntry> = select_hub_models(&registry, &models, &[])?
+                .into_iter()
+                .map(|(model, repo_id, revision)| CacheEntry {
+                    cached: cached_tokenizer(&cache, &repo_id, revision),
+                    name: &model.entry.name,
+                    repo_id,
+                    revision,
+                })
+                .collect();
+            print!("{}", render_cache_list(&entries, cache.path(), format));
+        }
+        CacheCommand::Prefetch { models: names } => {
+            if hub.offline {
+                bail!(
+                    help = "Unset `--offline`, `TOKENATOR_OFFLINE` and `HF_HUB_OFFLINE`",
+                    "Unable to prefetch tokenizers while running offline"
+                );
+            }
+            let selected = select_hub_models(&registry, &models, names)?;
+            let mut failed = 0;
+            for (model, repo_id, revision) in &selected {
+                match fetch_repo_tokenizer(repo_id, revision, hub) {
+                    Ok(_) => {
+                        // The config file only adds the context length, so is not required
+                        if let Err(error) = get_repo_file(repo_id, revision, CONFIG_FILENAME, hub) {
+                            log::info!("No config file for `{}`: {error}", model.entry.name);
+                        }
+                        eprintln!("Cached `{}` ({repo_id} at {revision})", model.entry.name);
+                    }
+                    Err(error) => {
+                        failed += 1;
+                        eprintln!("Unable to prefetch `{}`: {error}", model.entry.name);
+                    }
+                }
+            }
+            if failed > 0 {
+                bail!("Unable to prefetch {failed} of {} models", selected.len());
+            }
+        }
+        CacheCommand::Clear {
+            models: names,
+            stale,
+            ..
+        } => {
+            let mut removed = Removed::default();
+            let mut repo_ids: Vec<String> = select_hub_models(&registry, &models, names)?
+                .into_iter()
+                .map(|(_, repo_id, _)| repo_id)
+                .collect();
+            // Several models may share a tokenizer repo
+            repo_ids.sort_unstable();
+            repo_ids.dedup();
+            for repo_id in &repo_ids {
+                let repo_removed = if *stale {
+                    prune_stale_snapshots(&cache, repo_id)?
+                } else {
+                    remove_repo(&cache, repo_id)?
+                };
+                removed.count += repo_removed.count;
+                removed.bytes += repo_removed.bytes;
+            }
+            let what = if *stale {
+                "stale snapshots"
+            } else {
+                "cached tokenizer repos"
+            };
+            eprintln!(
+                "Removed {} {what}, freeing {} bytes",
+                removed.count,
+                format_number(usize::try_from(removed.bytes).unwrap_or(usize::MAX))
+            );
+        }
+        CacheCommand::Path => unreachable!("handled before loading the registry"),
+    }
+
+    Ok(())
+}
+
+/// Checks each model in the registry, printing any problems found.
+///
+/// # Errors
+/// Errors if any problems are found.
+fn validate(
+    registry: &Registry,
+    local: bool,
+    format: OutputFormat,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let mut problems = check_entries(registry);
+    for model in registry.models() {
+        // Local tokenizer files are cheap to check, so are checked even with `--local`
+        let source = model.entry.tokenizer_source()?;
+        if local && matches!(source, TokenizerSource::Hub(_)) {
+            continue;
+        }
+        log::info!("Checking `{}` tokenizer `{source}`", model.entry.name);
+        if let Err(error) = check_tokeniser_available(
+            &source,
+            model.entry.revision.as_deref().unwrap_or(DEFAULT_REVISION),
+            hub,
+        ) {
+            problems.push(Problem {
+                name: model.entry.name.clone(),
+                source: model.file.label(),
+                message: format!("Unable to find a tokenizer: {error}"),
+            });
+        }
+    }
+    let checked = registry.files.iter().map(|file| file.models.len()).sum();
+    print!("{}", render_problems(checked, &problems, format));
+
+    match problems.len() {
+        0 => Ok(()),
+        1 => bail!("Found a problem in the models registry"),
+        count => bail!("Found {count} problems in the models registry"),
+    }
+}
+
+/// Runs a `models` subcommand.
+///
+/// # Errors
+/// Errors if unable to load or change the registry, or the subcommand fails.
+pub fn run_models_command(
+    command: &ModelsCommand,
+    models_file: Option<&PathBuf>,
+    format: OutputFormat,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let sources = ModelSources::new(models_file.cloned());
+
+    match command {
+        ModelsCommand::List => {
+            let registry = Registry::load(&sources)?;
+            print!("{}", render_model_list(&registry.models(), format));
+        }
+        ModelsCommand::Add {
+            name,
+            hf_repo,
+            path: tokenizer_path,
+            tiktoken,
+            revision,
+            sha256,
+            context_length,
+            max_output_tokens,
+            project,
+        } => {
+            let path = target_models_file(models_file, *project)?;
+            // Store local paths as absolute paths, since relative paths in a models file are
+            // resolved against the models file directory, rather than the working directory
+            let tokenizer_path = tokenizer_path
+                .as_deref()
+                .map(std::path::absolute)
+                .transpose()
+                .map_err(|error| miette!("Unable to resolve the tokenizer path: {error}"))?;
+            let tiktoken = tiktoken
+                .as_deref()
+                .map(|encoding| {
+                    if is_built_in(encoding) {
+                        Ok(encoding.to_owned())
+                    } else {
+                        std::path::absolute(encoding).map(|path| path.display().to_string())
+                    }
+                })
+                .transpose()
+                .map_err(|error| miette!("Unable to resolve the rank file path: {error}"))?;
+            let model = ModelEntry {
+                name: name.clone(),
+                hf: hf_repo.clone(),
+                path: tokenizer_path,
+                tiktoken,
+                revision: revision.clone(),
+                sha256: sha256.clone(),
+                context_length: *context_length,
+                max_output_tokens: *max_output_tokens,
+            };
+            let replaced = add_model(&path, model)?;
+            let action = if replaced { "Updated" } else { "Added" };
+            eprintln!("{action} `{name}` in `{}`", path.display());
+        }
+        ModelsCommand::Remove { name, project } => {
+            let path = target_models_file(models_file, *project)?;
+            remove_model(&path, name)?;
+            eprintln!("Removed `{name}` from `{}`", path.display());
+        }
+        ModelsCommand::Show { name } => {
+            let registry = Registry::load(&sources)?;
+            let models = registry.models();
+            let model = find_registered_model(&registry, &models, name)?;
+            print!("{}", render_model_details(model, format));
+        }
+        ModelsCommand::Validate { local } => {
+            let registry = Registry::load(&sources)?;
+            validate(&registry, *local, format, hub)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    errors::AppError,
+    models::ResolvedModel,
+    registry::Registry,
+    token::{
+        HubOptions, TokenCount, TokenInfo, TokenizerBackend, create_tokeniser, fetch_context_length,
+    },
+};
+
+/// A model tokenizer, loaded once, for counting any number of prompts.  Loading downloads the
+/// tokenizer files, unless already cached, and parses them, so keep the counter around, rather
+/// than loading it for each prompt.
+///
+/// # Examples
+/// ```
+/// use tokenator::{HubOptions, ModelSources, Registry, TokenCounter};
+///
+/// let registry = Registry::load(&ModelSources::new(None))?;
+/// let counter = TokenCounter::for_model(&registry, "gpt-4o", &HubOptions::default())?;
+///
+/// assert_eq!(counter.count("Why is the sky blue?")?.tokens, 6);
+/// # Ok::<(), tokenator::AppError>(())
+/// ```
+pub struct TokenCounter {
+    model: ResolvedModel,
+    tokeniser: Box<dyn TokenizerBackend>,
+}
+
+impl TokenCounter {
+    /// Loads the tokenizer for `model`, checking it against any pinned hash.
+    ///
+    /// # Errors
+    /// Errors if unable to download or load the tokenizer, or it does not match the pinned hash.
+    pub fn load(model: ResolvedModel, hub: &HubOptions) -> Result<Self, AppError> {
+        let tokeniser = create_tokeniser(&model.tokenizer, &model.pin, hub)?;
+
+        Ok(Self { model, tokeniser })
+    }
+
+    /// Loads the tokenizer for the model called `name` in `registry`.
+    ///
+    /// # Errors
+    /// Errors if there is no model called `name`, or unable to load its tokenizer.
+    pub fn for_model(registry: &Registry, name: &str, hub: &HubOptions) -> Result<Self, AppError> {
+        Self::load(registry.resolve(name)?, hub)
+    }
+
+    /// Returns the model the counter was loaded for.
+    #[must_use]
+    pub fn model(&self) -> &ResolvedModel {
+        &self.model
+    }
+
+    /// Returns the loaded tokenizer, for use with functions taking any backend, such as
+    /// [`crate::chat::count_chat`].
+    #[must_use]
+    pub fn tokeniser(&self) -> &dyn TokenizerBackend {
+        self.tokeniser.as_ref()
+    }
+
+    /// Counts the tokens in `text`, including any special tokens the tokenizer wraps prompts in,
+    /// as the model sees it.
+    ///
+    /// # Errors
+    /// Errors if the tokenizer fails to encode the text.
+    pub fn count(&self, text: &str) -> Result<TokenCount, AppError> {
+        self.tokeniser.count(text, true)
+    }
+
+    /// Counts the tokens in `text`, leaving out special tokens the tokenizer would add, for text
+    /// joined into a larger prompt.
+    ///
+    /// # Errors
+    /// Errors if the tokenizer fails to encode the text.
+    pub fn count_without_special_tokens(&self, text: &str) -> Result<TokenCount, AppError> {
+        self.tokeniser.count(text, false)
+    }
+
+    /// Splits `text` into tokens, with the prompt span each covers.
+    ///
+    /// # Errors
+    /// Errors if the tokenizer fails to encode the text.
+    pub fn tokenize(
+        &self,
+        text: &str,
+        add_special_tokens: bool,
+    ) -> Result<Vec<TokenInfo>, AppError> {
+        self.tokeniser.tokenize(text, add_special_tokens)
+    }
+}
+
+/// Counters for models in a registry, loaded on first use and kept loaded for later calls.  Only
+/// the `capacity` most recently used counters stay loaded, so long-running servers do not hold
+/// every tokenizer they were ever asked for.
+pub struct CounterCache {
+    registry: Registry,
+    hub: HubOptions,
+    capacity: NonZeroUsize,
+
+    /// Loaded counters, least recently used first.
+    counters: Mutex<Vec<Arc<TokenCounter>>>,
+}
+
+impl CounterCache {
+    #[must_use]
+    pub fn new(registry: Registry, hub: HubOptions, capacity: NonZeroUsize) -> Self {
+        Self {
+            registry,
+            hub,
+            capacity,
+            counters: Mutex::new(Vec::with_capacity(capacity.get())),
+        }
+    }
+
+    /// Returns the registry counters are loaded from.
+    #[must_use]
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    fn counters(&self) -> MutexGuard<'_, Vec<Arc<TokenCounter>>> {
+        self.counters
+            .lock()
+            .expect("counter cache lock should not be poisoned")
+    }
+
+    /// Returns the counter for the model called `name`, loading it, and reading the model context
+    /// length when the registry does not list one, unless already loaded.  The lock is not held
+    /// while loading, so calls for loaded models are not held up by a slow download.
+    ///
+    /// # Errors
+    /// Errors if there is no model called `name`, or unable to load its tokenizer.
+    pub fn get(&self, name: &str) -> Result<Arc<TokenCounter>, AppError> {
+        {
+            let mut counters = self.counters();
+            if let Some(index) = counters
+                .iter()
+                .position(|counter| counter.model().name == name)
+            {
+                let counter = counters.remove(index);
+                counters.push(Arc::clone(&counter));
+                return Ok(counter);
+            }
+        }
+
+        let mut model = self.registry.resolve(name)?;
+        if model.context_length.is_none() {
+            match fetch_context_length(&model.tokenizer, model.pin.revision(), &self.hub) {
+                Ok(context_length) => model.context_length = context_length,
+                Err(error) => log::warn!(
+                    "Unable to read the context length for `{}`: {error}",
+                    model.name
+                ),
+            }
+        }
+        log::info!("Loading the `{name}` tokenizer");
+        let counter = Arc::new(TokenCounter::load(model, &self.hub)?);
+
+        let mut counters = self.counters();
+        counters.retain(|loaded| loaded.model().name != name);
+        if counters.len() == self.capacity.get() {
+            let unloaded = counters.remove(0);
+            log::info!("Unloading the `{}` tokenizer", unloaded.model().name);
+        }
+        counters.push(Arc::clone(&counter));
+
+        Ok(counter)
+    }
+
+    /// Returns the names of the models with loaded counters, least recently used first.
+    #[must_use]
+    pub fn loaded(&self) -> Vec<String> {
+        self.counters()
+            .iter()
+            .map(|counter| counter.model().name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
+
+    use crate::{
+        counter::{CounterCache, TokenCounter},
+        errors::AppError,
+        models::ResolvedModel,
+        registry::{ModelSources, Registry},
+        token::{HubOptions, TokenCount, TokenizerPin, TokenizerSource},
+    };
+
+    #[test]
+    fn token_counter_counts_prompts_with_a_loaded_tokenizer() {
+        // arrange
+        let model = ResolvedModel {
+            name: "local".to_owned(),
+            tokenizer: TokenizerSource::Path(PathBuf::from("tests/fixtures/tokenizer.json")),
+            pin: TokenizerPin::default(),
+            context_length: None,
+            max_output_tokens: None,
+        };
+        let counter = TokenCounter::load(model, &HubOptions::default()).unwrap();
+
+        // act
+        let with_special = counter.count("Why is the sky blue?").unwrap();
+        let without_special = counter
+            .count_without_special_tokens("Why is the sky blue?")
+            .unwrap();
+
+        // assert
+        assert_eq!(
+            with_special.tokens,
+            without_special.tokens + with_special.special
+        );
+        assert_eq!(without_special.special, 0);
+        assert_eq!(counter.model().name, "local");
+    }
+
+    #[test]
+    fn token_counter_loads_registry_models_by_name() {
+        // arrange
+        let registry = Registry::load(&ModelSources {
+            built_in: true,
+            ..ModelSources::default()
+        })
+        .unwrap();
+
+        // act
+        let counter = TokenCounter::for_model(&registry, "gpt-4o", &HubOptions::default()).unwrap();
+        let outcome = TokenCounter::for_model(&registry, "gpt-4oo", &HubOptions::default())
+            .map(drop)
+            .unwrap_err();
+
+        // assert
+        assert_eq!(
+            counter.count("Why is the sky blue?").unwrap(),
+            TokenCount {
+                tokens: 6,
+                special: 0
+            }
+        );
+        assert!(matches!(
+            outcome,
+            AppError::UnknownModel(ref error) if error.suggestion.as_deref() == Some("gpt-4o")
+        ));
+    }
+
+    #[test]
+    fn counter_cache_unloads_the_least_recently_used_counter() {
+        // arrange
+        let registry = Registry::load(&ModelSources {
+            built_in: true,
+            ..ModelSources::default()
+        })
+        .unwrap();
+        let cache = CounterCache::new(
+            registry,
+            HubOptions::default(),
+            NonZeroUsize::new(1).unwrap(),
+        );
+        let first = cache.get("gpt-4o").unwrap();
+
+        // act
+        let hit = cache.get("gpt-4o").unwrap();
+        cache.get("gpt-4").unwrap();
+        let reloaded = cache.get("gpt-4o").unwrap();
+        let unknown = cache.get("gpt-5o").map(drop).unwrap_err();
+
+        // assert
+        assert!(Arc::ptr_eq(&first, &hit));
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert_eq!(cache.loaded(), ["gpt-4o"]);
+        assert!(matches!(unknown, AppError::UnknownModel(_)));
+    }
+}
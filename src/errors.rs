@@ -1,3 +1,18 @@
+/// Exit status for errors without a more specific status, including I/O errors, such as a missing
+/// prompt file.  `clap` uses `2` for invalid command-line arguments.
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Exit status when the Hugging Face API request for a tokenizer fails, for example, with no
+/// network connection, or, offline, when the tokenizer is not cached.
+pub const EXIT_HF_API: u8 = 3;
+
+/// Exit status when a tokenizer cannot be loaded, does not match its pinned hash, or fails to
+/// encode a prompt.
+pub const EXIT_TOKENIZER: u8 = 4;
+
+/// Exit status when a prompt is over the `--max-tokens` or `--max-percent` budget.
+pub const EXIT_BUDGET_EXCEEDED: u8 = 5;
+
 #[derive(Debug, miette::Diagnostic, thiserror::Error)]
 #[error("{detail}")]
 pub struct HfApiError {
@@ -18,7 +33,8 @@ impl From<hf_hub::api::sync::ApiError> for HfApiError {
             hf_hub::api::sync::ApiError::RequestError(ref err) => match **err {
                 ureq::Error::StatusCode(404) => Self {
                     advice: "Check the repo listed in the `models.json` file is correct, the repo \
-                        is for a model and that the repo has a `tokenizer.json` file in the root \
+                        is for a model and that the repo has a `tokenizer.json`, \
+                        `tokenizer.model` or `vocab.json` and `merges.txt` files in the root \
                         directory."
                         .to_owned(),
                     detail: format!("{value:?}"),
@@ -39,6 +55,48 @@ impl From<hf_hub::api::sync::ApiError> for HfApiError {
     }
 }
 
+impl HfApiError {
+    /// Returns `true` when the requested repo or file does not exist.
+    #[must_use]
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            &self.cause,
+            hf_hub::api::sync::ApiError::RequestError(err)
+                if matches!(**err, ureq::Error::StatusCode(404))
+        )
+    }
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+#[diagnostic(code(tokenator::not_cached))]
+pub struct NotCachedError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+}
+
+impl NotCachedError {
+    /// Error for a repo file which is missing from the `hf_hub` cache at `cache_path` when running
+    /// offline.
+    #[must_use]
+    pub fn new(repo_id: &str, filename: &str, cache_path: &std::path::Path) -> Self {
+        Self {
+            advice: "Run tokenator once with a network connection to download the file, or \
+                unset `--offline`, `TOKENATOR_OFFLINE` and `HF_HUB_OFFLINE`"
+                .to_owned(),
+            detail: format!(
+                "Running offline and `{filename}` for `{repo_id}` was never downloaded to the \
+                Hugging Face cache at `{}`",
+                cache_path.display()
+            ),
+        }
+    }
+}
+
 #[derive(Debug, miette::Diagnostic, thiserror::Error)]
 #[error("{detail}")]
 pub struct TokenizerError {
@@ -64,6 +122,194 @@ impl From<tokenizers::tokenizer::Error> for TokenizerError {
     }
 }
 
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+pub struct TokenizerFileError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+}
+
+impl TokenizerFileError {
+    /// Error for a local tokenizer file which does not exist.
+    #[must_use]
+    pub fn missing(path: &std::path::Path) -> Self {
+        Self {
+            advice: "Check the path points to a `tokenizer.json` or `tokenizer.model` file, or \
+                a directory holding one, or holding `vocab.json` and `merges.txt` files"
+                .to_owned(),
+            detail: format!("No tokenizer file at `{}`", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+#[diagnostic(code(tokenator::hash_mismatch))]
+pub struct HashMismatchError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+}
+
+impl HashMismatchError {
+    /// Error for a tokenizer file with a different SHA-256 hash to the one pinned in the models
+    /// file.
+    #[must_use]
+    pub fn new(source: &crate::token::TokenizerSource, expected: &str, actual: &str) -> Self {
+        Self {
+            advice: "The tokenizer has changed since the hash was recorded.  Pin the model to the \
+                revision the hash was recorded for, or update the `sha256` in the models file"
+                .to_owned(),
+            detail: format!(
+                "Tokenizer for `{source}` has SHA-256 hash `{actual}`, but the models file \
+                expects `{expected}`"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+#[diagnostic(code(tokenator::budget_exceeded))]
+pub struct BudgetError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+#[diagnostic(code(tokenator::registry))]
+pub struct RegistryError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+}
+
+impl RegistryError {
+    /// Error for a models file which cannot be read, parsed or written.
+    #[must_use]
+    pub fn models_file(detail: String) -> Self {
+        Self {
+            advice: "Check the models file is a JSON array of models, each with a `name` and an \
+                `hf` repo, a local tokenizer `path` or a `tiktoken` encoding, like \
+                `data/models.json`"
+                .to_owned(),
+            detail,
+        }
+    }
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+#[diagnostic(code(tokenator::unknown_model))]
+pub struct UnknownModelError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+
+    /// Closest model name in the registry, if any.
+    #[allow(unused_assignments)]
+    pub suggestion: Option<String>,
+}
+
+impl UnknownModelError {
+    /// Error for a model name which is not in the registry, with the closest name, if any.
+    pub fn new(name: &str, suggestion: Option<&str>) -> Self {
+        Self {
+            advice: "Run `tokenator models list` to see the models available".to_owned(),
+            detail: match suggestion {
+                Some(suggestion) => {
+                    format!("No model matching `{name}`, did you mean `{suggestion}`?")
+                }
+                None => format!("No model matching `{name}`."),
+            },
+            suggestion: suggestion.map(str::to_owned),
+        }
+    }
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+#[diagnostic(code(tokenator::chat))]
+pub struct ChatError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+}
+
+impl ChatError {
+    /// Error for a messages file, or messages, which are not a valid conversation.
+    #[must_use]
+    pub fn messages(detail: String) -> Self {
+        Self {
+            advice:
+                "Messages should be a JSON array of objects with a `role` and `content`, as in \
+                the OpenAI chat format, or a chat request body with a `messages` array"
+                    .to_owned(),
+            detail,
+        }
+    }
+
+    /// Error for a chat template which is missing, invalid or fails to render the conversation.
+    #[must_use]
+    pub fn template(detail: String) -> Self {
+        Self {
+            advice: "Check the model is an instruction-tuned model, with a chat template in its \
+                `tokenizer_config.json` or `chat_template.jinja` file"
+                .to_owned(),
+            detail,
+        }
+    }
+}
+
+#[derive(Debug, miette::Diagnostic, thiserror::Error)]
+#[error("{detail}")]
+pub struct ModelFileError {
+    #[help]
+    #[allow(unused_assignments)]
+    pub advice: String,
+
+    #[allow(unused_assignments)]
+    pub detail: String,
+
+    #[allow(unused_assignments)]
+    pub cause: std::io::Error,
+}
+
+impl ModelFileError {
+    /// Error for a downloaded or local model file, such as `config.json`, which cannot be read.
+    #[must_use]
+    pub fn unreadable(path: &std::path::Path, cause: std::io::Error) -> Self {
+        Self {
+            advice: "Check the file permissions, or clear the cached file and re-run to download \
+                it again"
+                .to_owned(),
+            detail: format!("Error reading `{}`: {cause}", path.display()),
+            cause,
+        }
+    }
+}
+
 #[derive(Debug, miette::Diagnostic, thiserror::Error)]
 pub enum AppError {
     #[diagnostic(transparent)]
@@ -71,8 +317,71 @@ pub enum AppError {
     #[error(transparent)]
     HfApi(#[from] HfApiError),
 
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    NotCached(#[from] NotCachedError),
+
     #[diagnostic(transparent)]
     #[diagnostic_source]
     #[error(transparent)]
     Tokenizer(#[from] TokenizerError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    TokenizerFile(#[from] TokenizerFileError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    HashMismatch(#[from] HashMismatchError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    BudgetExceeded(#[from] BudgetError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    UnknownModel(#[from] UnknownModelError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    Chat(#[from] ChatError),
+
+    #[diagnostic(transparent)]
+    #[diagnostic_source]
+    #[error(transparent)]
+    ModelFile(#[from] ModelFileError),
+}
+
+impl AppError {
+    /// Returns the process exit status for the error.
+    #[must_use]
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::HfApi(_) | Self::NotCached(_) => EXIT_HF_API,
+            Self::Tokenizer(_) | Self::TokenizerFile(_) | Self::HashMismatch(_) => EXIT_TOKENIZER,
+            Self::Registry(_) | Self::UnknownModel(_) | Self::Chat(_) | Self::ModelFile(_) => {
+                EXIT_FAILURE
+            }
+            Self::BudgetExceeded(_) => EXIT_BUDGET_EXCEEDED,
+        }
+    }
+}
+
+/// Returns the process exit status for an error ending the run, falling back to [`EXIT_FAILURE`]
+/// for errors which are not an [`AppError`].
+pub fn exit_code(error: &miette::Report) -> u8 {
+    error
+        .downcast_ref::<AppError>()
+        .map_or(EXIT_FAILURE, AppError::exit_code)
 }
@@ -0,0 +1,1064 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Component, MAIN_SEPARATOR, Path, PathBuf},
+};
+
+use glob::MatchOptions;
+use ignore::{
+    Match, WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::{Override, OverrideBuilder},
+};
+use miette::{Context, IntoDiagnostic, bail};
+use tokenator::{
+    errors::AppError,
+    token::{TokenCount, TokenizerBackend, count_tokens},
+};
+
+use crate::{
+    prompt::is_stdin_path,
+    utility::{read_file, read_stdin},
+};
+
+/// A single input to count tokens for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PromptSource {
+    /// Prompt text passed directly on the command line.
+    Text(String),
+
+    /// Prompt text piped in on standard input.
+    Stdin,
+
+    /// A file, either named explicitly or discovered by expanding a glob pattern or walking a
+    /// directory.
+    File { path: PathBuf, explicit: bool },
+}
+
+impl PromptSource {
+    /// Returns the file path for file sources.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File { path, .. } => Some(path),
+            Self::Text(_) | Self::Stdin => None,
+        }
+    }
+}
+
+/// The kind of input a count was made for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    Text,
+    Stdin,
+    File,
+}
+
+/// Token, character and byte counts for a single input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InputCount {
+    pub kind: SourceKind,
+    pub path: Option<PathBuf>,
+    pub tokens: usize,
+
+    /// Tokens which are special tokens, such as beginning of sequence markers, included in
+    /// `tokens`.
+    pub special_tokens: usize,
+    pub chars: usize,
+    pub bytes: usize,
+}
+
+impl InputCount {
+    /// Creates the count for `text`, read from `source`, which the tokeniser split into `tokens`.
+    pub fn new(source: &PromptSource, text: &str, tokens: TokenCount) -> Self {
+        let kind = match source {
+            PromptSource::Text(_) => SourceKind::Text,
+            PromptSource::Stdin => SourceKind::Stdin,
+            PromptSource::File { .. } => SourceKind::File,
+        };
+
+        Self {
+            kind,
+            path: source.path().map(Path::to_path_buf),
+            tokens: tokens.tokens,
+            special_tokens: tokens.special,
+            chars: text.chars().count(),
+            bytes: text.len(),
+        }
+    }
+
+    /// Returns the number of tokens which are not special tokens.
+    pub fn content_tokens(&self) -> usize {
+        self.tokens - self.special_tokens
+    }
+
+    /// Returns a human-readable label for the input: the file path, `<prompt>` or `<stdin>`.
+    pub fn label(&self) -> String {
+        match (&self.path, self.kind) {
+            (Some(path), _) => path.display().to_string(),
+            (None, SourceKind::Stdin) => "<stdin>".to_owned(),
+            (None, SourceKind::Text | SourceKind::File) => "<prompt>".to_owned(),
+        }
+    }
+}
+
+/// A discovered file which was not counted, and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedInput {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Counts for every input in a run, along with any discovered files which were skipped.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SourceCounts {
+    pub counted: Vec<InputCount>,
+    pub skipped: Vec<SkippedInput>,
+}
+
+impl SourceCounts {
+    /// Returns the token count summed across all counted inputs.
+    pub fn total_tokens(&self) -> usize {
+        self.counted.iter().map(|count| count.tokens).sum()
+    }
+}
+
+/// Name of the tokenator-specific ignore file, which uses the same syntax as `.gitignore`.
+const IGNORE_FILENAME: &str = ".tokenatorignore";
+
+/// Options controlling which files are counted when walking directories or expanding glob
+/// patterns.  As with ripgrep, these filters do not apply to files named explicitly on the
+/// command line.
+#[derive(Debug, Default)]
+pub struct WalkOptions {
+    /// Include hidden files and directories.
+    pub hidden: bool,
+
+    /// Do not respect `.gitignore`, `.ignore` and `.tokenatorignore` files.
+    pub no_ignore: bool,
+
+    /// Glob patterns for files to include.  If any are given, only matching files are counted.
+    pub include: Vec<String>,
+
+    /// Glob patterns for files to exclude.
+    pub exclude: Vec<String>,
+}
+
+/// Returns `true` if the input contains glob pattern metacharacters.
+pub fn is_glob_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// Builds the matcher for the include and exclude patterns, relative to `root`.
+fn build_overrides<P: AsRef<Path>>(root: P, options: &WalkOptions) -> miette::Result<Override> {
+    let mut overrides = OverrideBuilder::new(root);
+    for pattern in &options.include {
+        overrides
+            .add(pattern)
+            .into_diagnostic()
+            .wrap_err(format!("Parsing include pattern `{pattern}`"))?;
+    }
+    for pattern in &options.exclude {
+        overrides
+            .add(&format!("!{pattern}"))
+            .into_diagnostic()
+            .wrap_err(format!("Parsing exclude pattern `{pattern}`"))?;
+    }
+
+    overrides
+        .build()
+        .into_diagnostic()
+        .wrap_err("Building include and exclude patterns")
+}
+
+/// Walks a directory, returning every file under it in lexicographic order.  Files excluded by
+/// ignore files, hidden files and files filtered out by include and exclude patterns are skipped,
+/// as set in `options`, as are entries which cannot be read.
+fn walk_directory<P: AsRef<Path>>(path: P, options: &WalkOptions) -> miette::Result<Vec<PathBuf>> {
+    let mut walk_builder = WalkBuilder::new(&path);
+    walk_builder
+        .standard_filters(!options.no_ignore)
+        .hidden(!options.hidden)
+        .overrides(build_overrides(&path, options)?)
+        .sort_by_file_path(Path::cmp);
+    if !options.no_ignore {
+        walk_builder.add_custom_ignore_filename(IGNORE_FILENAME);
+    }
+
+    let mut files = Vec::new();
+    for entry in walk_builder.build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn!("Skipping entry in `{}`: {error}", path.as_ref().display());
+                continue;
+            }
+        };
+        if entry
+            .file_type()
+            .is_some_and(|file_type| !file_type.is_dir())
+        {
+            files.push(entry.into_path());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the matcher for the ignore files in `dir`.  `.gitignore` files only apply inside a git
+/// repo, as when walking directories, and `.tokenatorignore` rules take precedence over `.ignore`
+/// rules, which take precedence over `.gitignore` rules.
+fn ignore_matcher(dir: &Path, in_git_repo: bool) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    let filenames = [".gitignore", ".ignore", IGNORE_FILENAME];
+    for filename in filenames.iter().skip(usize::from(!in_git_repo)) {
+        let path = dir.join(filename);
+        if path.is_file()
+            && let Some(error) = builder.add(&path)
+        {
+            log::warn!("Skipping rules in `{}`: {error}", path.display());
+        }
+    }
+
+    builder.build().unwrap_or_else(|error| {
+        log::warn!("Skipping ignore files in `{}`: {error}", dir.display());
+        Gitignore::empty()
+    })
+}
+
+/// Filters paths matched by a glob pattern in the same way as files found walking a directory,
+/// against the ignore files in the directories above them, and the include and exclude patterns.
+struct GlobFilter<'a> {
+    options: &'a WalkOptions,
+    root: PathBuf,
+    overrides: Override,
+
+    /// Ignore file matchers for each directory, and whether it is inside a git repo, cached as
+    /// matches often share directories.
+    ignores: HashMap<(PathBuf, bool), Gitignore>,
+}
+
+impl<'a> GlobFilter<'a> {
+    fn new(pattern: &str, options: &'a WalkOptions) -> miette::Result<Self> {
+        let root = glob_root(pattern);
+        Ok(Self {
+            options,
+            overrides: build_overrides(&root, options)?,
+            root,
+            ignores: HashMap::new(),
+        })
+    }
+
+    /// Returns `true` if an ignore file in a directory above `path` excludes it.  The closest
+    /// ignore file with a matching rule decides.
+    fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(path) = std::path::absolute(path) else {
+            return false;
+        };
+        let git_root = path
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf);
+        for dir in path.ancestors().skip(1) {
+            let in_git_repo = git_root.as_ref().is_some_and(|root| dir.starts_with(root));
+            let matcher = self
+                .ignores
+                .entry((dir.to_path_buf(), in_git_repo))
+                .or_insert_with(|| ignore_matcher(dir, in_git_repo));
+            match matcher.matched_path_or_any_parents(&path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+
+        false
+    }
+
+    /// Returns `true` if `path` should be counted, or walked, for a directory.
+    fn keep(&mut self, path: &Path, is_dir: bool) -> bool {
+        !self.is_excluded(path, is_dir)
+            && (self.options.no_ignore || !self.is_ignored(path, is_dir))
+    }
+
+    /// Returns `true` if the include and exclude patterns filter out `path`, or a directory between
+    /// it and the pattern root, which a directory walk would not descend into.
+    fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.overrides.matched(path, is_dir).is_ignore()
+            || path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != self.root && dir.starts_with(&self.root))
+                .any(|dir| self.overrides.matched(dir, true).is_ignore())
+    }
+}
+
+/// Expands a single path argument into prompt sources.  Directories are walked recursively, and
+/// glob patterns are expanded against the file system.  Returns an empty vector if the input
+/// matches nothing.
+fn expand_path(input: &str, options: &WalkOptions) -> miette::Result<Vec<PromptSource>> {
+    let path = Path::new(input);
+    if path.is_dir() {
+        return Ok(walk_directory(path, options)?
+            .into_iter()
+            .map(|path| PromptSource::File {
+                path,
+                explicit: false,
+            })
+            .collect());
+    }
+    if path.exists() {
+        return Ok(vec![PromptSource::File {
+            path: path.to_path_buf(),
+            explicit: true,
+        }]);
+    }
+    if !is_glob_pattern(input) {
+        return Ok(Vec::new());
+    }
+
+    Ok(expand_glob(input, options)?
+        .into_iter()
+        .map(|path| PromptSource::File {
+            path,
+            explicit: false,
+        })
+        .collect())
+}
+
+/// Returns the directory a glob pattern matches files under: the path up to the first component
+/// holding a glob metacharacter.
+pub fn glob_root(pattern: &str) -> PathBuf {
+    let root: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|component| !is_glob_pattern(&component.as_os_str().to_string_lossy()))
+        .collect();
+    if root.as_os_str().is_empty() {
+        PathBuf::from(Component::CurDir.as_os_str())
+    } else {
+        root
+    }
+}
+
+/// Returns `true` if the last component of `path` is a `.` or `..` directory entry.
+fn is_dot_entry(path: &Path) -> bool {
+    let path = path.as_os_str().to_string_lossy();
+    let name = path
+        .rsplit(['/', MAIN_SEPARATOR])
+        .next()
+        .unwrap_or_default();
+
+    name == "." || name == ".."
+}
+
+/// Expands a glob pattern, filtering the matches in the same way as files found walking a
+/// directory.  Directories matching the pattern are walked, as when passed on the command line,
+/// and paths which cannot be read are skipped.
+fn expand_glob(pattern: &str, options: &WalkOptions) -> miette::Result<Vec<PathBuf>> {
+    let match_options = MatchOptions {
+        require_literal_leading_dot: !options.hidden,
+        ..MatchOptions::new()
+    };
+    let entries = glob::glob_with(pattern, match_options)
+        .into_diagnostic()
+        .wrap_err(format!("Parsing glob pattern `{pattern}`"))?;
+    let mut filter = GlobFilter::new(pattern, options)?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            Ok(path) => path,
+            Err(error) => {
+                log::warn!("Skipping match for `{pattern}`: {error}");
+                continue;
+            }
+        };
+        // Patterns with a leading dot also match the current and parent directory entries
+        if is_dot_entry(&path) {
+            continue;
+        }
+        let is_dir = path.is_dir();
+        if !filter.keep(&path, is_dir) {
+            log::debug!("Skipping ignored match `{}`", path.display());
+        } else if is_dir {
+            files.extend(walk_directory(&path, options)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns `true` for a lone input which reads as prompt text, rather than a path or glob
+/// pattern: a question, ending in `?`, several words without a path separator, or text which is
+/// not a valid glob pattern.
+fn is_prompt_text(input: &str) -> bool {
+    input.trim_end().ends_with('?')
+        || (input.trim().contains(char::is_whitespace) && !input.contains(MAIN_SEPARATOR))
+        || (is_glob_pattern(input) && glob::Pattern::new(input).is_err())
+}
+
+/// Resolves the `--file` arguments and positional inputs into a list of prompt sources.  A lone
+/// positional input which is neither an existing path nor a glob pattern matching some file is
+/// treated as prompt text, so `tokenator "Why is the sky blue?"` still works.  Duplicate files
+/// are only counted once, and `options` filters the files found by walking directories.
+///
+/// # Returns
+/// A `miette::Result` containing the prompt sources, which is empty if no inputs were given.
+///
+/// # Errors
+/// Errors if a file or pattern matches no files, or if a directory cannot be walked.
+pub fn resolve_inputs<P: AsRef<Path>>(
+    files: &[P],
+    inputs: &[String],
+    options: &WalkOptions,
+) -> miette::Result<Vec<PromptSource>> {
+    let lone_input = files.is_empty() && inputs.len() == 1;
+    let mut sources: Vec<PromptSource> = Vec::new();
+    let arguments = files
+        .iter()
+        .map(|file| file.as_ref().to_string_lossy().into_owned())
+        .chain(inputs.iter().cloned());
+    for argument in arguments {
+        if is_stdin_path(&argument) {
+            if !sources.contains(&PromptSource::Stdin) {
+                sources.push(PromptSource::Stdin);
+            }
+            continue;
+        }
+        // Never look for files matching a lone question or sentence, which could be slow, and
+        // match files by chance
+        if lone_input && !Path::new(&argument).exists() && is_prompt_text(&argument) {
+            return Ok(vec![PromptSource::Text(argument)]);
+        }
+        let expanded = expand_path(&argument, options)?;
+        if expanded.is_empty() {
+            if lone_input {
+                return Ok(vec![PromptSource::Text(argument)]);
+            }
+            bail!("No files matching `{argument}`");
+        }
+        for source in expanded {
+            let duplicate = sources
+                .iter()
+                .any(|existing| existing.path().is_some() && existing.path() == source.path());
+            if !duplicate {
+                sources.push(source);
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Text read from a single prompt source.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LoadedInput<'a> {
+    pub source: &'a PromptSource,
+    pub text: String,
+}
+
+/// Text for every input in a run, along with any discovered files which were skipped.  Inputs
+/// are read once, so they can be counted with several tokenisers.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LoadedSources<'a> {
+    pub inputs: Vec<LoadedInput<'a>>,
+    pub skipped: Vec<SkippedInput>,
+}
+
+/// Reads the text for each prompt source.  Discovered files which cannot be read as UTF-8 text,
+/// or are empty, are skipped with a warning, while errors reading explicitly named files are
+/// returned.
+///
+/// # Returns
+/// A `miette::Result` containing the text for each input, in input order, and skipped files.
+///
+/// # Errors
+/// Errors if an explicitly named file or standard input cannot be read.
+pub fn read_sources<R: Read>(
+    sources: &[PromptSource],
+    stdin: R,
+) -> miette::Result<LoadedSources<'_>> {
+    let mut stdin = Some(stdin);
+    let mut loaded = LoadedSources::default();
+    for source in sources {
+        let text = match source {
+            PromptSource::Text(text) => text.to_owned(),
+            PromptSource::Stdin => read_stdin(
+                stdin
+                    .take()
+                    .expect("standard input should only appear once in sources"),
+            )?,
+            PromptSource::File { path, explicit } => match read_file(path) {
+                Ok(text) if text.is_empty() && !explicit => {
+                    loaded.skipped.push(SkippedInput {
+                        path: path.to_owned(),
+                        reason: "File is empty".to_owned(),
+                    });
+                    continue;
+                }
+                Ok(text) => text,
+                Err(error) if !explicit => {
+                    log::warn!("Skipping `{}`: {error}", path.display());
+                    loaded.skipped.push(SkippedInput {
+                        path: path.to_owned(),
+                        reason: error.to_string(),
+                    });
+                    continue;
+                }
+                Err(error) => return Err(error),
+            },
+        };
+        loaded.inputs.push(LoadedInput { source, text });
+    }
+
+    Ok(loaded)
+}
+
+/// Counts tokens for each loaded input, reusing the same tokeniser for every input.  With
+/// `add_special_tokens`, each input count includes any special tokens the tokeniser wraps prompts
+/// in, so leave them out when the inputs are fragments of a single prompt.
+///
+/// # Returns
+/// A `Result` containing the per-input counts, in input order, and skipped files.
+///
+/// # Errors
+/// Errors if a prompt cannot be encoded.
+pub fn count_sources(
+    tokeniser: &dyn TokenizerBackend,
+    loaded: &LoadedSources,
+    add_special_tokens: bool,
+) -> Result<SourceCounts, AppError> {
+    let counted = loaded
+        .inputs
+        .iter()
+        .map(|input| {
+            let tokens = count_tokens(tokeniser, &input.text, add_special_tokens)?;
+            Ok(InputCount::new(input.source, &input.text, tokens))
+        })
+        .collect::<Result<_, AppError>>()?;
+
+    Ok(SourceCounts {
+        counted,
+        skipped: loaded.skipped.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteBin, FileWriteStr, PathChild},
+    };
+    use tokenizers::Tokenizer;
+
+    use crate::files::{
+        InputCount, LoadedInput, LoadedSources, PromptSource, SkippedInput, SourceKind,
+        WalkOptions, count_sources, glob_root, read_sources, resolve_inputs,
+    };
+
+    fn get_tokeniser_fixture() -> Tokenizer {
+        Tokenizer::from_file("tests/fixtures/tokenizer.json").unwrap()
+    }
+
+    fn get_directory_fixture() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("prompts/system.md")
+            .write_str("Why is the sky blue?")
+            .unwrap();
+        temp_dir
+            .child("prompts/nested/user.txt")
+            .write_str("Hello world!")
+            .unwrap();
+        temp_dir.child("notes.md").write_str("A prompt.").unwrap();
+
+        temp_dir
+    }
+
+    #[test]
+    fn resolve_inputs_treats_lone_non_path_input_as_prompt_text() {
+        // arrange
+        let inputs = vec!["Why is the sky blue?".to_owned()];
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![PromptSource::Text("Why is the sky blue?".to_owned())]
+        );
+    }
+
+    #[test]
+    fn resolve_inputs_walks_directories_and_expands_globs() {
+        // arrange
+        let temp_dir = get_directory_fixture();
+        let inputs = vec![
+            temp_dir.join("prompts").display().to_string(),
+            temp_dir.join("*.md").display().to_string(),
+        ];
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![
+                PromptSource::File {
+                    path: temp_dir.join("prompts/nested/user.txt"),
+                    explicit: false,
+                },
+                PromptSource::File {
+                    path: temp_dir.join("prompts/system.md"),
+                    explicit: false,
+                },
+                PromptSource::File {
+                    path: temp_dir.join("notes.md"),
+                    explicit: false,
+                },
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_treats_a_lone_question_as_prompt_text() {
+        // arrange
+        let inputs = vec!["Why is the sky blue?".to_owned()];
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![PromptSource::Text("Why is the sky blue?".to_owned())]
+        );
+    }
+
+    #[test]
+    fn resolve_inputs_treats_a_lone_prompt_matching_a_file_as_prompt_text() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("Why1").write_str("Not the prompt").unwrap();
+        let prompt = temp_dir.join("Why?").display().to_string();
+        let inputs = vec![prompt.clone()];
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap();
+
+        // assert
+        assert_eq!(outcome, vec![PromptSource::Text(prompt)]);
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_treats_a_lone_invalid_glob_pattern_as_prompt_text() {
+        // arrange
+        let inputs = vec!["arr[i".to_owned()];
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap();
+
+        // assert
+        assert_eq!(outcome, vec![PromptSource::Text("arr[i".to_owned())]);
+    }
+
+    #[test]
+    fn resolve_inputs_only_includes_each_file_once() {
+        // arrange
+        let temp_dir = get_directory_fixture();
+        let files = vec![temp_dir.join("notes.md")];
+        let inputs = vec![
+            temp_dir.join("*.md").display().to_string(),
+            "-".to_owned(),
+            "-".to_owned(),
+        ];
+
+        // act
+        let outcome = resolve_inputs(&files, &inputs, &WalkOptions::default()).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![
+                PromptSource::File {
+                    path: temp_dir.join("notes.md"),
+                    explicit: true,
+                },
+                PromptSource::Stdin,
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_returns_error_for_unmatched_pattern() {
+        // arrange
+        let temp_dir = get_directory_fixture();
+        let unmatched = temp_dir.join("*.json").display().to_string();
+        let inputs = vec![temp_dir.join("notes.md").display().to_string(), unmatched];
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some(format!(
+                "No files matching `{}`",
+                temp_dir.join("*.json").display()
+            ))
+        );
+        assert!(chain.next().is_none());
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn count_sources_counts_each_file_and_skips_unreadable_discovered_files() {
+        // arrange
+        let temp_dir = get_directory_fixture();
+        temp_dir
+            .child("prompts/image.png")
+            .write_binary(&[0xF8, 0x82, 0x80])
+            .unwrap();
+        let inputs = vec![temp_dir.join("prompts").display().to_string()];
+        let sources = resolve_inputs::<PathBuf>(&[], &inputs, &WalkOptions::default()).unwrap();
+        let tokeniser = get_tokeniser_fixture();
+
+        // act
+        let loaded = read_sources(&sources, std::io::empty()).unwrap();
+        let outcome = count_sources(&tokeniser, &loaded, true).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome.counted,
+            vec![
+                InputCount {
+                    kind: SourceKind::File,
+                    path: Some(temp_dir.join("prompts/nested/user.txt")),
+                    tokens: 4,
+                    special_tokens: 1,
+                    chars: 12,
+                    bytes: 12,
+                },
+                InputCount {
+                    kind: SourceKind::File,
+                    path: Some(temp_dir.join("prompts/system.md")),
+                    tokens: 7,
+                    special_tokens: 1,
+                    chars: 20,
+                    bytes: 20,
+                },
+            ]
+        );
+        assert_eq!(
+            outcome.skipped,
+            vec![SkippedInput {
+                path: temp_dir.join("prompts/image.png"),
+                reason: format!(
+                    "Error reading file `{}`",
+                    temp_dir.join("prompts/image.png").display()
+                ),
+            }]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn count_sources_leaves_out_added_special_tokens_but_counts_written_ones() {
+        // arrange
+        let texts = ["Hello world!", "A prompt.[EOS]"];
+        let sources = texts.map(|text| PromptSource::Text(text.to_owned()));
+        let loaded = LoadedSources {
+            inputs: sources
+                .iter()
+                .zip(texts)
+                .map(|(source, text)| LoadedInput {
+                    source,
+                    text: text.to_owned(),
+                })
+                .collect(),
+            skipped: Vec::new(),
+        };
+        let tokeniser = get_tokeniser_fixture();
+
+        // act
+        let outcome = count_sources(&tokeniser, &loaded, false).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome
+                .counted
+                .iter()
+                .map(|count| (count.tokens, count.special_tokens, count.content_tokens()))
+                .collect::<Vec<_>>(),
+            [(3, 0, 3), (4, 1, 3)]
+        );
+        assert_eq!(outcome.total_tokens(), 7);
+    }
+
+    #[test]
+    fn read_sources_returns_error_for_unreadable_explicit_file() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("image.png")
+            .write_binary(&[0xF8, 0x82, 0x80])
+            .unwrap();
+        let sources = vec![PromptSource::File {
+            path: temp_dir.join("image.png"),
+            explicit: true,
+        }];
+
+        // act
+        let outcome = read_sources(&sources, std::io::empty()).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some(format!(
+                "Error reading file `{}`",
+                temp_dir.join("image.png").display()
+            ))
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    fn get_ignore_fixture() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child(".git/HEAD").write_str("").unwrap();
+        temp_dir.child(".gitignore").write_str("target/\n").unwrap();
+        temp_dir.child(".ignore").write_str("*.log\n").unwrap();
+        temp_dir
+            .child(".tokenatorignore")
+            .write_str("drafts/\n")
+            .unwrap();
+        temp_dir.child("README.md").write_str("Hello").unwrap();
+        temp_dir
+            .child("src/main.rs")
+            .write_str("fn main() {}")
+            .unwrap();
+        temp_dir.child("src/.env").write_str("SECRET=1").unwrap();
+        temp_dir.child("build.log").write_str("Build").unwrap();
+        temp_dir.child("drafts/idea.md").write_str("Idea").unwrap();
+        temp_dir
+            .child("target/debug/output.txt")
+            .write_str("Output")
+            .unwrap();
+
+        temp_dir
+    }
+
+    fn resolve_fixture_paths(temp_dir: &TempDir, options: &WalkOptions) -> Vec<PathBuf> {
+        let inputs = vec![temp_dir.path().display().to_string()];
+        resolve_inputs::<PathBuf>(&[], &inputs, options)
+            .unwrap()
+            .iter()
+            .map(|source| {
+                source
+                    .path()
+                    .unwrap()
+                    .strip_prefix(temp_dir.path())
+                    .unwrap()
+                    .to_path_buf()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolve_inputs_respects_ignore_files_and_skips_hidden_files() {
+        // arrange
+        let temp_dir = get_ignore_fixture();
+
+        // act
+        let outcome = resolve_fixture_paths(&temp_dir, &WalkOptions::default());
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![PathBuf::from("README.md"), PathBuf::from("src/main.rs")]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_includes_hidden_and_ignored_files_when_requested() {
+        // arrange
+        let temp_dir = get_ignore_fixture();
+        let options = WalkOptions {
+            hidden: true,
+            no_ignore: true,
+            exclude: vec![".git/".to_owned()],
+            ..WalkOptions::default()
+        };
+
+        // act
+        let outcome = resolve_fixture_paths(&temp_dir, &options);
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from(".ignore"),
+                PathBuf::from(".tokenatorignore"),
+                PathBuf::from("README.md"),
+                PathBuf::from("build.log"),
+                PathBuf::from("drafts/idea.md"),
+                PathBuf::from("src/.env"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("target/debug/output.txt"),
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_applies_include_and_exclude_patterns() {
+        // arrange
+        let temp_dir = get_ignore_fixture();
+        temp_dir.child("docs/guide.md").write_str("Guide").unwrap();
+        temp_dir.child("docs/api.md").write_str("API").unwrap();
+        let options = WalkOptions {
+            include: vec!["*.md".to_owned()],
+            exclude: vec!["api.md".to_owned()],
+            ..WalkOptions::default()
+        };
+
+        // act
+        let outcome = resolve_fixture_paths(&temp_dir, &options);
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![PathBuf::from("README.md"), PathBuf::from("docs/guide.md")]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_filters_glob_matches_like_directory_walks() {
+        // arrange
+        let temp_dir = get_ignore_fixture();
+        temp_dir
+            .child("drafts/.notes.md")
+            .write_str("Notes")
+            .unwrap();
+        let all = temp_dir.join("**/*").display().to_string();
+        let dotfiles = temp_dir.join("*/.*").display().to_string();
+        let hidden = WalkOptions {
+            hidden: true,
+            no_ignore: true,
+            exclude: vec![".git/".to_owned()],
+            ..WalkOptions::default()
+        };
+        let resolve = |input: &str, options: &WalkOptions| {
+            resolve_inputs(&[input], &[], options).map(|sources| {
+                sources
+                    .iter()
+                    .map(|source| {
+                        source
+                            .path()
+                            .unwrap()
+                            .strip_prefix(&temp_dir)
+                            .unwrap()
+                            .to_path_buf()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // act
+        let filtered = resolve(&all, &WalkOptions::default()).unwrap();
+        let unfiltered = resolve(&all, &hidden).unwrap();
+        let filtered_dotfiles = resolve(&dotfiles, &WalkOptions::default());
+        let unfiltered_dotfiles = resolve(&dotfiles, &hidden).unwrap();
+
+        // assert
+        assert_eq!(
+            filtered,
+            vec![PathBuf::from("README.md"), PathBuf::from("src/main.rs")]
+        );
+        assert_eq!(
+            unfiltered,
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from(".ignore"),
+                PathBuf::from(".tokenatorignore"),
+                PathBuf::from("README.md"),
+                PathBuf::from("build.log"),
+                PathBuf::from("drafts/.notes.md"),
+                PathBuf::from("drafts/idea.md"),
+                PathBuf::from("src/.env"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("target/debug/output.txt"),
+            ]
+        );
+        assert_eq!(
+            filtered_dotfiles.unwrap_err().to_string(),
+            format!("No files matching `{dotfiles}`")
+        );
+        assert_eq!(
+            unfiltered_dotfiles,
+            vec![PathBuf::from("drafts/.notes.md"), PathBuf::from("src/.env")]
+        );
+        assert_eq!(glob_root("*.md"), PathBuf::from("."));
+        assert_eq!(glob_root("prompts/*/system.md"), PathBuf::from("prompts"));
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_does_not_filter_explicit_files() {
+        // arrange
+        let temp_dir = get_ignore_fixture();
+        let files = vec![temp_dir.join("build.log")];
+        let options = WalkOptions {
+            exclude: vec!["*.log".to_owned()],
+            ..WalkOptions::default()
+        };
+
+        // act
+        let outcome = resolve_inputs::<PathBuf>(&files, &[], &options).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![PromptSource::File {
+                path: temp_dir.join("build.log"),
+                explicit: true,
+            }]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::prompt::MAX_PROMPT_BYTES;
+
+/// Largest message accepted: the largest prompt, with room for the rest of the JSON-RPC message.
+pub const MAX_MESSAGE_BYTES: usize = MAX_PROMPT_BYTES + 64 * 1024;
+
+/// JSON-RPC error codes.
+pub const PARSE_ERROR: i64 = -32_700;
+pub const INVALID_REQUEST: i64 = -32_600;
+pub const METHOD_NOT_FOUND: i64 = -32_601;
+pub const INVALID_PARAMS: i64 = -32_602;
+pub const INTERNAL_ERROR: i64 = -32_603;
+
+/// A JSON-RPC request, or a notification when there is no `id`.
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    jsonrpc: String,
+
+    #[serde(default)]
+    pub id: Option<Value>,
+
+    pub method: String,
+
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// A JSON-RPC error, returned in place of a result.
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Parses a JSON-RPC message.  Responses to requests the server sent are returned as [`None`],
+/// since they need no reply.
+///
+/// # Errors
+/// Errors with the error response to send back, if the message is not valid JSON or not a
+/// JSON-RPC 2.0 request.
+pub fn parse_message(text: &str) -> Result<Option<Message>, Value> {
+    let value: Value = serde_json::from_str(text).map_err(|error| {
+        error_response(
+            &Value::Null,
+            &RpcError::new(PARSE_ERROR, format!("Parse error: {error}")),
+        )
+    })?;
+    if value.get("method").is_none() && value.get("id").is_some() {
+        return Ok(None);
+    }
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    match serde_json::from_value::<Message>(value) {
+        Ok(message) if message.jsonrpc == "2.0" => Ok(Some(message)),
+        Ok(_) | Err(_) => Err(error_response(
+            &id,
+            &RpcError::new(INVALID_REQUEST, "Invalid JSON-RPC 2.0 request"),
+        )),
+    }
+}
+
+/// Returns the response to the request with `id`.
+pub fn response(id: &Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => error_response(id, &error),
+    }
+}
+
+pub fn error_response(id: &Value, error: &RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+/// Returns a notification, which the client does not reply to.
+pub fn notification(method: &str, params: &Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
@@ -0,0 +1,18 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+pub mod chat;
+mod counter;
+pub mod errors;
+pub mod models;
+pub mod registry;
+mod sentencepiece;
+pub mod tiktoken;
+pub mod token;
+
+pub use counter::{CounterCache, TokenCounter};
+pub use errors::AppError;
+pub use models::ResolvedModel;
+pub use registry::{ModelEntry, ModelSources, Registry};
+pub use token::{
+    HubOptions, TokenCount, TokenInfo, TokenizerBackend, TokenizerPin, TokenizerSource,
+};
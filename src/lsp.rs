@@ -0,0 +1,1073 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+    num::NonZeroUsize,
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use miette::{IntoDiagnostic, WrapErr, miette};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokenator::{
+    CounterCache, HubOptions, ModelSources, Registry, ResolvedModel, TokenCounter,
+    registry::project_models_path, token::TokenInfo,
+};
+
+use crate::{
+    budget::Budget,
+    jsonrpc::{
+        INTERNAL_ERROR, INVALID_REQUEST, MAX_MESSAGE_BYTES, METHOD_NOT_FOUND, Message, RpcError,
+        notification, parse_message, response,
+    },
+    output::{context_share, format_number},
+    prompt::MAX_PROMPT_BYTES,
+    visualise::show_whitespace,
+};
+
+/// Most tokenizers to keep loaded.  Each workspace has its own server, so only a change of the
+/// `model` setting loads another.
+const MAX_TOKENIZERS: NonZeroUsize = NonZeroUsize::new(2).unwrap();
+
+/// Most tokens from the hovered line shown in a hover, centred on the hovered token.
+const HOVER_TOKENS: usize = 24;
+
+/// Longest message header line read, well over any `Content-Length` or `Content-Type` header.
+const MAX_HEADER_BYTES: usize = 1024;
+
+/// LSP error code for requests sent before `initialize`.
+const SERVER_NOT_INITIALIZED: i64 = -32_002;
+
+/// LSP diagnostic severity and message type for warnings, and message type for errors.
+const WARNING: u8 = 2;
+const ERROR: u8 = 1;
+
+/// Model and budget settings, set on the command line and overridden by editor settings.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    /// Model to count tokens for.
+    pub model: Option<String>,
+
+    /// Warn when a document uses more than this many tokens.
+    pub max_tokens: Option<usize>,
+
+    /// Warn when a document uses more than this share of the model context window.
+    pub max_percent: Option<f64>,
+}
+
+impl Settings {
+    /// Parses editor settings, either at the top level or under a `tokenator` key, as
+    /// `workspace/didChangeConfiguration` usually sends them.  Invalid settings are logged and
+    /// ignored.
+    fn from_editor(value: &Value) -> Self {
+        let value = value.get("tokenator").unwrap_or(value);
+        if value.is_null() {
+            return Self::default();
+        }
+
+        serde_json::from_value(value.clone()).unwrap_or_else(|error| {
+            log::warn!("Ignoring invalid settings: {error}");
+            Self::default()
+        })
+    }
+
+    /// Returns these settings, with any not set taken from `defaults`.
+    fn or(self, defaults: &Self) -> Self {
+        Self {
+            model: self.model.or_else(|| defaults.model.clone()),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            max_percent: self
+                .max_percent
+                .filter(|percent| percent.is_finite() && *percent > 0.0)
+                .or(defaults.max_percent),
+        }
+    }
+
+    fn budget(&self) -> Budget {
+        Budget {
+            max_tokens: self.max_tokens,
+            max_percent: self.max_percent,
+        }
+    }
+}
+
+/// Options for the `lsp` subcommand.
+pub struct LspOptions {
+    /// Settings used where the editor does not set them.
+    pub settings: Settings,
+    pub models_file: Option<PathBuf>,
+    pub hub: HubOptions,
+}
+
+/// Units LSP positions count characters in.  Clients must support UTF-16, and may offer UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PositionEncoding {
+    Utf8,
+    Utf16,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+struct Position {
+    line: usize,
+    character: usize,
+}
+
+/// A markdown section, from an ATX heading, such as `## Examples`, to the next heading.
+#[derive(Debug, PartialEq, Eq)]
+struct Section {
+    /// Line of the heading.
+    line: usize,
+
+    /// Start and end byte offsets of the section.
+    bytes: (usize, usize),
+}
+
+/// Returns `true` for markdown ATX headings: up to three spaces, then one to six `#` characters
+/// followed by whitespace or the line end.
+fn is_heading(line: &str) -> bool {
+    let rest = line.trim_start_matches(' ');
+    let hashes = rest.len() - rest.trim_start_matches('#').len();
+
+    line.len() - rest.len() <= 3
+        && (1..=6).contains(&hashes)
+        && rest[hashes..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+}
+
+/// Splits `text` into sections at markdown headings, skipping `#` lines in fenced code blocks.
+/// Text before the first heading is not in any section.
+fn sections(text: &str) -> Vec<Section> {
+    let mut headings = Vec::new();
+    let mut fenced = false;
+    let mut start = 0;
+    for (line, content) in text.split_inclusive('\n').enumerate() {
+        let trimmed = content.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fenced = !fenced;
+        } else if !fenced && is_heading(content) {
+            headings.push((line, start));
+        }
+        start += content.len();
+    }
+
+    headings
+        .iter()
+        .enumerate()
+        .map(|(index, &(line, start))| Section {
+            line,
+            bytes: (
+                start,
+                headings.get(index + 1).map_or(text.len(), |&(_, end)| end),
+            ),
+        })
+        .collect()
+}
+
+/// Token counts for a document, kept until the document or settings change.
+struct Analysis {
+    model: ResolvedModel,
+    tokens: Vec<TokenInfo>,
+
+    /// Heading line and token count for each section, leaving out special tokens.
+    sections: Vec<(usize, usize)>,
+}
+
+impl Analysis {
+    fn new(counter: &TokenCounter, text: &str) -> Result<Self, String> {
+        if text.len() > MAX_PROMPT_BYTES {
+            return Err(format!("Document is too large to count: {}!", text.len()));
+        }
+        let tokens = counter
+            .tokenize(text, true)
+            .map_err(|error| error.to_string())?;
+        let sections = sections(text)
+            .iter()
+            .map(|section| {
+                let count = tokens
+                    .iter()
+                    .filter(|token| {
+                        !token.special
+                            && token.bytes.0 >= section.bytes.0
+                            && token.bytes.0 < section.bytes.1
+                    })
+                    .count();
+                (section.line, count)
+            })
+            .collect();
+
+        Ok(Self {
+            model: counter.model().clone(),
+            tokens,
+            sections,
+        })
+    }
+}
+
+/// An open document, with its token counts, if they could be counted.
+struct Document {
+    text: String,
+
+    /// Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    analysis: Option<Analysis>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        Self {
+            text,
+            line_starts,
+            analysis: None,
+        }
+    }
+
+    /// Returns the text of `line`, without its line ending.
+    fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |end| end - 1);
+
+        self.text[start..end].trim_end_matches('\r')
+    }
+
+    /// Returns the position of the byte at `offset`, rounding offsets inside a character down to
+    /// its start, which tiktoken token boundaries may fall within.
+    fn position(&self, offset: usize, encoding: PositionEncoding) -> Position {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let before = &self.text[self.line_starts[line]..offset];
+        let character = match encoding {
+            PositionEncoding::Utf8 => before.len(),
+            PositionEncoding::Utf16 => before.encode_utf16().count(),
+        };
+
+        Position { line, character }
+    }
+
+    /// Returns the position at the end of `line`.
+    fn line_end(&self, line: usize, encoding: PositionEncoding) -> Position {
+        self.position(self.line_starts[line] + self.line(line).len(), encoding)
+    }
+
+    /// Returns the byte offset of `position`, or [`None`] if it is past the end of the document.
+    /// Characters past the line end are clamped to the line end, as the LSP specification asks.
+    fn offset(&self, position: Position, encoding: PositionEncoding) -> Option<usize> {
+        let start = *self.line_starts.get(position.line)?;
+        let mut units = 0;
+        for (index, char) in self.line(position.line).char_indices() {
+            if units >= position.character {
+                return Some(start + index);
+            }
+            units += match encoding {
+                PositionEncoding::Utf8 => char.len_utf8(),
+                PositionEncoding::Utf16 => char.len_utf16(),
+            };
+        }
+
+        Some(start + self.line(position.line).len())
+    }
+
+    fn range(&self, bytes: (usize, usize), encoding: PositionEncoding) -> Value {
+        json!({
+            "start": self.position(bytes.0, encoding),
+            "end": self.position(bytes.1, encoding),
+        })
+    }
+}
+
+/// Wraps `text` in an inline code span, using double backticks if it holds a backtick.
+fn inline_code(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {text} ``")
+    } else {
+        format!("`{text}`")
+    }
+}
+
+/// Language server showing token counts for prompt documents as inlay hints, with diagnostics
+/// for documents over the budget and hovers showing how lines split into tokens.
+pub struct LanguageServer {
+    options: LspOptions,
+    settings: Settings,
+    encoding: PositionEncoding,
+
+    /// Counters for the workspace models, set once initialised.
+    counters: Option<CounterCache>,
+    documents: HashMap<String, Document>,
+
+    /// Whether the client accepts requests to refresh inlay hints.
+    refresh_hints: bool,
+    next_request: u64,
+
+    /// Last error shown to the user, so repeated failures, such as on each keystroke with an
+    /// unknown model, are only shown once.
+    last_error: Option<String>,
+    shut_down: bool,
+}
+
+impl LanguageServer {
+    pub fn new(options: LspOptions) -> Self {
+        Self {
+            settings: options.settings.clone(),
+            options,
+            encoding: PositionEncoding::Utf16,
+            counters: None,
+            documents: HashMap::new(),
+            refresh_hints: false,
+            next_request: 0,
+            last_error: None,
+            shut_down: false,
+        }
+    }
+
+    /// Loads the registry for the workspace, using the project models file in the workspace
+    /// root, and negotiates the position encoding.
+    fn initialize(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let root = params["rootUri"]
+            .as_str()
+            .or_else(|| params["workspaceFolders"][0]["uri"].as_str())
+            .and_then(path_from_uri)
+            .or_else(|| params["rootPath"].as_str().map(PathBuf::from));
+        let mut sources = ModelSources::new(self.options.models_file.clone());
+        if let Some(root) = &root {
+            sources.project_file = Some(project_models_path(root));
+        }
+        let registry = Registry::load(&sources)
+            .map_err(|error| RpcError::new(INTERNAL_ERROR, error.to_string()))?;
+        self.counters = Some(CounterCache::new(
+            registry,
+            self.options.hub.clone(),
+            MAX_TOKENIZERS,
+        ));
+        self.settings =
+            Settings::from_editor(&params["initializationOptions"]).or(&self.options.settings);
+        let capabilities = &params["capabilities"];
+        if capabilities["general"]["positionEncodings"]
+            .as_array()
+            .is_some_and(|encodings| encodings.iter().any(|encoding| encoding == "utf-8"))
+        {
+            self.encoding = PositionEncoding::Utf8;
+        }
+        self.refresh_hints = capabilities["workspace"]["inlayHint"]["refreshSupport"] == true;
+
+        Ok(json!({
+            "capabilities": {
+                "positionEncoding": match self.encoding {
+                    PositionEncoding::Utf8 => "utf-8",
+                    PositionEncoding::Utf16 => "utf-16",
+                },
+                "textDocumentSync": { "openClose": true, "change": 1 },
+                "hoverProvider": true,
+                "inlayHintProvider": true,
+            },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        }))
+    }
+
+    /// Returns the counter for the model in the settings.
+    fn counter(&self) -> Result<Arc<TokenCounter>, String> {
+        let counters = self
+            .counters
+            .as_ref()
+            .ok_or("The server is not initialised")?;
+        let model = self.settings.model.as_deref().ok_or(
+            "Set the model to count tokens for with `tokenator lsp --model`, or the `model` \
+            setting",
+        )?;
+
+        counters.get(model).map_err(|error| error.to_string())
+    }
+
+    /// Counts tokens for the document at `uri` and publishes its diagnostics.  Failures are shown
+    /// to the user, unless the same failure was the last shown.
+    fn analyse(&mut self, uri: &str, outgoing: &mut Vec<Value>) {
+        let Some(text) = self.documents.get(uri).map(|document| &document.text) else {
+            return;
+        };
+        let analysis = self
+            .counter()
+            .and_then(|counter| Analysis::new(&counter, text));
+        let analysis = match analysis {
+            Ok(analysis) => {
+                self.last_error = None;
+                Some(analysis)
+            }
+            Err(error) => {
+                log::warn!("Unable to count tokens for `{uri}`: {error}");
+                if self.last_error.as_ref() != Some(&error) {
+                    outgoing.push(notification(
+                        "window/showMessage",
+                        &json!({ "type": ERROR, "message": format!("tokenator: {error}") }),
+                    ));
+                    self.last_error = Some(error);
+                }
+                None
+            }
+        };
+        if let Some(document) = self.documents.get_mut(uri) {
+            document.analysis = analysis;
+        }
+        outgoing.push(self.diagnostics(uri));
+    }
+
+    /// Returns the `textDocument/publishDiagnostics` notification for the document at `uri`, with
+    /// a warning over the part of the document past the budget, if it is over budget.
+    fn diagnostics(&self, uri: &str) -> Value {
+        let mut diagnostics = Vec::new();
+        if let Some(document) = self.documents.get(uri)
+            && let Some(analysis) = &document.analysis
+        {
+            let context_length = analysis.model.context_length.filter(|length| *length > 0);
+            if self.settings.max_percent.is_some() && context_length.is_none() {
+                log::warn!(
+                    "Ignoring the percentage budget, as the context length for `{}` is not known",
+                    analysis.model.name
+                );
+            }
+            if let Some(limit) = self.settings.budget().limit(context_length)
+                && analysis.tokens.len() > limit
+            {
+                let start = analysis.tokens[limit..]
+                    .iter()
+                    .find(|token| !token.special)
+                    .map_or(document.text.len(), |token| token.bytes.0);
+                diagnostics.push(json!({
+                    "range": document.range((start, document.text.len()), self.encoding),
+                    "severity": WARNING,
+                    "source": env!("CARGO_PKG_NAME"),
+                    "code": "budget",
+                    "message": format!(
+                        "Document uses {} tokens with `{}`, over the budget of {} tokens",
+                        format_number(analysis.tokens.len()),
+                        analysis.model.name,
+                        format_number(limit)
+                    ),
+                }));
+            }
+        }
+
+        notification(
+            "textDocument/publishDiagnostics",
+            &json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /// Returns inlay hints with the token count for each section heading in the requested range,
+    /// and the document total at the end of the first line.
+    fn inlay_hints(&self, params: &Value) -> Value {
+        let Some(document) = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+        else {
+            return json!([]);
+        };
+        let Some(analysis) = &document.analysis else {
+            return json!([]);
+        };
+        let lines = params["range"]["start"]["line"].as_u64().unwrap_or(0)
+            ..=params["range"]["end"]["line"].as_u64().unwrap_or(u64::MAX);
+        let in_range = |line: usize| lines.contains(&u64::try_from(line).unwrap_or(u64::MAX));
+        let hint = |line: usize, label: String| {
+            json!({
+                "position": document.line_end(line, self.encoding),
+                "label": label,
+                "paddingLeft": true,
+            })
+        };
+
+        let mut hints: Vec<Value> = analysis
+            .sections
+            .iter()
+            .filter(|(line, _)| in_range(*line))
+            .map(|&(line, tokens)| hint(line, format!("{} tokens", format_number(tokens))))
+            .collect();
+        if in_range(0) {
+            let total = analysis.tokens.len();
+            let share = context_share(total, &analysis.model)
+                .map(|share| format!(" ({share})"))
+                .unwrap_or_default();
+            let label = format!(
+                "{} tokens with {}{share}",
+                format_number(total),
+                analysis.model.name
+            );
+            hints.insert(
+                usize::from(
+                    analysis
+                        .sections
+                        .first()
+                        .is_some_and(|(line, _)| *line == 0),
+                ),
+                hint(0, label),
+            );
+        }
+
+        Value::Array(hints)
+    }
+
+    /// Returns a hover for the token under the cursor, showing how its line splits into tokens.
+    fn hover(&self, params: &Value) -> Value {
+        let Some(document) = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+        else {
+            return Value::Null;
+        };
+        let (Some(analysis), Ok(position)) = (
+            &document.analysis,
+            serde_json::from_value::<Position>(params["position"].clone()),
+        ) else {
+            return Value::Null;
+        };
+        let Some(offset) = document.offset(position, self.encoding) else {
+            return Value::Null;
+        };
+        let Some((index, token)) =
+            analysis.tokens.iter().enumerate().find(|(_, token)| {
+                !token.special && token.bytes.0 <= offset && offset < token.bytes.1
+            })
+        else {
+            return Value::Null;
+        };
+
+        let line_start = document.line_starts[position.line];
+        let line_end = line_start + document.line(position.line).len();
+        let line_tokens: Vec<(usize, &TokenInfo)> = analysis
+            .tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| {
+                !token.special && token.bytes.0 <= line_end && token.bytes.1 > line_start
+            })
+            .collect();
+        let hovered = line_tokens
+            .iter()
+            .position(|(line_index, _)| *line_index == index)
+            .unwrap_or_default();
+        let first = hovered
+            .saturating_sub(HOVER_TOKENS / 2)
+            .min(line_tokens.len().saturating_sub(HOVER_TOKENS));
+        let last = (first + HOVER_TOKENS).min(line_tokens.len());
+        let mut split: Vec<String> = line_tokens[first..last]
+            .iter()
+            .map(|&(line_index, line_token)| {
+                let code = inline_code(&show_whitespace(&line_token.text).replace('\n', ""));
+                if line_index == index {
+                    format!("**{code}**")
+                } else {
+                    code
+                }
+            })
+            .collect();
+        if first > 0 {
+            split.insert(0, "…".to_owned());
+        }
+        if last < line_tokens.len() {
+            split.push("…".to_owned());
+        }
+        let value = format!(
+            "**Token {} of {}** · ID `{}` · {}\n\nLine {} splits into {} tokens:\n\n{}",
+            format_number(index + 1),
+            format_number(analysis.tokens.len()),
+            token.id,
+            inline_code(&token.token),
+            position.line + 1,
+            format_number(line_tokens.len()),
+            split.join(" ")
+        );
+
+        json!({
+            "contents": { "kind": "markdown", "value": value },
+            "range": document.range(token.bytes, self.encoding),
+        })
+    }
+
+    fn request(&mut self, message: &Message) -> Result<Value, RpcError> {
+        match (message.method.as_str(), &self.counters) {
+            ("initialize", None) => self.initialize(&message.params),
+            ("initialize", Some(_)) => Err(RpcError::new(
+                INVALID_REQUEST,
+                "The server is already initialised",
+            )),
+            (_, None) => Err(RpcError::new(
+                SERVER_NOT_INITIALIZED,
+                "The server is not initialised",
+            )),
+            (_, Some(_)) if self.shut_down => Err(RpcError::new(
+                INVALID_REQUEST,
+                "The server is shutting down",
+            )),
+            ("shutdown", Some(_)) => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            ("textDocument/inlayHint", Some(_)) => Ok(self.inlay_hints(&message.params)),
+            ("textDocument/hover", Some(_)) => Ok(self.hover(&message.params)),
+            (method, Some(_)) => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: `{method}`"),
+            )),
+        }
+    }
+
+    fn notify(&mut self, message: &Message, outgoing: &mut Vec<Value>) {
+        if self.counters.is_none() || self.shut_down {
+            return;
+        }
+        let params = &message.params;
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match message.method.as_str() {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents
+                    .insert(uri.to_owned(), Document::new(text.to_owned()));
+                self.analyse(uri, outgoing);
+            }
+            "textDocument/didChange" => {
+                // Documents are synced in full, so the last change holds the whole text
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents
+                        .insert(uri.to_owned(), Document::new(text.to_owned()));
+                    self.analyse(uri, outgoing);
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                outgoing.push(notification(
+                    "textDocument/publishDiagnostics",
+                    &json!({ "uri": uri, "diagnostics": [] }),
+                ));
+            }
+            "workspace/didChangeConfiguration" => {
+                self.settings =
+                    Settings::from_editor(&params["settings"]).or(&self.options.settings);
+                let uris: Vec<String> = self.documents.keys().cloned().collect();
+                for uri in uris {
+                    self.analyse(&uri, outgoing);
+                }
+                if self.refresh_hints {
+                    self.next_request += 1;
+                    outgoing.push(json!({
+                        "jsonrpc": "2.0",
+                        "id": self.next_request,
+                        "method": "workspace/inlayHint/refresh",
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a single message, adding responses and notifications to send to `outgoing`.
+    /// Breaks on the `exit` notification.
+    fn handle(&mut self, text: &str, outgoing: &mut Vec<Value>) -> ControlFlow<()> {
+        let message = match parse_message(text) {
+            Ok(Some(message)) => message,
+            Ok(None) => return ControlFlow::Continue(()),
+            Err(error) => {
+                outgoing.push(error);
+                return ControlFlow::Continue(());
+            }
+        };
+        log::debug!("Received `{}`", message.method);
+        if message.method == "exit" {
+            return ControlFlow::Break(());
+        }
+        match &message.id {
+            Some(id) => {
+                let outcome = self.request(&message);
+                outgoing.push(response(id, outcome));
+            }
+            None => self.notify(&message, outgoing),
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Reads messages from `input` until the client sends `exit` or closes `input`, writing
+    /// responses and notifications to `output`.
+    ///
+    /// # Errors
+    /// Errors if unable to read or write a message, or if the client exits without first asking
+    /// the server to shut down.
+    pub fn serve<R: BufRead, W: Write>(
+        &mut self,
+        mut input: R,
+        mut output: W,
+    ) -> miette::Result<()> {
+        let mut outgoing = Vec::new();
+        while let Some(text) = read_message(&mut input)
+            .into_diagnostic()
+            .wrap_err("Reading a message from the client")?
+        {
+            let flow = self.handle(&text, &mut outgoing);
+            for message in outgoing.drain(..) {
+                write_message(&mut output, &message)
+                    .into_diagnostic()
+                    .wrap_err("Sending a message to the client")?;
+            }
+            if flow.is_break() {
+                return if self.shut_down {
+                    Ok(())
+                } else {
+                    Err(miette!(
+                        "The client exited without shutting the server down"
+                    ))
+                };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the file path for a `file://` URI, decoding percent-encoded bytes.
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        let byte = encoded
+            .get(index + 1..index + 3)
+            .filter(|_| encoded[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = byte {
+            decoded.push(byte);
+            index += 3;
+        } else {
+            decoded.push(encoded[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// Reads a message body, framed by a `Content-Length` header, or returns [`None`] once `input`
+/// closes.  Messages over [`MAX_MESSAGE_BYTES`], or which are not valid UTF-8, are discarded with
+/// a warning, without buffering the body, and the next message is read instead.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    loop {
+        let Some(length) = read_content_length(input)? else {
+            return Ok(None);
+        };
+        if length > MAX_MESSAGE_BYTES {
+            let length = u64::try_from(length).unwrap_or(u64::MAX);
+            io::copy(&mut (&mut *input).take(length), &mut io::sink())?;
+            log::warn!("Skipping a message over the {MAX_MESSAGE_BYTES} byte limit");
+            continue;
+        }
+        let mut body = vec![0; length];
+        input.read_exact(&mut body)?;
+        match String::from_utf8(body) {
+            Ok(body) => return Ok(Some(body)),
+            Err(error) => log::warn!("Skipping a message which is not valid UTF-8: {error}"),
+        }
+    }
+}
+
+/// Reads message headers, returning the `Content-Length` value, or [`None`] once `input` closes.
+/// Header lines over [`MAX_HEADER_BYTES`] are rejected.
+fn read_content_length<R: BufRead>(input: &mut R) -> io::Result<Option<usize>> {
+    let limit = u64::try_from(MAX_HEADER_BYTES).unwrap_or(u64::MAX);
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if (&mut *input).take(limit).read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        if !header.ends_with('\n') && header.len() >= MAX_HEADER_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Header is over the {MAX_HEADER_BYTES} byte limit"),
+            ));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = Some(value.trim().parse::<usize>().map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid `Content-Length` header: {error}"),
+                )
+            })?);
+        }
+    }
+
+    length.map(Some).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Missing `Content-Length` header",
+        )
+    })
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+
+    output.flush()
+}
+
+/// Runs the language server on standard input and output until the editor exits it.  Logs go to
+/// standard error, which editors usually show in a server log.
+///
+/// # Errors
+/// Errors if unable to read from standard input or write to standard output, or if the editor
+/// exits the server without first shutting it down.
+pub fn run_lsp_server(options: LspOptions) -> miette::Result<()> {
+    log::info!("Starting the language server on standard input and output");
+
+    LanguageServer::new(options).serve(io::stdin().lock(), io::stdout().lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::{Value, json};
+    use tokenator::HubOptions;
+
+    use crate::{
+        jsonrpc::MAX_MESSAGE_BYTES,
+        lsp::{
+            Document, LanguageServer, LspOptions, MAX_HEADER_BYTES, Position, PositionEncoding,
+            Section, Settings, path_from_uri, read_message, sections, write_message,
+        },
+    };
+
+    fn get_server_fixture() -> LanguageServer {
+        LanguageServer::new(LspOptions {
+            settings: Settings {
+                model: Some("local".to_owned()),
+                ..Settings::default()
+            },
+            models_file: Some(PathBuf::from("tests/fixtures/local-models.json")),
+            hub: HubOptions::default(),
+        })
+    }
+
+    fn frame(messages: &[Value]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+
+        input
+    }
+
+    /// Splits the framed server output back into messages.
+    fn unframe(output: &[u8]) -> Vec<Value> {
+        let mut reader = output;
+        std::iter::from_fn(|| read_message(&mut reader).unwrap())
+            .map(|body| serde_json::from_str(&body).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sections_split_at_headings_outside_code_blocks() {
+        // arrange
+        let text = "Preamble\n# System\nBe brief.\n```\n# comment\n```\n  ## Examples\n#tag\n";
+
+        // act
+        let outcome = sections(text);
+
+        // assert
+        assert_eq!(
+            outcome,
+            [
+                Section {
+                    line: 1,
+                    bytes: (9, 46)
+                },
+                Section {
+                    line: 6,
+                    bytes: (46, text.len())
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn document_converts_between_offsets_and_positions() {
+        // arrange
+        let document = Document::new("ab\r\n😀é x\n".to_owned());
+        let x = document.text.find('x').unwrap();
+
+        // act
+        let utf16 = document.position(x, PositionEncoding::Utf16);
+        let utf8 = document.position(x, PositionEncoding::Utf8);
+        let inside_emoji = document.position(5, PositionEncoding::Utf16);
+
+        // assert
+        assert_eq!(
+            utf16,
+            Position {
+                line: 1,
+                character: 4
+            }
+        );
+        assert_eq!(
+            utf8,
+            Position {
+                line: 1,
+                character: 7
+            }
+        );
+        assert_eq!(inside_emoji.character, 0);
+        assert_eq!(document.offset(utf16, PositionEncoding::Utf16), Some(x));
+        assert_eq!(document.offset(utf8, PositionEncoding::Utf8), Some(x));
+        assert_eq!(
+            document.line_end(0, PositionEncoding::Utf16),
+            Position {
+                line: 0,
+                character: 2
+            }
+        );
+        assert_eq!(
+            path_from_uri("file:///home/user/My%20Prompts"),
+            Some(PathBuf::from("/home/user/My Prompts"))
+        );
+    }
+
+    #[test]
+    fn serve_shows_counts_budget_warnings_and_token_hovers() {
+        // arrange
+        let mut server = get_server_fixture();
+        let uri = "file:///prompts/system.md";
+        let text = "# System\nWhy is the sky blue?\n# User\nHello world!\n";
+        let input = frame(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                "capabilities": {},
+                "initializationOptions": { "tokenator": { "maxTokens": 4 } },
+            }}),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": { "uri": uri, "languageId": "markdown", "version": 1, "text": text },
+            }}),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/inlayHint", "params": {
+                "textDocument": { "uri": uri },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 4, "character": 0 } },
+            }}),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 9 },
+            }}),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+        let mut output = Vec::new();
+
+        // act
+        server.serve(input.as_slice(), &mut output).unwrap();
+
+        // assert
+        let messages = unframe(&output);
+        assert_eq!(messages.len(), 5);
+        assert_eq!(
+            messages[0]["result"]["capabilities"]["positionEncoding"],
+            "utf-16"
+        );
+
+        let diagnostics = &messages[1]["params"]["diagnostics"];
+        assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(diagnostics[0]["code"], "budget");
+        assert_eq!(diagnostics[0]["range"]["end"]["line"], 4);
+
+        let hints = messages[2]["result"].as_array().unwrap();
+        let labels: Vec<&str> = hints
+            .iter()
+            .map(|hint| hint["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels.len(), 3);
+        assert!(labels[1].contains("tokens with local ("));
+        assert_eq!(hints[2]["position"], json!({ "line": 2, "character": 6 }));
+
+        let hover = messages[3]["result"]["contents"]["value"].as_str().unwrap();
+        assert!(hover.contains("Line 2 splits into"));
+        assert!(hover.contains("**`"));
+        assert_eq!(messages[3]["result"]["range"]["start"]["line"], 1);
+        assert_eq!(messages[4]["result"], Value::Null);
+    }
+
+    #[test]
+    fn serve_rejects_requests_before_initialize_and_exit_before_shutdown() {
+        // arrange
+        let mut server = get_server_fixture();
+        let input = frame(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "textDocument/hover", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+        let mut output = Vec::new();
+
+        // act
+        let outcome = server.serve(input.as_slice(), &mut output).unwrap_err();
+
+        // assert
+        assert_eq!(unframe(&output)[0]["error"]["code"], -32_002);
+        assert_eq!(
+            format!("{outcome}"),
+            "The client exited without shutting the server down"
+        );
+    }
+    #[test]
+    fn read_message_skips_oversized_messages() {
+        // arrange
+        let mut input = format!(
+            "Content-Length: {}\r\n\r\n{}",
+            MAX_MESSAGE_BYTES + 1,
+            "x".repeat(MAX_MESSAGE_BYTES + 1)
+        )
+        .into_bytes();
+        input.extend(frame(&[json!({ "jsonrpc": "2.0", "method": "exit" })]));
+
+        // act
+        let outcome = read_message(&mut input.as_slice()).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome.as_deref(),
+            Some(r#"{"jsonrpc":"2.0","method":"exit"}"#)
+        );
+    }
+
+    #[test]
+    fn read_message_rejects_oversized_headers() {
+        // arrange
+        let input = format!("X-Padding: {}\r\n\r\n{{}}", "x".repeat(MAX_HEADER_BYTES));
+
+        // act
+        let outcome = read_message(&mut input.as_bytes()).unwrap_err();
+
+        // assert
+        assert_eq!(
+            outcome.to_string(),
+            format!("Header is over the {MAX_HEADER_BYTES} byte limit")
+        );
+    }
+}
@@ -1,99 +1,438 @@
 #![warn(clippy::all, clippy::pedantic)]
 
+mod budget;
+mod cache;
 mod cli;
-mod errors;
-mod models;
+mod commands;
+mod files;
+mod jsonrpc;
+mod lsp;
+mod mcp;
+mod output;
 mod prompt;
-mod token;
+mod select;
+mod serve;
 mod utility;
+mod visualise;
+mod watch;
+
+use std::{
+    io::{self, IsTerminal},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use clap::Parser;
 use dotenvy::dotenv;
-use num_format::Locale;
+use miette::{bail, miette};
+use tokenator::{
+    CounterCache, HubOptions, ModelSources, Registry, ResolvedModel, TokenCounter, TokenizerPin,
+    TokenizerSource,
+    chat::{ChatOptions, count_chat, load_chat_template, messages_from_file},
+    errors::{AppError, exit_code},
+    token::fetch_context_length,
+};
 
 use crate::{
-    cli::Cli,
-    models::get_repo_id,
-    prompt::get_prompt,
-    token::{count_tokens, create_tokeniser},
+    budget::Budget,
+    cli::{Cli, Command},
+    commands::{run_cache_command, run_models_command},
+    files::{
+        LoadedInput, LoadedSources, PromptSource, WalkOptions, count_sources, read_sources,
+        resolve_inputs,
+    },
+    lsp::{LspOptions, Settings, run_lsp_server},
+    mcp::run_mcp_server,
+    output::{
+        ChatReport, Comparison, FailedModel, OutputFormat, Report, TokenReport, prompt_token_count,
+        render_chat_report, render_comparison, render_error, render_report, render_tokens,
+        special_count,
+    },
+    prompt::{get_prompt, is_stdin_path},
+    select::get_repo_ids,
+    serve::{ServeOptions, run_server},
+    utility::{read_file, read_stdin},
+    visualise::render_visualisation,
+    watch::{WatchOptions, run_watch},
 };
 
-fn format_number(number: usize) -> String {
-    let mut buf = num_format::Buffer::default();
-    buf.write_formatted(&number, &Locale::en);
+/// Reads the prompt text for a run with at most one input.  Standard input is read when it is
+/// piped and no other input is given.
+fn read_single_prompt(source: Option<&PromptSource>) -> miette::Result<String> {
+    let stdin = io::stdin();
+    let (file, prompt) = match source {
+        Some(PromptSource::File { path, .. }) => (Some(path.as_path()), None),
+        Some(PromptSource::Stdin) => (Some(Path::new("-")), None),
+        Some(PromptSource::Text(text)) => (None, Some(text.as_str())),
+        None => (None, None),
+    };
+    let read_stdin = file.is_some_and(is_stdin_path) || !stdin.is_terminal();
 
-    buf.as_str().to_owned()
+    get_prompt(file, prompt, read_stdin.then(|| stdin.lock()))
 }
 
-/// Main function to run the token counting tool.
-fn main() -> miette::Result<()> {
-    let cli = &Cli::parse();
-    dotenv().ok();
-    env_logger::Builder::new()
-        .filter_level(cli.verbose.log_level_filter())
-        .init();
-    let Cli {
+/// Fills in context lengths missing from the models file, reading them from each model
+/// `config.json` file.  Failures are logged, rather than ending the run, since the context length
+/// only adds detail to the report.
+fn detect_context_lengths(models: &mut [ResolvedModel], hub: &HubOptions) {
+    for model in models
+        .iter_mut()
+        .filter(|model| model.context_length.is_none())
+    {
+        match fetch_context_length(&model.tokenizer, model.pin.revision(), hub) {
+            Ok(context_length) => model.context_length = context_length,
+            Err(error) => log::warn!(
+                "Unable to read the context length for `{}`: {error}",
+                model.name
+            ),
+        }
+    }
+}
+
+/// Counts tokens for the loaded inputs with a single model, and prints the report, token list or
+/// visualisation requested on the command line.  The budget is checked after printing, so the
+/// counts are still shown when the prompt is over budget.
+fn print_single_model(
+    cli: &Cli,
+    model: &ResolvedModel,
+    loaded: &LoadedSources,
+    budget: Budget,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let counter = TokenCounter::load(model.clone(), hub)?;
+    let add_special_tokens = !cli.no_special_tokens;
+    if cli.visualise || cli.show_tokens {
+        let prompt_text = &loaded.inputs[0].text;
+        let tokens = counter.tokenize(prompt_text, add_special_tokens)?;
+        if cli.visualise {
+            let colour = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            print!("{}", render_visualisation(prompt_text, &tokens, colour));
+            println!(
+                "{}",
+                prompt_token_count(tokens.len(), special_count(&tokens), model)
+            );
+        } else {
+            let report = TokenReport {
+                model,
+                revision: model.pin.revision(),
+                tokens: &tokens,
+            };
+            print!("{}", render_tokens(&report, cli.format));
+        }
+
+        return budget.check(model, tokens.len());
+    }
+
+    let counts = count_sources(counter.tokeniser(), loaded, add_special_tokens)?;
+    let report = Report {
         model,
-        file,
-        prompt,
-        ..
-    } = cli;
+        revision: model.pin.revision(),
+        counts: &counts,
+    };
+    print!("{}", render_report(&report, cli.format));
 
-    let repo_id = get_repo_id(model.as_ref(), None)?;
-    let tokeniser = create_tokeniser(&repo_id)?;
-    let prompt_text = get_prompt(file.clone(), prompt.as_deref())?;
-    let tokens = count_tokens(&tokeniser, &prompt_text)?;
+    budget.check(model, counts.total_tokens())
+}
 
-    println!("Prompt token count: {}", format_number(tokens));
+/// Renders the conversation in a messages file through the model chat template, and prints the
+/// token counts.  As for prompts, the budget is checked after printing.
+fn print_chat(
+    cli: &Cli,
+    path: &Path,
+    model: &ResolvedModel,
+    budget: Budget,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let content = if is_stdin_path(path) {
+        read_stdin(io::stdin())?
+    } else {
+        read_file(path)?
+    };
+    let messages = messages_from_file(path, &content)?;
+    let template = load_chat_template(&model.tokenizer, model.pin.revision(), hub)?;
+    let counter = TokenCounter::load(model.clone(), hub)?;
+    let options = ChatOptions {
+        add_generation_prompt: cli.add_generation_prompt,
+        per_message: cli.per_message,
+    };
+    let counts = count_chat(counter.tokeniser(), &template, &messages, options)?;
+    let report = ChatReport {
+        model,
+        revision: model.pin.revision(),
+        counts: &counts,
+    };
+    print!("{}", render_chat_report(&report, cli.format));
 
-    Ok(())
+    budget.check(model, counts.tokens)
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::format_number;
+/// Counts tokens for the loaded inputs with each model, and prints a comparison against the
+/// baseline model.  Models other than the baseline which fail to load are reported, rather than
+/// ending the run.  Every model counted with must fit the budget.
+fn print_comparison(
+    models: &[ResolvedModel],
+    baseline: usize,
+    loaded: &LoadedSources,
+    add_special_tokens: bool,
+    format: OutputFormat,
+    budget: Budget,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let mut counted = Vec::with_capacity(models.len());
+    let mut failed = Vec::new();
+    for (index, model) in models.iter().enumerate() {
+        let counts = TokenCounter::load(model.clone(), hub)
+            .and_then(|counter| count_sources(counter.tokeniser(), loaded, add_special_tokens));
+        match counts {
+            Ok(counts) => counted.push((model, counts)),
+            Err(error) if index == baseline => return Err(error.into()),
+            Err(error) => {
+                log::warn!("Unable to count tokens for `{}`: {error}", model.name);
+                failed.push(FailedModel {
+                    model,
+                    reason: error.to_string(),
+                });
+            }
+        }
+    }
+    let comparison = Comparison {
+        baseline: counted
+            .iter()
+            .position(|(model, _)| model.name == models[baseline].name)
+            .expect("baseline model should have been counted"),
+        reports: counted
+            .iter()
+            .map(|(model, counts)| Report {
+                model,
+                revision: model.pin.revision(),
+                counts,
+            })
+            .collect(),
+        failed,
+    };
+    print!("{}", render_comparison(&comparison, format));
 
-    #[test]
-    fn cli_tests() {
-        trycmd::TestCases::new().case("tests/cmd/*.toml");
+    counted
+        .iter()
+        .try_for_each(|(model, counts)| budget.check(model, counts.total_tokens()))
+}
+
+/// Returns the models to count with: the local tokenizer passed with `--tokenizer`, or the models
+/// named on the command line, with any `--revision` override applied.
+fn resolve_models(cli: &Cli) -> miette::Result<Vec<ResolvedModel>> {
+    let mut models = if let Some(path) = &cli.tokenizer {
+        vec![ResolvedModel {
+            name: path.display().to_string(),
+            tokenizer: TokenizerSource::local(path.clone()),
+            pin: TokenizerPin::default(),
+            context_length: None,
+            max_output_tokens: None,
+        }]
+    } else {
+        let model_sources = ModelSources::new(cli.models_file.clone());
+        get_repo_ids(&cli.model, cli.all_models, &model_sources)?
+    };
+    if let Some(revision) = &cli.revision {
+        for model in &mut models {
+            model.pin.override_revision(revision);
+        }
     }
 
-    #[test]
-    fn format_number_generates_expected_output_for_valid_input() {
-        // arrange
-        let number = 42;
+    Ok(models)
+}
 
-        // act
-        let outcome = format_number(number);
+/// Runs a subcommand, instead of counting tokens.
+fn run_command(
+    command: &Command,
+    models_file: Option<&PathBuf>,
+    format: OutputFormat,
+    hub: HubOptions,
+) -> miette::Result<()> {
+    match command {
+        Command::Models { command } => run_models_command(command, models_file, format, &hub),
+        Command::Cache { command } => run_cache_command(command, models_file, format, &hub),
+        Command::Serve {
+            port,
+            host,
+            max_tokenizers,
+            preload,
+        } => {
+            let options = ServeOptions {
+                host,
+                port: *port,
+                max_tokenizers: NonZeroUsize::new(usize::from(*max_tokenizers))
+                    .expect("`--max-tokenizers` should be at least one"),
+                preload,
+            };
+            let registry = Registry::load(&ModelSources::new(models_file.cloned()))?;
 
-        // assert
-        assert_eq!(&outcome, "42");
+            run_server(&options, registry, hub)
+        }
+        Command::Mcp { max_tokenizers } => {
+            let registry = Registry::load(&ModelSources::new(models_file.cloned()))?;
+            let capacity = NonZeroUsize::new(usize::from(*max_tokenizers))
+                .expect("`--max-tokenizers` should be at least one");
 
-        // arrange
-        let number = 10_000_000_000;
+            run_mcp_server(CounterCache::new(registry, hub, capacity))
+        }
+        Command::Lsp {
+            model,
+            max_tokens,
+            max_percent,
+        } => run_lsp_server(LspOptions {
+            settings: Settings {
+                model: model.clone(),
+                max_tokens: *max_tokens,
+                max_percent: *max_percent,
+            },
+            models_file: models_file.cloned(),
+            hub,
+        }),
+    }
+}
 
-        // act
-        let outcome = format_number(number);
+/// Counts tokens for the inputs given on the command line and prints the report.
+fn run(cli: &Cli) -> miette::Result<()> {
+    let Cli {
+        model,
+        all_models,
+        baseline,
+        file,
+        inputs,
+        hidden,
+        no_ignore,
+        include,
+        exclude,
+        format,
+        show_tokens,
+        visualise,
+        max_tokens,
+        max_percent,
+        models_file,
+        offline,
+        cache_dir,
+        chat,
+        watch,
+        command,
+        ..
+    } = cli;
 
-        // assert
-        assert_eq!(&outcome, "10,000,000,000");
+    let hub = HubOptions::new(*offline, cache_dir.clone());
+    if let Some(command) = command {
+        return run_command(command, models_file.as_ref(), *format, hub);
+    }
 
-        // arrange
-        let number = 1_000_000;
+    let walk_options = WalkOptions {
+        hidden: *hidden,
+        no_ignore: *no_ignore,
+        include: include.clone(),
+        exclude: exclude.clone(),
+    };
+    let sources = resolve_inputs(file, inputs, &walk_options)?;
+    if (*show_tokens || *visualise) && (sources.len() > 1 || model.len() > 1 || *all_models) {
+        bail!("`--show-tokens` and `--visualise` work with a single prompt and a single model");
+    }
+    if *visualise && *format != OutputFormat::Text {
+        bail!("`--visualise` only supports text output");
+    }
+    let mut models = resolve_models(cli)?;
+    let baseline = match baseline {
+        Some(name) => models
+            .iter()
+            .position(|model| &model.name == name)
+            .ok_or_else(|| miette!("Baseline model `{name}` is not one of the models counted"))?,
+        None => 0,
+    };
+    let budget = Budget {
+        max_tokens: *max_tokens,
+        max_percent: *max_percent,
+    };
 
-        // act
-        let outcome = format_number(number);
+    if let Some(path) = chat {
+        if models.len() > 1 {
+            bail!("`--chat` works with a single model");
+        }
+        detect_context_lengths(&mut models, &hub);
+        return print_chat(cli, path, &models[0], budget, &hub);
+    }
 
-        // assert
-        assert_eq!(&outcome, "1,000,000");
+    if *watch {
+        if *format != OutputFormat::Text {
+            bail!("`--watch` only supports text output");
+        }
+        detect_context_lengths(&mut models, &hub);
+        let options = WatchOptions {
+            files: file,
+            inputs,
+            walk_options: &walk_options,
+            add_special_tokens: !cli.no_special_tokens,
+            budget,
+        };
+        return run_watch(&models, &options, &hub);
+    }
 
-        // arrange
-        let number = 0;
+    let loaded = if let [_, _, ..] = sources.as_slice() {
+        read_sources(&sources, io::stdin().lock())?
+    } else {
+        let source = sources.first().unwrap_or(&PromptSource::Stdin);
+        LoadedSources {
+            inputs: vec![LoadedInput {
+                source,
+                text: read_single_prompt(sources.first())?,
+            }],
+            skipped: Vec::new(),
+        }
+    };
 
-        // act
-        let outcome = format_number(number);
+    detect_context_lengths(&mut models, &hub);
+    if let [model] = models.as_slice() {
+        print_single_model(cli, model, &loaded, budget, &hub)
+    } else {
+        print_comparison(
+            &models,
+            baseline,
+            &loaded,
+            !cli.no_special_tokens,
+            *format,
+            budget,
+            &hub,
+        )
+    }
+}
 
-        // assert
-        assert_eq!(&outcome, "0");
+/// Main function to run the token counting tool.  Errors are printed as a diagnostic on standard
+/// error, and the exit status depends on the kind of error (see [`errors`]).
+fn main() -> ExitCode {
+    let cli = &Cli::parse();
+    dotenv().ok();
+    env_logger::Builder::new()
+        .filter_level(cli.verbose.log_level_filter())
+        .init();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            // The report is already printed for prompts over budget, so skip the JSON error object
+            // to keep standard output parsable
+            let over_budget = matches!(
+                error.downcast_ref::<AppError>(),
+                Some(AppError::BudgetExceeded(_))
+            );
+            if !over_budget && let Some(output) = render_error(&error, cli.format) {
+                print!("{output}");
+            }
+            eprintln!("Error: {error:?}");
+
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn cli_tests() {
+        trycmd::TestCases::new().case("tests/cmd/*.toml");
     }
 }
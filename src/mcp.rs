@@ -0,0 +1,693 @@
+use std::{
+    io::{self, BufRead, Read, Write},
+    sync::Arc,
+};
+
+use miette::{bail, miette};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use tokenator::{CounterCache, TokenCounter};
+
+use crate::{
+    budget::Budget,
+    files::{PromptSource, SourceCounts, WalkOptions, count_sources, read_sources, resolve_inputs},
+    jsonrpc::{
+        INVALID_PARAMS, INVALID_REQUEST, MAX_MESSAGE_BYTES, METHOD_NOT_FOUND, PARSE_ERROR,
+        RpcError, error_response, parse_message, response,
+    },
+    output::{
+        OutputFormat, Report, SCHEMA_VERSION, TokenReport, render_error, render_model_list,
+        render_report, render_tokens,
+    },
+    prompt::{MAX_PROMPT_BYTES, is_stdin_path},
+};
+
+/// MCP protocol versions the server supports, newest first.  Clients asking for any other version
+/// are offered the newest.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Arguments for tools counting prompt text or files.
+#[derive(Deserialize)]
+struct InputArguments {
+    /// Name of the model, as listed by the `list_models` tool.
+    model: String,
+
+    #[serde(default)]
+    text: Option<String>,
+
+    /// Files, directories and glob patterns, relative to the server working directory.
+    #[serde(default)]
+    paths: Vec<String>,
+
+    #[serde(default = "add_special_tokens_default")]
+    add_special_tokens: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenizeArguments {
+    model: String,
+    text: String,
+
+    #[serde(default = "add_special_tokens_default")]
+    add_special_tokens: bool,
+}
+
+#[derive(Deserialize)]
+struct FitArguments {
+    #[serde(flatten)]
+    input: InputArguments,
+
+    #[serde(default)]
+    max_tokens: Option<usize>,
+
+    #[serde(default)]
+    max_percent: Option<f64>,
+}
+
+fn add_special_tokens_default() -> bool {
+    true
+}
+
+/// Returns the `tools/list` result, describing each tool and its arguments.
+fn tool_list() -> Value {
+    let model = json!({
+        "type": "string",
+        "description": "Model name, as listed by the `list_models` tool, such as `gpt-4o`"
+    });
+    let text = json!({
+        "type": "string",
+        "description": "Prompt text"
+    });
+    let paths = json!({
+        "type": "array",
+        "items": { "type": "string" },
+        "description": "Files, directories or glob patterns to count, instead of `text`.  \
+            Directories are walked, respecting `.gitignore` files"
+    });
+    let add_special_tokens = json!({
+        "type": "boolean",
+        "description": "Include the special tokens the tokenizer wraps prompts in",
+        "default": true
+    });
+
+    json!({
+        "tools": [
+            {
+                "name": "count_tokens",
+                "description": "Count the tokens in prompt text, or in files, for a model",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "model": model,
+                        "text": text,
+                        "paths": paths,
+                        "add_special_tokens": add_special_tokens
+                    },
+                    "required": ["model"]
+                }
+            },
+            {
+                "name": "list_models",
+                "description": "List the models tokens can be counted for, with their context \
+                    lengths",
+                "inputSchema": { "type": "object", "properties": {} }
+            },
+            {
+                "name": "fit_to_budget",
+                "description": "Check prompt text, or files, fit within a token budget for a \
+                    model, before adding them to a prompt.  The budget defaults to the model \
+                    context length",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "model": model,
+                        "text": text,
+                        "paths": paths,
+                        "add_special_tokens": add_special_tokens,
+                        "max_tokens": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Most tokens the inputs may use"
+                        },
+                        "max_percent": {
+                            "type": "number",
+                            "exclusiveMinimum": 0,
+                            "description": "Largest share of the model context length the \
+                                inputs may use, as a percentage"
+                        }
+                    },
+                    "required": ["model"]
+                }
+            },
+            {
+                "name": "tokenize",
+                "description": "Split prompt text into tokens, with the ID and text of each",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "model": model,
+                        "text": text,
+                        "add_special_tokens": add_special_tokens
+                    },
+                    "required": ["model", "text"]
+                }
+            }
+        ]
+    })
+}
+
+/// Parses tool arguments, reporting invalid arguments as a JSON-RPC error.
+fn parse_arguments<T: DeserializeOwned>(name: &str, arguments: Value) -> Result<T, RpcError> {
+    serde_json::from_value(arguments).map_err(|error| {
+        RpcError::new(
+            INVALID_PARAMS,
+            format!("Invalid arguments for `{name}`: {error}"),
+        )
+    })
+}
+
+/// Returns the prompt sources for the `text` or `paths` argument.  Standard input carries the
+/// protocol messages, so is not available as an input.
+fn input_sources(arguments: &InputArguments) -> miette::Result<Vec<PromptSource>> {
+    match (&arguments.text, arguments.paths.as_slice()) {
+        (Some(text), []) => {
+            if text.len() > MAX_PROMPT_BYTES {
+                bail!("Prompt context is too large: {}!", text.len());
+            }
+            Ok(vec![PromptSource::Text(text.to_owned())])
+        }
+        (None, paths) if !paths.is_empty() => {
+            if paths.iter().any(is_stdin_path) {
+                bail!(
+                    help = "Pass the prompt in the `text` argument instead",
+                    "Standard input is not available to MCP tools"
+                );
+            }
+            resolve_inputs(paths, &[], &WalkOptions::default())
+        }
+        _ => Err(miette!(
+            help = "Pass the prompt in `text`, or a list of files in `paths`",
+            "Give either `text` or `paths`, but not both"
+        )),
+    }
+}
+
+/// Answers MCP requests, keeping tokenizers loaded between tool calls.
+pub struct McpServer {
+    counters: CounterCache,
+}
+
+impl McpServer {
+    pub fn new(counters: CounterCache) -> Self {
+        Self { counters }
+    }
+
+    /// Loads the counter for `model` and counts the inputs with it.
+    fn count(
+        &self,
+        arguments: &InputArguments,
+    ) -> miette::Result<(Arc<TokenCounter>, SourceCounts)> {
+        let sources = input_sources(arguments)?;
+        let loaded = read_sources(&sources, io::empty())?;
+        let counter = self.counters.get(&arguments.model)?;
+        let counts = count_sources(counter.tokeniser(), &loaded, arguments.add_special_tokens)?;
+
+        Ok((counter, counts))
+    }
+
+    fn count_tokens(&self, arguments: &InputArguments) -> miette::Result<String> {
+        let (counter, counts) = self.count(arguments)?;
+        let model = counter.model();
+        let report = Report {
+            model,
+            revision: model.pin.revision(),
+            counts: &counts,
+        };
+
+        Ok(render_report(&report, OutputFormat::Json))
+    }
+
+    fn tokenize(&self, arguments: &TokenizeArguments) -> miette::Result<String> {
+        if arguments.text.len() > MAX_PROMPT_BYTES {
+            bail!("Prompt context is too large: {}!", arguments.text.len());
+        }
+        let counter = self.counters.get(&arguments.model)?;
+        let tokens = counter.tokenize(&arguments.text, arguments.add_special_tokens)?;
+        let model = counter.model();
+        let report = TokenReport {
+            model,
+            revision: model.pin.revision(),
+            tokens: &tokens,
+        };
+
+        Ok(render_tokens(&report, OutputFormat::Json))
+    }
+
+    /// Counts the inputs and compares the total with the budget, which is the model context
+    /// length unless `max_tokens` or `max_percent` is given.
+    fn fit_to_budget(&self, arguments: &FitArguments) -> miette::Result<String> {
+        if let Some(max_percent) = arguments.max_percent
+            && !(max_percent.is_finite() && max_percent > 0.0)
+        {
+            bail!("`max_percent` must be a percentage greater than zero, not {max_percent}");
+        }
+        let (counter, counts) = self.count(&arguments.input)?;
+        let model = counter.model();
+        let context_length = model.context_length.filter(|length| *length > 0);
+        if arguments.max_percent.is_some() && context_length.is_none() {
+            bail!(
+                help = "Set `max_tokens` instead, or add a `context_length` for the model to the \
+                    models file",
+                "`max_percent` needs the context length for `{}`, which is not known",
+                model.name
+            );
+        }
+        let budget = Budget {
+            max_tokens: arguments.max_tokens,
+            max_percent: arguments.max_percent,
+        }
+        .limit(context_length)
+        .or(context_length)
+        .ok_or_else(|| {
+            miette!(
+                help = "Set `max_tokens`, or add a `context_length` for the model to the models \
+                    file",
+                "No budget to check against, as the context length for `{}` is not known",
+                model.name
+            )
+        })?;
+        let tokens = counts.total_tokens();
+        let as_i64 = |value: usize| i64::try_from(value).unwrap_or(i64::MAX);
+        let inputs: Vec<Value> = counts
+            .counted
+            .iter()
+            .map(|count| json!({ "input": count.label(), "tokens": count.tokens }))
+            .collect();
+        let skipped: Vec<String> = counts
+            .skipped
+            .iter()
+            .map(|skipped| skipped.path.display().to_string())
+            .collect();
+        let mut output = serde_json::to_string_pretty(&json!({
+            "schema_version": SCHEMA_VERSION,
+            "model": model.name,
+            "fits": tokens <= budget,
+            "tokens": tokens,
+            "budget": budget,
+            "headroom": as_i64(budget) - as_i64(tokens),
+            "context_length": model.context_length,
+            "inputs": inputs,
+            "skipped": skipped,
+        }))
+        .expect("budget result should serialise to JSON");
+        output.push('\n');
+
+        Ok(output)
+    }
+
+    /// Runs a tool.  Tool failures, such as an unknown model, are returned as an error result, so
+    /// the client can show them to the model, while unknown tools and invalid arguments are
+    /// protocol errors.
+    fn call_tool(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing tool `name`"))?
+            .to_owned();
+        let arguments = params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        let outcome = match name.as_str() {
+            "count_tokens" => self.count_tokens(&parse_arguments(&name, arguments)?),
+            "list_models" => Ok(render_model_list(
+                &self.counters.registry().models(),
+                OutputFormat::Json,
+            )),
+            "fit_to_budget" => self.fit_to_budget(&parse_arguments(&name, arguments)?),
+            "tokenize" => self.tokenize(&parse_arguments(&name, arguments)?),
+            _ => {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("Unknown tool: `{name}`"),
+                ));
+            }
+        };
+        let (text, is_error) = match outcome {
+            Ok(text) => (text, false),
+            Err(error) => (
+                render_error(&error, OutputFormat::Json).expect("JSON errors should render"),
+                true,
+            ),
+        };
+
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+
+    fn dispatch(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => {
+                let requested = params.get("protocolVersion").and_then(Value::as_str);
+                let version = PROTOCOL_VERSIONS
+                    .into_iter()
+                    .find(|version| Some(*version) == requested)
+                    .unwrap_or(PROTOCOL_VERSIONS[0]);
+
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                    "instructions": "Count tokens for prompt text and files with model \
+                        tokenizers.  Call `fit_to_budget` before adding files to a prompt, to \
+                        check they fit in the context window.",
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(tool_list()),
+            "tools/call" => self.call_tool(params),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: `{method}`"),
+            )),
+        }
+    }
+
+    /// Handles a single line of JSON-RPC, returning the response, or [`None`] for notifications
+    /// and responses, which get no reply.
+    fn handle(&self, line: &str) -> Option<Value> {
+        let message = match parse_message(line) {
+            Ok(message) => message?,
+            Err(response) => return Some(response),
+        };
+        log::debug!("Received `{}`", message.method);
+        let outcome = self.dispatch(&message.method, &message.params);
+
+        Some(response(&message.id?, outcome))
+    }
+
+    /// Reads newline-delimited JSON-RPC messages from `input` until it closes, writing each
+    /// response to `output` as a single line.  Messages over [`MAX_MESSAGE_BYTES`] are skipped,
+    /// without buffering them, and answered with an error.
+    ///
+    /// # Errors
+    /// Errors if unable to read a message or write a response.
+    pub fn serve<R: BufRead, W: Write>(&self, mut input: R, mut output: W) -> io::Result<()> {
+        let limit = u64::try_from(MAX_MESSAGE_BYTES + 1).unwrap_or(u64::MAX);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if (&mut input).take(limit).read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            // A full line of the largest size ends in a newline
+            let response = if line.len() > MAX_MESSAGE_BYTES && line.last() != Some(&b'\n') {
+                skip_line(&mut input)?;
+                Some(error_response(
+                    &Value::Null,
+                    &RpcError::new(
+                        INVALID_REQUEST,
+                        format!("Message is over the {MAX_MESSAGE_BYTES} byte limit"),
+                    ),
+                ))
+            } else {
+                match str::from_utf8(&line) {
+                    Ok(line) if line.trim().is_empty() => continue,
+                    Ok(line) => self.handle(line),
+                    Err(error) => Some(error_response(
+                        &Value::Null,
+                        &RpcError::new(PARSE_ERROR, format!("Parse error: {error}")),
+                    )),
+                }
+            };
+            if let Some(response) = response {
+                writeln!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+    }
+}
+
+/// Discards the rest of the current line of `input`, without buffering it.
+fn skip_line<R: BufRead>(input: &mut R) -> io::Result<()> {
+    loop {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        if let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            input.consume(end + 1);
+            return Ok(());
+        }
+        let length = buffer.len();
+        input.consume(length);
+    }
+}
+
+/// Runs the MCP server on standard input and output until the client closes standard input.
+/// Logs go to standard error, so they do not mix with protocol messages.
+///
+/// # Errors
+/// Errors if unable to read from standard input or write to standard output.
+pub fn run_mcp_server(counters: CounterCache) -> miette::Result<()> {
+    log::info!("Starting the MCP server on standard input and output");
+    McpServer::new(counters)
+        .serve(io::stdin().lock(), io::stdout().lock())
+        .map_err(|error| miette!("MCP transport failed: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use serde_json::{Value, json};
+    use tokenator::{CounterCache, HubOptions, ModelSources, Registry};
+
+    use crate::{jsonrpc::MAX_MESSAGE_BYTES, mcp::McpServer};
+
+    fn get_server_fixture() -> McpServer {
+        let registry = Registry::load(&ModelSources {
+            models_file: Some("tests/fixtures/local-models.json".into()),
+            ..ModelSources::default()
+        })
+        .unwrap();
+
+        McpServer::new(CounterCache::new(
+            registry,
+            HubOptions::default(),
+            NonZeroUsize::new(2).unwrap(),
+        ))
+    }
+
+    fn call_tool(server: &McpServer, name: &str, arguments: &Value) -> Value {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        });
+
+        server.handle(&request.to_string()).unwrap()["result"].clone()
+    }
+
+    fn tool_output(result: &Value) -> Value {
+        serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn serve_answers_requests_and_skips_notifications() {
+        // arrange
+        let server = get_server_fixture();
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26","capabilities":{},"clientInfo":{"name":"test","version":"1"}}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            "",
+            r#"{"jsonrpc":"2.0","id":"two","method":"tools/list"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+
+        // act
+        server.serve(input.as_bytes(), &mut output).unwrap();
+
+        // assert
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "tokenator");
+        assert_eq!(responses[1]["id"], "two");
+        let tools: Vec<&str> = responses[1]["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            tools,
+            ["count_tokens", "list_models", "fit_to_budget", "tokenize"]
+        );
+    }
+
+    #[test]
+    fn serve_answers_invalid_utf8_with_a_parse_error() {
+        // arrange
+        let server = get_server_fixture();
+        let mut input = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"\xff\"}\n".to_vec();
+        input.extend_from_slice(b"{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"ping\"}\n");
+        let mut output = Vec::new();
+
+        // act
+        server.serve(input.as_slice(), &mut output).unwrap();
+
+        // assert
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            responses,
+            [
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {
+                        "code": -32_700,
+                        "message": "Parse error: invalid utf-8 sequence of 1 bytes from index 34",
+                    },
+                }),
+                json!({ "jsonrpc": "2.0", "id": 2, "result": {} }),
+            ]
+        );
+    }
+
+    #[test]
+    fn serve_skips_oversized_messages_with_an_error() {
+        // arrange
+        let server = get_server_fixture();
+        let input = format!(
+            "{}\n{}\n",
+            "x".repeat(MAX_MESSAGE_BYTES + 10),
+            r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#
+        );
+        let mut output = Vec::new();
+
+        // act
+        server.serve(input.as_bytes(), &mut output).unwrap();
+
+        // assert
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            responses,
+            [
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {
+                        "code": -32_600,
+                        "message": format!("Message is over the {MAX_MESSAGE_BYTES} byte limit"),
+                    },
+                }),
+                json!({ "jsonrpc": "2.0", "id": 2, "result": {} }),
+            ]
+        );
+    }
+
+    #[test]
+    fn tools_count_and_check_budgets_for_text_and_files() {
+        // arrange
+        let server = get_server_fixture();
+
+        // act
+        let count = call_tool(
+            &server,
+            "count_tokens",
+            &json!({ "model": "local", "text": "Why is the sky blue?" }),
+        );
+        let files = call_tool(
+            &server,
+            "count_tokens",
+            &json!({ "model": "local", "paths": ["tests/fixtures/messages.json"] }),
+        );
+        let fits = call_tool(
+            &server,
+            "fit_to_budget",
+            &json!({ "model": "local", "text": "Why is the sky blue?" }),
+        );
+        let over = call_tool(
+            &server,
+            "fit_to_budget",
+            &json!({ "model": "local", "text": "Why is the sky blue?", "max_tokens": 2 }),
+        );
+
+        // assert
+        assert_eq!(count["isError"], false);
+        let tokens = &tool_output(&count)["total"]["tokens"];
+        assert!(tokens.as_u64().unwrap() > 0);
+        assert_eq!(
+            tool_output(&files)["inputs"][0]["path"],
+            "tests/fixtures/messages.json"
+        );
+        let fits = tool_output(&fits);
+        assert_eq!(fits["fits"], true);
+        assert_eq!(&fits["tokens"], tokens);
+        assert_eq!(fits["budget"], 64);
+        let over = tool_output(&over);
+        assert_eq!(over["fits"], false);
+        assert_eq!(over["headroom"], 2 - over["tokens"].as_i64().unwrap());
+    }
+
+    #[test]
+    fn errors_are_reported_as_tool_or_protocol_errors() {
+        // arrange
+        let server = get_server_fixture();
+
+        // act
+        let unknown_model = call_tool(
+            &server,
+            "tokenize",
+            &json!({ "model": "locale", "text": "Why is the sky blue?" }),
+        );
+        let both_inputs = call_tool(
+            &server,
+            "count_tokens",
+            &json!({ "model": "local", "text": "Why?", "paths": ["-"] }),
+        );
+        let outcome = [
+            "not json",
+            r#"{"jsonrpc":"1.0","id":1,"method":"ping"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"resources/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"count"}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"tokenize","arguments":{}}}"#,
+        ]
+        .map(|line| server.handle(line).unwrap()["error"]["code"].clone());
+
+        // assert
+        assert_eq!(unknown_model["isError"], true);
+        assert_eq!(
+            tool_output(&unknown_model)["error"]["message"],
+            "No model matching `locale`, did you mean `local`?"
+        );
+        assert_eq!(both_inputs["isError"], true);
+        assert_eq!(outcome, [-32_700, -32_600, -32_601, -32_602, -32_602]);
+        assert!(
+            server
+                .handle(r#"{"jsonrpc":"2.0","method":"notifications/cancelled"}"#)
+                .is_none()
+        );
+    }
+}
@@ -1,42 +1,57 @@
-use std::{
-    collections::HashMap,
-    fs,
-    path::{Path, PathBuf},
-};
+use std::collections::HashMap;
 
-use inquire::Select;
-use miette::{Context, IntoDiagnostic, bail, miette};
 use strsim::normalized_damerau_levenshtein;
 
-#[derive(serde::Deserialize)]
-struct Model {
-    name: String,
-    hf: String,
+use crate::{
+    errors::AppError,
+    registry::ModelEntry,
+    token::{TokenizerPin, TokenizerSource},
+};
+
+/// A model name from the models file, along with the Hugging Face repo it maps to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedModel {
+    pub name: String,
+
+    /// Hugging Face repo or local file the tokenizer is loaded from.
+    pub tokenizer: TokenizerSource,
+
+    /// Repo revision and expected hash of the tokenizer file.
+    pub pin: TokenizerPin,
+
+    /// Maximum number of tokens the model accepts, including the prompt and any output.
+    pub context_length: Option<usize>,
+
+    /// Maximum number of tokens the model generates in a single response.
+    pub max_output_tokens: Option<usize>,
 }
 
-/// Loads the model name map from the JSON file (`data/models.json`) and returns it as a `HashMap`.
-///
-/// # Errors
-/// Errors if:
-/// - unable to read the file; or
-/// - unable to parse the JSON content.
-fn load_model_name_map<P: AsRef<Path>>(
-    path: P,
-) -> miette::Result<HashMap<String, String, ahash::RandomState>> {
-    let data = fs::read_to_string(&path)
-        .into_diagnostic()
-        .wrap_err("Reading models JSON file")?;
-    let models: Vec<Model> = serde_json::from_str(&data)
-        .into_diagnostic()
-        .wrap_err("Parsing models JSON file")?;
-    if models.is_empty() {
-        log::warn!("Models file `{}` is empty", path.as_ref().display());
+impl ResolvedModel {
+    /// Returns the Hugging Face repo ID, or [`None`] for local tokenizers and tiktoken encodings.
+    #[must_use]
+    pub fn repo_id(&self) -> Option<&str> {
+        match &self.tokenizer {
+            TokenizerSource::Hub(repo_id) => Some(repo_id),
+            TokenizerSource::Path(_) | TokenizerSource::Tiktoken(_) => None,
+        }
     }
+}
 
-    Ok(models
-        .into_iter()
-        .map(|model| (model.name, model.hf))
-        .collect())
+impl TryFrom<&ModelEntry> for ResolvedModel {
+    type Error = AppError;
+
+    fn try_from(value: &ModelEntry) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: value.name.clone(),
+            tokenizer: value.tokenizer_source()?,
+            pin: TokenizerPin {
+                revision: value.revision.clone(),
+                sha256: value.sha256.clone(),
+            },
+            context_length: value.context_length,
+            max_output_tokens: value.max_output_tokens,
+        })
+    }
 }
 
 /// Suggests a model name based on the input name.  Useful if the input name does not match any
@@ -45,8 +60,12 @@ fn load_model_name_map<P: AsRef<Path>>(
 ///
 /// # Returns
 /// An `Option` containing the suggested model name or `None` if no suggestion is found.
-fn model_name_suggestion<'a>(
-    model_name_map: &'a HashMap<String, String, ahash::RandomState>,
+///
+/// # Panics
+/// Panics if a name distance is `NaN`, which `strsim` never returns.
+#[must_use]
+pub fn model_name_suggestion<'a>(
+    model_name_map: &'a HashMap<String, ModelEntry, ahash::RandomState>,
     input_name: &str,
 ) -> Option<&'a str> {
     model_name_map
@@ -63,177 +82,36 @@ fn model_name_suggestion<'a>(
         .map(|(suggestion_key, _suggestion_hf)| suggestion_key.as_str())
 }
 
-/// Prompts the user to select a model name from a list.
-///
-/// # Returns
-/// A `miette::Result` containing the user-selected model name.
-fn get_user_selected_repo_id(
-    model_name_map: &HashMap<String, String, ahash::RandomState>,
-) -> miette::Result<String> {
-    debug_assert!(!model_name_map.is_empty());
-    let mut options: Vec<&String> = model_name_map.keys().collect();
-    options.sort();
-
-    let choice = Select::new("Which model are you using?", options)
-        .prompt()
-        .into_diagnostic()
-        .wrap_err("Getting user model choice")?;
-
-    Ok(model_name_map
-        .get(choice)
-        .expect("Choice should be in the model name map")
-        .to_owned())
-}
-
-/// Retrieves the repository ID based on the model name.
-///
-/// # Returns
-/// A `miette::Result` containing the repository ID.  Makes a suggestion if the given model name
-/// does not exist.
-///
-/// # Errors
-/// Errors if `model_name` does not match any existing models.
-pub fn get_repo_id(
-    model_name: Option<&String>,
-    model_map_path: Option<PathBuf>,
-) -> miette::Result<String> {
-    let model_map_path = model_map_path
-        .unwrap_or(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("data/models.json"));
-    let model_name_map = load_model_name_map(&model_map_path)?;
-    if model_name_map.is_empty() {
-        bail!(
-            "Error: no models in model file `{}`",
-            model_map_path.display()
-        );
-    }
-    match model_name {
-        Some(value) => {
-            if let Some(repo_id) = model_name_map.get(value) {
-                // Successfully matched user model name to a known repo
-                Ok(repo_id.to_owned())
-            } else {
-                // Unable to match user-provided model name to a known repo
-                let error_message = match model_name_suggestion(&model_name_map, value) {
-                    Some(suggestion) => {
-                        format!("No model matching `{value}`, did you mean `{suggestion}`?")
-                    }
-                    // No suggestion available
-                    None => format!("No model matching `{value}`."),
-                };
-                Err(miette!(error_message))
-            }
-        }
-        // No model name provided; prompt the user for model name
-        None => get_user_selected_repo_id(&model_name_map),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use assert_fs::{
-        TempDir,
-        prelude::{FileWriteStr, PathChild},
-    };
-
-    use crate::models::{get_repo_id, load_model_name_map, model_name_suggestion};
-
-    #[test]
-    fn load_model_name_map_generates_expected_output_from_valid_input() {
-        // arrange
-        let content = r#"[
-  {
-    "name": "example:latest",
-    "hf": "example/Example-1-M-state-of-the-art"
-  },
-  {
-    "name": "example:100b",
-    "hf": "example/Example-100-B-most-capable"
-  }
-]
-"#;
-        let temp_dir = TempDir::new().unwrap();
-        let _ = temp_dir.child("models.json").write_str(content);
-        let temp_data_path = temp_dir.join("models.json");
-
-        // act
-        let outcome = load_model_name_map(temp_data_path).unwrap();
-
-        // assert
-        insta::assert_json_snapshot!(outcome, { "." => insta::sorted_redaction() });
-    }
-
-    #[test]
-    fn load_model_name_map_returns_error_if_json_is_not_valid() {
-        // arrange
-        let content = r#"[
-  {
-    "name": "example:latest",
-    "hf": "example/Example-1-M-state-of-the-art"
-  },
-  {
-    "name": "example:100b",
-    "hugging face": "example/Example-100-B-most-capable"
-  }
-]
-"#;
-        let temp_dir = TempDir::new().unwrap();
-        let _ = temp_dir.child("models.json").write_str(content);
-        let temp_data_path = temp_dir.join("models.json");
-
-        // act
-        let outcome = load_model_name_map(temp_data_path).unwrap_err();
-
-        // assert
-        let mut chain = outcome.chain();
-        assert_eq!(
-            chain.next().map(|val| format!("{val}")),
-            Some("Parsing models JSON file".to_owned())
-        );
-        assert_eq!(
-            chain.next().map(|val| format!("{val}")),
-            Some("missing field `hf` at line 9 column 3".to_owned())
-        );
-        assert!(chain.next().is_none());
-
-        // cleanup
-        temp_dir.close().unwrap();
-    }
-
-    #[test]
-    fn load_model_name_map_handles_empty_input() {
-        // arrange
-        let content = "[ ]";
-        let temp_dir = TempDir::new().unwrap();
-        let _ = temp_dir.child("models.json").write_str(content);
-        let temp_data_path = temp_dir.join("models.json");
-
-        // act
-        let outcome = load_model_name_map(temp_data_path).unwrap();
-
-        // assert
-        assert!(outcome.is_empty());
-    }
-
-    fn get_model_name_map_fixture() -> HashMap<String, String, ahash::RandomState> {
-        let hasher = ahash::RandomState::new();
-        let mut model_name_map: HashMap<String, String, ahash::RandomState> =
-            HashMap::with_hasher(hasher);
-        model_name_map.insert(
-            "example-model".to_owned(),
-            "example/Example-Model".to_owned(),
-        );
-        model_name_map.insert(
-            "nothing-to-do-with-the-other-one".to_owned(),
-            "example/TheOtherExample".to_owned(),
-        );
-        model_name_map.insert(
-            "example-model:7b".to_owned(),
-            "example/Example-7-B".to_owned(),
-        );
-
-        model_name_map
+    use crate::{models::model_name_suggestion, registry::ModelEntry};
+
+    fn get_model_name_map_fixture() -> HashMap<String, ModelEntry, ahash::RandomState> {
+        [
+            ("example-model", "example/Example-Model"),
+            (
+                "nothing-to-do-with-the-other-one",
+                "example/TheOtherExample",
+            ),
+            ("example-model:7b", "example/Example-7-B"),
+        ]
+        .into_iter()
+        .map(|(name, hf)| {
+            let model = ModelEntry {
+                name: name.to_owned(),
+                hf: Some(hf.to_owned()),
+                path: None,
+                tiktoken: None,
+                revision: None,
+                sha256: None,
+                context_length: None,
+                max_output_tokens: None,
+            };
+            (name.to_owned(), model)
+        })
+        .collect()
     }
 
     #[test]
@@ -248,94 +126,4 @@ mod tests {
         // assert
         assert_eq!(outcome, "example-model");
     }
-
-    #[test]
-    fn get_repo_id_generates_expected_result_with_valid_input() {
-        // arrange
-        let content = r#"[
-  {
-    "name": "example-model",
-    "hf": "example/Example-Model"
-  },
-  {
-    "name": "nothing-to-do-with-the-other-one",
-    "hf": "example/TheOtherExample"
-  }
-]
-"#;
-        let temp_dir = TempDir::new().unwrap();
-        let _ = temp_dir.child("models.json").write_str(content);
-        let temp_data_path = temp_dir.join("models.json");
-
-        // act
-        let outcome =
-            get_repo_id(Some(&String::from("example-model")), Some(temp_data_path)).unwrap();
-
-        // assert
-        assert_eq!(outcome, "example/Example-Model");
-    }
-
-    #[test]
-    fn get_repo_id_generates_expected_error_with_invalid_input() {
-        // arrange
-        let content = r#"[
-  {
-    "name": "example-model",
-    "hf": "example/Example-Model"
-  },
-  {
-    "name": "nothing-to-do-with-the-other-one",
-    "hf": "example/TheOtherExample"
-  }
-]
-"#;
-        let temp_dir = TempDir::new().unwrap();
-        let _ = temp_dir.child("models.json").write_str(content);
-        let temp_data_path = temp_dir.join("models.json");
-
-        // act
-        let outcome =
-            get_repo_id(Some(&String::from("example-modal")), Some(temp_data_path)).unwrap_err();
-
-        // assert
-        let mut chain = outcome.chain();
-        assert_eq!(
-            chain.next().map(|val| format!("{val}")),
-            Some("No model matching `example-modal`, did you mean `example-model`?".to_owned())
-        );
-        assert!(chain.next().is_none());
-
-        // cleanup
-        temp_dir.close().unwrap();
-    }
-
-    #[test]
-    fn get_repo_id_generates_expected_error_with_empty_model_name_map() {
-        // arrange
-        let content = "[ ]";
-        let temp_dir = TempDir::new().unwrap();
-        let _ = temp_dir.child("models.json").write_str(content);
-        let temp_data_path = temp_dir.join("models.json");
-
-        // act
-        let outcome = get_repo_id(
-            Some(&String::from("example-modal")),
-            Some(temp_data_path.clone()),
-        )
-        .unwrap_err();
-
-        // assert
-        let mut chain = outcome.chain();
-        assert_eq!(
-            chain.next().map(|val| format!("{val}")),
-            Some(format!(
-                "Error: no models in model file `{}`",
-                temp_data_path.display()
-            ))
-        );
-        assert!(chain.next().is_none());
-
-        // cleanup
-        temp_dir.close().unwrap();
-    }
 }
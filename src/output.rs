@@ -0,0 +1,1995 @@
+use std::{collections::HashMap, path::Path};
+
+use num_format::Locale;
+use serde::Serialize;
+use tokenator::{
+    chat::ChatCounts,
+    models::ResolvedModel,
+    registry::{Layer, Problem, RegisteredModel},
+    token::{DEFAULT_REVISION, TokenInfo, TokenizerSource},
+};
+
+use crate::{
+    cache::CachedTokenizer,
+    files::{InputCount, SourceCounts, SourceKind},
+};
+
+/// Version of the JSON output schema.  Only bumped for breaking changes; adding new fields is not
+/// considered breaking.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Format for results printed to standard output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+
+    /// A single JSON object, following the documented schema
+    Json,
+
+    /// Comma-separated values, with a header row and one row per input
+    Csv,
+
+    /// Newline-delimited JSON, with one object per input
+    Ndjson,
+}
+
+/// Results of a token counting run, ready to render.
+pub struct Report<'a> {
+    pub model: &'a ResolvedModel,
+    pub revision: &'a str,
+    pub counts: &'a SourceCounts,
+}
+
+/// Tokens for a single prompt, ready to render.
+pub struct TokenReport<'a> {
+    pub model: &'a ResolvedModel,
+    pub revision: &'a str,
+    pub tokens: &'a [TokenInfo],
+}
+
+/// Token counts for a conversation, ready to render.
+pub struct ChatReport<'a> {
+    pub model: &'a ResolvedModel,
+    pub revision: &'a str,
+    pub counts: &'a ChatCounts,
+}
+
+/// A model which could not be loaded or counted with, when comparing several models.
+pub struct FailedModel<'a> {
+    pub model: &'a ResolvedModel,
+    pub reason: String,
+}
+
+/// Token counts for a single model in a `--watch` run, with the counts from the previous run, to
+/// show what changed.
+pub struct WatchReport<'a> {
+    pub model: &'a ResolvedModel,
+    pub counts: &'a SourceCounts,
+
+    /// Counts from the previous run, or [`None`] for the first run.
+    pub previous: Option<&'a SourceCounts>,
+}
+
+/// Results of counting the same inputs with several models, ready to render.
+pub struct Comparison<'a> {
+    /// Reports for each model which was counted with, in the order given.
+    pub reports: Vec<Report<'a>>,
+
+    /// Index of the report other models are compared to.
+    pub baseline: usize,
+
+    /// Models which could not be counted with.
+    pub failed: Vec<FailedModel<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonModel<'a> {
+    name: &'a str,
+    repo_id: Option<&'a str>,
+    tokenizer_path: Option<String>,
+    tiktoken: Option<&'a str>,
+    revision: Option<&'a str>,
+    context_length: Option<usize>,
+    max_output_tokens: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct JsonInput {
+    source: &'static str,
+    path: Option<String>,
+    tokens: usize,
+    special_tokens: usize,
+    content_tokens: usize,
+    chars: usize,
+    bytes: usize,
+}
+
+#[derive(Serialize)]
+struct JsonSkipped<'a> {
+    path: String,
+    reason: &'a str,
+}
+
+#[derive(Default, Serialize)]
+struct JsonTotal {
+    inputs: usize,
+    tokens: usize,
+    special_tokens: usize,
+    content_tokens: usize,
+    chars: usize,
+    bytes: usize,
+    context_percent: Option<f64>,
+    headroom: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u32>,
+    model: JsonModel<'a>,
+    inputs: Vec<JsonInput>,
+    skipped: Vec<JsonSkipped<'a>>,
+    total: JsonTotal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    difference: Option<JsonDifference>,
+}
+
+#[derive(Serialize)]
+struct JsonDifference {
+    tokens: i64,
+    percent: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct JsonFailedModel<'a> {
+    name: &'a str,
+    repo_id: Option<&'a str>,
+    reason: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonComparison<'a> {
+    schema_version: u32,
+    baseline: &'a str,
+    models: Vec<JsonReport<'a>>,
+    failed: Vec<JsonFailedModel<'a>>,
+}
+
+/// A single input, flattened with the model details for row-based formats (CSV and NDJSON).
+#[derive(Serialize)]
+struct Row<'a> {
+    source: &'static str,
+    path: Option<String>,
+    model: &'a str,
+    repo_id: Option<&'a str>,
+    revision: Option<&'a str>,
+    tokens: usize,
+    chars: usize,
+    bytes: usize,
+    tokens_per_char: Option<f64>,
+    context_length: Option<usize>,
+    context_percent: Option<f64>,
+    headroom: Option<i64>,
+    tokenizer_path: Option<String>,
+    tiktoken: Option<&'a str>,
+    special_tokens: usize,
+    content_tokens: usize,
+}
+
+#[derive(Serialize)]
+struct JsonToken<'a> {
+    index: usize,
+    id: u32,
+    token: &'a str,
+    text: &'a str,
+    bytes: (usize, usize),
+    chars: (usize, usize),
+    special: bool,
+}
+
+#[derive(Serialize)]
+struct JsonTokenReport<'a> {
+    schema_version: u32,
+    model: JsonModel<'a>,
+    tokens: Vec<JsonToken<'a>>,
+}
+
+/// A single token, flattened for row-based formats (CSV and NDJSON).
+#[derive(Serialize)]
+struct TokenRow<'a> {
+    index: usize,
+    id: u32,
+    token: &'a str,
+    text: &'a str,
+    byte_start: usize,
+    byte_end: usize,
+    char_start: usize,
+    char_end: usize,
+    special: bool,
+}
+
+#[derive(Serialize)]
+struct JsonMessage<'a> {
+    index: usize,
+    role: &'a str,
+    tokens: usize,
+}
+
+#[derive(Serialize)]
+struct JsonChatTotal {
+    messages: usize,
+    tokens: usize,
+    chars: usize,
+    bytes: usize,
+    context_percent: Option<f64>,
+    headroom: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct JsonChatReport<'a> {
+    schema_version: u32,
+    model: JsonModel<'a>,
+    messages: Option<Vec<JsonMessage<'a>>>,
+    generation_prompt: Option<usize>,
+    total: JsonChatTotal,
+}
+
+/// A single message or the generation prompt, or the whole conversation when there is no
+/// per-message breakdown, flattened for row-based formats (CSV and NDJSON).
+#[derive(Serialize)]
+struct ChatRow<'a> {
+    part: &'static str,
+    index: Option<usize>,
+    role: Option<&'a str>,
+    model: &'a str,
+    repo_id: Option<&'a str>,
+    revision: Option<&'a str>,
+    tokens: usize,
+    context_length: Option<usize>,
+    tokenizer_path: Option<String>,
+    tiktoken: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonErrorDetail {
+    message: String,
+    causes: Vec<String>,
+    help: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonError {
+    schema_version: u32,
+    error: JsonErrorDetail,
+}
+
+/// A model in the registry, flattened for listings.
+#[derive(Serialize)]
+struct ModelRow<'a> {
+    name: &'a str,
+    repo_id: Option<&'a str>,
+    tokenizer_path: Option<String>,
+    context_length: Option<usize>,
+    max_output_tokens: Option<usize>,
+    layer: Layer,
+    path: Option<String>,
+    revision: Option<&'a str>,
+    sha256: Option<&'a str>,
+    tiktoken: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonModelList<'a> {
+    schema_version: u32,
+    models: Vec<ModelRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonModelDetails<'a> {
+    schema_version: u32,
+    model: ModelRow<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonProblem<'a> {
+    name: &'a str,
+    source: &'a str,
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonValidation<'a> {
+    schema_version: u32,
+    checked: usize,
+    problems: Vec<JsonProblem<'a>>,
+}
+
+/// A registry model, with its cached tokenizer, if any, ready to render.
+pub struct CacheEntry<'a> {
+    pub name: &'a str,
+    pub repo_id: String,
+    pub revision: &'a str,
+    pub cached: Option<CachedTokenizer>,
+}
+
+/// A registry model and its cached tokenizer, flattened for listings.
+#[derive(Serialize)]
+struct CacheRow<'a> {
+    name: &'a str,
+    repo_id: &'a str,
+    revision: &'a str,
+    cached: bool,
+    commit: Option<&'a str>,
+    size: Option<u64>,
+    path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonCacheList<'a> {
+    schema_version: u32,
+    cache_dir: &'a str,
+    models: Vec<CacheRow<'a>>,
+}
+
+/// Share of a model context window taken up by a prompt.
+#[derive(Clone, Copy)]
+struct ContextUsage {
+    /// Percentage of the context window, rounded to two decimal places.
+    percent: f64,
+
+    /// Tokens left in the context window, which is negative when the prompt does not fit.
+    headroom: i64,
+}
+
+/// Returns the local tokenizer path, or [`None`] for tokenizers from Hugging Face repos.
+fn tokenizer_path(model: &ResolvedModel) -> Option<String> {
+    match &model.tokenizer {
+        TokenizerSource::Hub(_) | TokenizerSource::Tiktoken(_) => None,
+        TokenizerSource::Path(path) => Some(path.display().to_string()),
+    }
+}
+
+/// Returns the tiktoken encoding or rank file, or [`None`] for other tokenizers.
+fn tiktoken_encoding(model: &ResolvedModel) -> Option<&str> {
+    match &model.tokenizer {
+        TokenizerSource::Tiktoken(encoding) => Some(encoding),
+        TokenizerSource::Hub(_) | TokenizerSource::Path(_) => None,
+    }
+}
+
+impl<'a> JsonModel<'a> {
+    fn new(model: &'a ResolvedModel, revision: &'a str) -> Self {
+        Self {
+            name: &model.name,
+            repo_id: model.repo_id(),
+            tokenizer_path: tokenizer_path(model),
+            tiktoken: tiktoken_encoding(model),
+            revision: model.repo_id().map(|_| revision),
+            context_length: model.context_length,
+            max_output_tokens: model.max_output_tokens,
+        }
+    }
+}
+
+/// Returns the schema name for the kind of input.
+fn source_name(kind: SourceKind) -> &'static str {
+    match kind {
+        SourceKind::Text => "text",
+        SourceKind::Stdin => "stdin",
+        SourceKind::File => "file",
+    }
+}
+
+impl From<&InputCount> for JsonInput {
+    fn from(value: &InputCount) -> Self {
+        Self {
+            source: source_name(value.kind),
+            path: value.path.as_ref().map(|path| path.display().to_string()),
+            tokens: value.tokens,
+            special_tokens: value.special_tokens,
+            content_tokens: value.content_tokens(),
+            chars: value.chars,
+            bytes: value.bytes,
+        }
+    }
+}
+
+/// Formats a number with thousands separators, using the `en` locale.
+pub fn format_number(number: usize) -> String {
+    let mut buf = num_format::Buffer::default();
+    buf.write_formatted(&number, &Locale::en);
+
+    buf.as_str().to_owned()
+}
+
+/// Converts a token, character or context count to a float, for working out shares and rates.
+#[allow(clippy::cast_precision_loss)]
+pub fn as_f64(count: usize) -> f64 {
+    // Precision loss only affects counts above 2^52, far beyond the maximum prompt size
+    count as f64
+}
+
+/// Sums counts across all inputs.
+fn total(counts: &SourceCounts) -> JsonTotal {
+    counts
+        .counted
+        .iter()
+        .fold(JsonTotal::default(), |total, count| JsonTotal {
+            inputs: total.inputs + 1,
+            tokens: total.tokens + count.tokens,
+            special_tokens: total.special_tokens + count.special_tokens,
+            content_tokens: total.content_tokens + count.content_tokens(),
+            chars: total.chars + count.chars,
+            bytes: total.bytes + count.bytes,
+            ..JsonTotal::default()
+        })
+}
+
+/// Returns how much of the model context window `tokens` takes up, or [`None`] when the context
+/// length is not known.
+fn context_usage(tokens: usize, model: &ResolvedModel) -> Option<ContextUsage> {
+    let context_length = model.context_length.filter(|length| *length > 0)?;
+    let as_i64 = |value: usize| i64::try_from(value).unwrap_or(i64::MAX);
+
+    Some(ContextUsage {
+        percent: (as_f64(tokens) / as_f64(context_length) * 10_000.0).round() / 100.0,
+        headroom: as_i64(context_length) - as_i64(tokens),
+    })
+}
+
+/// Describes the share of the model context window `tokens` takes up, for example
+/// `3.77% of 32,768`.
+pub fn context_share(tokens: usize, model: &ResolvedModel) -> Option<String> {
+    let context_length = model.context_length?;
+    let ContextUsage { percent, .. } = context_usage(tokens, model)?;
+
+    Some(format!(
+        "{percent:.2}% of {}",
+        format_number(context_length)
+    ))
+}
+
+/// Describes how much of the model context window `tokens` takes up, along with the remaining
+/// headroom, for example `3.77% of 32,768 context, 31,534 tokens of headroom`.
+fn describe_context(tokens: usize, model: &ResolvedModel) -> Option<String> {
+    let share = context_share(tokens, model)?;
+    let context_length = model.context_length?;
+    let headroom = match context_length.checked_sub(tokens) {
+        Some(headroom) => format!("{} tokens of headroom", format_number(headroom)),
+        None => format!(
+            "exceeds the context by {} tokens",
+            format_number(tokens - context_length)
+        ),
+    };
+
+    Some(format!("{share} context, {headroom}"))
+}
+
+/// Describes how many of `tokens` are content and special tokens, for example
+/// `6 content + 1 special`, or [`None`] when there are no special tokens.
+fn describe_special(tokens: usize, special: usize) -> Option<String> {
+    (special > 0).then(|| {
+        format!(
+            "{} content + {} special",
+            format_number(tokens - special),
+            format_number(special)
+        )
+    })
+}
+
+/// Returns the one-line token count summary for a single prompt, including how many tokens are
+/// special tokens, when there are any, and the share of the model context window used, when the
+/// context length is known.
+pub fn prompt_token_count(tokens: usize, special: usize, model: &ResolvedModel) -> String {
+    let details: Vec<String> = [
+        describe_special(tokens, special),
+        describe_context(tokens, model),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if details.is_empty() {
+        format!("Prompt token count: {}", format_number(tokens))
+    } else {
+        format!(
+            "Prompt token count: {} ({})",
+            format_number(tokens),
+            details.join("; ")
+        )
+    }
+}
+
+/// Renders the report as human-readable text.  A single input gives a one-line summary, while
+/// several inputs give a per-file breakdown followed by the grand total.
+fn render_text(report: &Report) -> String {
+    let SourceCounts { counted, skipped } = report.counts;
+    if let ([count], []) = (counted.as_slice(), skipped.as_slice()) {
+        return format!(
+            "{}\n",
+            prompt_token_count(count.tokens, count.special_tokens, report.model)
+        );
+    }
+
+    let width = counted
+        .iter()
+        .map(|count| format_number(count.tokens).len())
+        .max()
+        .unwrap_or_default();
+    let mut lines: Vec<String> = counted
+        .iter()
+        .map(|count| {
+            let line = format!("{:>width$}  {}", format_number(count.tokens), count.label());
+            match describe_special(count.tokens, count.special_tokens) {
+                Some(special) => format!("{line} ({special})"),
+                None => line,
+            }
+        })
+        .collect();
+    lines.extend(skipped.iter().map(|skipped| {
+        format!(
+            "{:>width$}  {} (skipped: {})",
+            "-",
+            skipped.path.display(),
+            skipped.reason
+        )
+    }));
+    let total = total(report.counts);
+    let details: Vec<String> = [
+        Some(format!("{} files", format_number(total.inputs))),
+        describe_special(total.tokens, total.special_tokens),
+        describe_context(total.tokens, report.model),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    lines.push(format!(
+        "Total token count: {} ({})\n",
+        format_number(total.tokens),
+        details.join("; ")
+    ));
+
+    lines.join("\n")
+}
+
+/// Builds the JSON representation of a report.
+fn json_report<'a>(report: &'a Report) -> JsonReport<'a> {
+    let total = total(report.counts);
+    let usage = context_usage(total.tokens, report.model);
+
+    JsonReport {
+        schema_version: Some(SCHEMA_VERSION),
+        model: JsonModel::new(report.model, report.revision),
+        inputs: report.counts.counted.iter().map(JsonInput::from).collect(),
+        skipped: report
+            .counts
+            .skipped
+            .iter()
+            .map(|skipped| JsonSkipped {
+                path: skipped.path.display().to_string(),
+                reason: &skipped.reason,
+            })
+            .collect(),
+        total: JsonTotal {
+            context_percent: usage.map(|usage| usage.percent),
+            headroom: usage.map(|usage| usage.headroom),
+            ..total
+        },
+        difference: None,
+    }
+}
+
+/// Renders the report as a JSON object.
+fn render_json(report: &Report) -> String {
+    let mut output = serde_json::to_string_pretty(&json_report(report))
+        .expect("report should serialise to JSON");
+    output.push('\n');
+
+    output
+}
+
+/// Returns the number of tokens per character, rounded to four decimal places, or [`None`] for
+/// empty inputs.
+fn tokens_per_char(count: &InputCount) -> Option<f64> {
+    (count.chars > 0)
+        .then(|| (as_f64(count.tokens) / as_f64(count.chars) * 10_000.0).round() / 10_000.0)
+}
+
+/// Flattens each counted input into a row, for the row-based formats.
+fn rows<'a>(report: &'a Report) -> impl Iterator<Item = Row<'a>> {
+    report.counts.counted.iter().map(|count| {
+        let usage = context_usage(count.tokens, report.model);
+
+        Row {
+            source: source_name(count.kind),
+            path: count.path.as_ref().map(|path| path.display().to_string()),
+            model: &report.model.name,
+            repo_id: report.model.repo_id(),
+            revision: report.model.repo_id().map(|_| report.revision),
+            tokens: count.tokens,
+            chars: count.chars,
+            bytes: count.bytes,
+            tokens_per_char: tokens_per_char(count),
+            context_length: report.model.context_length,
+            context_percent: usage.map(|usage| usage.percent),
+            headroom: usage.map(|usage| usage.headroom),
+            tokenizer_path: tokenizer_path(report.model),
+            tiktoken: tiktoken_encoding(report.model),
+            special_tokens: count.special_tokens,
+            content_tokens: count.content_tokens(),
+        }
+    })
+}
+
+/// Renders rows as CSV, with a header row.
+fn render_csv<T: Serialize>(rows: impl Iterator<Item = T>) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).expect("row should serialise to CSV");
+    }
+    let output = writer
+        .into_inner()
+        .expect("CSV writer should flush to memory");
+
+    String::from_utf8(output).expect("CSV output should be valid UTF-8")
+}
+
+/// Renders rows as newline-delimited JSON, with one object per line.
+fn render_ndjson<T: Serialize>(rows: impl Iterator<Item = T>) -> String {
+    rows.map(|row| {
+        let mut line = serde_json::to_string(&row).expect("row should serialise to JSON");
+        line.push('\n');
+        line
+    })
+    .collect()
+}
+
+/// Renders the report in the requested format.
+pub fn render_report(report: &Report, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => render_text(report),
+        OutputFormat::Json => render_json(report),
+        OutputFormat::Csv => render_csv(rows(report)),
+        OutputFormat::Ndjson => render_ndjson(rows(report)),
+    }
+}
+
+/// Returns the difference between a token count and the baseline count, as a number of tokens
+/// and a percentage of the baseline, rounded to two decimal places.  The percentage is [`None`]
+/// when the baseline is zero.
+fn difference(tokens: usize, baseline: usize) -> JsonDifference {
+    let as_i64 = |value: usize| i64::try_from(value).unwrap_or(i64::MAX);
+    let difference = as_i64(tokens) - as_i64(baseline);
+
+    JsonDifference {
+        tokens: difference,
+        percent: (baseline > 0).then(|| {
+            ((as_f64(tokens) - as_f64(baseline)) / as_f64(baseline) * 10_000.0).round() / 100.0
+        }),
+    }
+}
+
+/// Describes the change from the `previous` token count, for example `+66 tokens, +5.50%`.
+fn describe_change(tokens: usize, previous: usize) -> String {
+    let JsonDifference { tokens, percent } = difference(tokens, previous);
+    match percent {
+        Some(percent) => format!("{tokens:+} tokens, {percent:+.2}%"),
+        None => format!("{tokens:+} tokens"),
+    }
+}
+
+/// Renders the comparison as a text table, with one row for each model.
+fn render_comparison_text(comparison: &Comparison) -> String {
+    let baseline_tokens = total(comparison.reports[comparison.baseline].counts).tokens;
+    let header = ["Model", "Tokens", "Context", "Difference"].map(str::to_owned);
+    let table: Vec<[String; 4]> = std::iter::once(header)
+        .chain(
+            comparison
+                .reports
+                .iter()
+                .enumerate()
+                .map(|(index, report)| {
+                    let tokens = total(report.counts).tokens;
+                    let difference = if index == comparison.baseline {
+                        "baseline".to_owned()
+                    } else {
+                        let JsonDifference { tokens, percent } =
+                            difference(tokens, baseline_tokens);
+                        match percent {
+                            Some(percent) => format!("{tokens:+} ({percent:+.2}%)"),
+                            None => format!("{tokens:+}"),
+                        }
+                    };
+                    let context =
+                        context_share(tokens, report.model).unwrap_or_else(|| "-".to_owned());
+                    [
+                        report.model.name.clone(),
+                        format_number(tokens),
+                        context,
+                        difference,
+                    ]
+                }),
+        )
+        .chain(comparison.failed.iter().map(|failed| {
+            [
+                failed.model.name.clone(),
+                "-".to_owned(),
+                "-".to_owned(),
+                format!("failed: {}", failed.reason),
+            ]
+        }))
+        .collect();
+    let name_width = table
+        .iter()
+        .map(|[name, ..]| name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let tokens_width = table
+        .iter()
+        .map(|[_, tokens, ..]| tokens.len())
+        .max()
+        .unwrap_or_default();
+    let context_width = table
+        .iter()
+        .map(|[_, _, context, _]| context.len())
+        .max()
+        .unwrap_or_default();
+
+    let mut lines: Vec<String> = table
+        .iter()
+        .map(|[name, tokens, context, difference]| {
+            format!(
+                "{name:<name_width$}  {tokens:>tokens_width$}  {context:>context_width$}  \
+                {difference}"
+            )
+        })
+        .collect();
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+/// Renders the comparison as a JSON object, with a report for each model.
+fn render_comparison_json(comparison: &Comparison) -> String {
+    let baseline = &comparison.reports[comparison.baseline];
+    let baseline_tokens = total(baseline.counts).tokens;
+    let json_comparison = JsonComparison {
+        schema_version: SCHEMA_VERSION,
+        baseline: &baseline.model.name,
+        models: comparison
+            .reports
+            .iter()
+            .map(|report| JsonReport {
+                schema_version: None,
+                difference: Some(difference(total(report.counts).tokens, baseline_tokens)),
+                ..json_report(report)
+            })
+            .collect(),
+        failed: comparison
+            .failed
+            .iter()
+            .map(|failed| JsonFailedModel {
+                name: &failed.model.name,
+                repo_id: failed.model.repo_id(),
+                reason: &failed.reason,
+            })
+            .collect(),
+    };
+
+    let mut output = serde_json::to_string_pretty(&json_comparison)
+        .expect("comparison should serialise to JSON");
+    output.push('\n');
+
+    output
+}
+
+/// Renders a comparison of several models in the requested format.  Row-based formats have one
+/// row per input for each model, and leave out models which failed.
+pub fn render_comparison(comparison: &Comparison, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => render_comparison_text(comparison),
+        OutputFormat::Json => render_comparison_json(comparison),
+        OutputFormat::Csv => render_csv(comparison.reports.iter().flat_map(rows)),
+        OutputFormat::Ndjson => render_ndjson(comparison.reports.iter().flat_map(rows)),
+    }
+}
+
+/// Renders a single model's `--watch` run as text: the per-file breakdown, marking files which
+/// changed, were added or were removed since the previous run, followed by the total and its
+/// change.
+fn render_watch_text(report: &WatchReport) -> String {
+    let SourceCounts { counted, skipped } = report.counts;
+    let previous: HashMap<String, usize> = report
+        .previous
+        .iter()
+        .flat_map(|previous| previous.counted.iter())
+        .map(|count| (count.label(), count.tokens))
+        .collect();
+    let removed: Vec<(String, usize)> = report
+        .previous
+        .iter()
+        .flat_map(|previous| previous.counted.iter())
+        .filter(|count| {
+            !counted
+                .iter()
+                .any(|current| current.label() == count.label())
+        })
+        .map(|count| (count.label(), count.tokens))
+        .collect();
+
+    let width = counted
+        .iter()
+        .map(|count| format_number(count.tokens).len())
+        .max()
+        .unwrap_or(1);
+    let mut lines: Vec<String> = counted
+        .iter()
+        .map(|count| {
+            let label = count.label();
+            let line = format!("{:>width$}  {label}", format_number(count.tokens));
+            match (report.previous, previous.get(&label)) {
+                (Some(_), None) => format!("{line} (new)"),
+                (Some(_), Some(&before)) if before != count.tokens => {
+                    format!("{line} ({})", describe_change(count.tokens, before))
+                }
+                _ => line,
+            }
+        })
+        .collect();
+    lines.extend(removed.iter().map(|(label, tokens)| {
+        format!(
+            "{:>width$}  {label} (removed, was {})",
+            "-",
+            format_number(*tokens)
+        )
+    }));
+    lines.extend(skipped.iter().map(|skipped| {
+        format!(
+            "{:>width$}  {} (skipped: {})",
+            "-",
+            skipped.path.display(),
+            skipped.reason
+        )
+    }));
+    let total = total(report.counts);
+    let change = report.previous.map(|previous| {
+        let before = previous.total_tokens();
+        if before == total.tokens {
+            "unchanged".to_owned()
+        } else {
+            describe_change(total.tokens, before)
+        }
+    });
+    let details: Vec<String> = [
+        change,
+        Some(format!("{} files", format_number(total.inputs))),
+        describe_context(total.tokens, report.model),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    lines.push(format!(
+        "Total token count: {} ({})\n",
+        format_number(total.tokens),
+        details.join("; ")
+    ));
+
+    lines.join("\n")
+}
+
+/// Renders a `--watch` run, with a section for each model, headed by the model name when there
+/// are several.
+pub fn render_watch_report(reports: &[WatchReport]) -> String {
+    if let [report] = reports {
+        return render_watch_text(report);
+    }
+
+    reports
+        .iter()
+        .map(|report| format!("{}:\n{}", report.model.name, render_watch_text(report)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the number of special tokens in `tokens`.
+pub fn special_count(tokens: &[TokenInfo]) -> usize {
+    tokens.iter().filter(|token| token.special).count()
+}
+
+/// Renders the tokens as a text table.  Token strings and prompt slices are quoted and escaped,
+/// so whitespace and control characters are visible.
+fn render_tokens_text(report: &TokenReport) -> String {
+    let header = ["Index", "ID", "Token", "Text", "Bytes", "Chars"].map(str::to_owned);
+    let table: Vec<[String; 6]> = std::iter::once(header)
+        .chain(report.tokens.iter().enumerate().map(|(index, token)| {
+            [
+                index.to_string(),
+                token.id.to_string(),
+                format!("{:?}", token.token),
+                format!("{:?}", token.text),
+                format!("{}..{}", token.bytes.0, token.bytes.1),
+                format!("{}..{}", token.chars.0, token.chars.1),
+            ]
+        }))
+        .collect();
+    let widths: Vec<usize> = (0..6)
+        .map(|column| {
+            table
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut lines: Vec<String> = table
+        .iter()
+        .map(|row| {
+            let [index, id, token, text, bytes, chars] = row;
+            format!(
+                "{index:>w0$}  {id:>w1$}  {token:<w2$}  {text:<w3$}  {bytes:<w4$}  {chars}",
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            )
+            .trim_end()
+            .to_owned()
+        })
+        .collect();
+    lines.push(format!(
+        "{}\n",
+        prompt_token_count(
+            report.tokens.len(),
+            special_count(report.tokens),
+            report.model
+        )
+    ));
+
+    lines.join("\n")
+}
+
+/// Renders the tokens in the requested format.
+pub fn render_tokens(report: &TokenReport, format: OutputFormat) -> String {
+    let json_tokens = report
+        .tokens
+        .iter()
+        .enumerate()
+        .map(|(index, token)| JsonToken {
+            index,
+            id: token.id,
+            token: &token.token,
+            text: &token.text,
+            bytes: token.bytes,
+            chars: token.chars,
+            special: token.special,
+        });
+    let token_rows = report
+        .tokens
+        .iter()
+        .enumerate()
+        .map(|(index, token)| TokenRow {
+            index,
+            id: token.id,
+            token: &token.token,
+            text: &token.text,
+            byte_start: token.bytes.0,
+            byte_end: token.bytes.1,
+            char_start: token.chars.0,
+            char_end: token.chars.1,
+            special: token.special,
+        });
+
+    match format {
+        OutputFormat::Text => render_tokens_text(report),
+        OutputFormat::Json => {
+            let json_report = JsonTokenReport {
+                schema_version: SCHEMA_VERSION,
+                model: JsonModel::new(report.model, report.revision),
+                tokens: json_tokens.collect(),
+            };
+            let mut output = serde_json::to_string_pretty(&json_report)
+                .expect("token report should serialise to JSON");
+            output.push('\n');
+
+            output
+        }
+        OutputFormat::Csv => render_csv(token_rows),
+        OutputFormat::Ndjson => render_ndjson(token_rows),
+    }
+}
+
+/// Renders the conversation counts as text.  The per-message breakdown, when there is one, lists
+/// the tokens each message adds, before the one-line total.
+fn render_chat_text(report: &ChatReport) -> String {
+    let counts = report.counts;
+    let mut parts: Vec<(usize, String)> = counts
+        .breakdown
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, message)| (message.tokens, format!("#{} {}", index + 1, message.role)))
+        .collect();
+    parts.extend(
+        counts
+            .generation_prompt
+            .map(|tokens| (tokens, "generation prompt".to_owned())),
+    );
+    let width = parts
+        .iter()
+        .map(|(tokens, _)| format_number(*tokens).len())
+        .max()
+        .unwrap_or_default();
+    let mut lines: Vec<String> = parts
+        .iter()
+        .map(|(tokens, label)| format!("{:>width$}  {label}", format_number(*tokens)))
+        .collect();
+    let messages = match counts.messages {
+        1 => "1 message".to_owned(),
+        messages => format!("{} messages", format_number(messages)),
+    };
+    lines.push(match describe_context(counts.tokens, report.model) {
+        Some(context) => format!(
+            "Conversation token count: {} ({messages}; {context})\n",
+            format_number(counts.tokens)
+        ),
+        None => format!(
+            "Conversation token count: {} ({messages})\n",
+            format_number(counts.tokens)
+        ),
+    });
+
+    lines.join("\n")
+}
+
+/// Flattens the conversation counts into rows, for the row-based formats.
+fn chat_rows<'a>(report: &'a ChatReport) -> Vec<ChatRow<'a>> {
+    let row = |part, index, role, tokens| ChatRow {
+        part,
+        index,
+        role,
+        model: &report.model.name,
+        repo_id: report.model.repo_id(),
+        revision: report.model.repo_id().map(|_| report.revision),
+        tokens,
+        context_length: report.model.context_length,
+        tokenizer_path: tokenizer_path(report.model),
+        tiktoken: tiktoken_encoding(report.model),
+    };
+    let counts = report.counts;
+    let Some(breakdown) = &counts.breakdown else {
+        return vec![row("conversation", None, None, counts.tokens)];
+    };
+
+    breakdown
+        .iter()
+        .enumerate()
+        .map(|(index, message)| {
+            row(
+                "message",
+                Some(index),
+                Some(message.role.as_str()),
+                message.tokens,
+            )
+        })
+        .chain(
+            counts
+                .generation_prompt
+                .map(|tokens| row("generation_prompt", None, None, tokens)),
+        )
+        .collect()
+}
+
+/// Renders the conversation counts in the requested format.
+pub fn render_chat_report(report: &ChatReport, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => render_chat_text(report),
+        OutputFormat::Json => {
+            let counts = report.counts;
+            let usage = context_usage(counts.tokens, report.model);
+            let json_report = JsonChatReport {
+                schema_version: SCHEMA_VERSION,
+                model: JsonModel::new(report.model, report.revision),
+                messages: counts.breakdown.as_ref().map(|breakdown| {
+                    breakdown
+                        .iter()
+                        .enumerate()
+                        .map(|(index, message)| JsonMessage {
+                            index,
+                            role: &message.role,
+                            tokens: message.tokens,
+                        })
+                        .collect()
+                }),
+                generation_prompt: counts.generation_prompt,
+                total: JsonChatTotal {
+                    messages: counts.messages,
+                    tokens: counts.tokens,
+                    chars: counts.chars,
+                    bytes: counts.bytes,
+                    context_percent: usage.map(|usage| usage.percent),
+                    headroom: usage.map(|usage| usage.headroom),
+                },
+            };
+
+            to_json_output(&json_report)
+        }
+        OutputFormat::Csv => render_csv(chat_rows(report).into_iter()),
+        OutputFormat::Ndjson => render_ndjson(chat_rows(report).into_iter()),
+    }
+}
+
+impl<'a> From<&RegisteredModel<'a>> for ModelRow<'a> {
+    fn from(value: &RegisteredModel<'a>) -> Self {
+        Self {
+            name: &value.entry.name,
+            repo_id: value.entry.hf.as_deref(),
+            tokenizer_path: value
+                .entry
+                .path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            context_length: value.entry.context_length,
+            max_output_tokens: value.entry.max_output_tokens,
+            layer: value.file.layer,
+            path: value
+                .file
+                .path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            revision: value.entry.revision.as_deref(),
+            sha256: value.entry.sha256.as_deref(),
+            tiktoken: value.entry.tiktoken.as_deref(),
+        }
+    }
+}
+
+/// Renders an optional count, using `-` when it is not known.
+fn format_optional_number(number: Option<usize>) -> String {
+    number.map_or_else(|| "-".to_owned(), format_number)
+}
+
+/// Serialises a value as pretty-printed JSON, with a trailing newline.
+fn to_json_output<T: Serialize>(value: &T) -> String {
+    let mut output = serde_json::to_string_pretty(value).expect("value should serialise to JSON");
+    output.push('\n');
+
+    output
+}
+
+/// Renders the models in the registry as a text table.
+fn render_model_list_text(models: &[RegisteredModel]) -> String {
+    let header = ["Name", "Tokenizer", "Context", "Source"].map(str::to_owned);
+    let table: Vec<[String; 4]> = std::iter::once(header)
+        .chain(models.iter().map(|model| {
+            [
+                model.entry.name.clone(),
+                model
+                    .entry
+                    .tokenizer_source()
+                    .map_or_else(|_| "-".to_owned(), |source| source.to_string()),
+                format_optional_number(model.entry.context_length),
+                model.file.layer.name().to_owned(),
+            ]
+        }))
+        .collect();
+    let widths: Vec<usize> = (0..3)
+        .map(|column| {
+            table
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut lines: Vec<String> = table
+        .iter()
+        .map(|[name, repo, context, source]| {
+            format!(
+                "{name:<w0$}  {repo:<w1$}  {context:>w2$}  {source}",
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )
+        })
+        .collect();
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+/// Renders the models in the registry in the requested format.
+pub fn render_model_list(models: &[RegisteredModel], format: OutputFormat) -> String {
+    let rows = models.iter().map(ModelRow::from);
+
+    match format {
+        OutputFormat::Text => render_model_list_text(models),
+        OutputFormat::Json => to_json_output(&JsonModelList {
+            schema_version: SCHEMA_VERSION,
+            models: rows.collect(),
+        }),
+        OutputFormat::Csv => render_csv(rows),
+        OutputFormat::Ndjson => render_ndjson(rows),
+    }
+}
+
+impl<'a> From<&'a CacheEntry<'a>> for CacheRow<'a> {
+    fn from(value: &'a CacheEntry<'a>) -> Self {
+        Self {
+            name: value.name,
+            repo_id: &value.repo_id,
+            revision: value.revision,
+            cached: value.cached.is_some(),
+            commit: value.cached.as_ref().map(|cached| cached.commit.as_str()),
+            size: value.cached.as_ref().map(|cached| cached.size),
+            path: value
+                .cached
+                .as_ref()
+                .map(|cached| cached.path.display().to_string()),
+        }
+    }
+}
+
+/// Renders registry models and their cached tokenizers as a text table.
+fn render_cache_list_text(entries: &[CacheEntry]) -> String {
+    let header = ["Name", "Repo", "Revision", "Commit", "Bytes"].map(str::to_owned);
+    let table: Vec<[String; 5]> = std::iter::once(header)
+        .chain(entries.iter().map(|entry| match &entry.cached {
+            Some(cached) => [
+                entry.name.to_owned(),
+                entry.repo_id.clone(),
+                entry.revision.to_owned(),
+                cached.commit.chars().take(12).collect(),
+                format_number(usize::try_from(cached.size).unwrap_or(usize::MAX)),
+            ],
+            None => [
+                entry.name.to_owned(),
+                entry.repo_id.clone(),
+                entry.revision.to_owned(),
+                "not cached".to_owned(),
+                "-".to_owned(),
+            ],
+        }))
+        .collect();
+    let widths: Vec<usize> = (0..5)
+        .map(|column| {
+            table
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut lines: Vec<String> = table
+        .iter()
+        .map(|[name, repo, revision, commit, bytes]| {
+            format!(
+                "{name:<w0$}  {repo:<w1$}  {revision:<w2$}  {commit:<w3$}  {bytes:>w4$}",
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            )
+        })
+        .collect();
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+/// Renders registry models and their cached tokenizers in the requested format, along with the
+/// cache directory for JSON output.
+pub fn render_cache_list(entries: &[CacheEntry], cache_dir: &Path, format: OutputFormat) -> String {
+    let rows = entries.iter().map(CacheRow::from);
+
+    match format {
+        OutputFormat::Text => render_cache_list_text(entries),
+        OutputFormat::Json => to_json_output(&JsonCacheList {
+            schema_version: SCHEMA_VERSION,
+            cache_dir: &cache_dir.display().to_string(),
+            models: rows.collect(),
+        }),
+        OutputFormat::Csv => render_csv(rows),
+        OutputFormat::Ndjson => render_ndjson(rows),
+    }
+}
+
+/// Renders the details of a single model in the requested format.
+pub fn render_model_details(model: &RegisteredModel, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => {
+            let source = format!("{} ({})", model.file.layer.name(), model.file.label());
+            [
+                ("Name", model.entry.name.clone()),
+                match model.entry.tokenizer_source() {
+                    Ok(TokenizerSource::Hub(repo_id)) => ("Repo", repo_id),
+                    Ok(TokenizerSource::Path(path)) => ("Path", path.display().to_string()),
+                    Ok(TokenizerSource::Tiktoken(encoding)) => ("Tiktoken", encoding),
+                    Err(_) => ("Tokenizer", "-".to_owned()),
+                },
+                (
+                    "Revision",
+                    model
+                        .entry
+                        .revision
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_REVISION.to_owned()),
+                ),
+                (
+                    "SHA-256",
+                    model.entry.sha256.clone().unwrap_or_else(|| "-".to_owned()),
+                ),
+                (
+                    "Context length",
+                    format_optional_number(model.entry.context_length),
+                ),
+                (
+                    "Max output tokens",
+                    format_optional_number(model.entry.max_output_tokens),
+                ),
+                ("Source", source),
+            ]
+            .iter()
+            .map(|(label, value)| format!("{:<19}{value}", format!("{label}:")))
+            .chain(std::iter::once(String::new()))
+            .collect::<Vec<String>>()
+            .join("\n")
+        }
+        OutputFormat::Json => to_json_output(&JsonModelDetails {
+            schema_version: SCHEMA_VERSION,
+            model: model.into(),
+        }),
+        OutputFormat::Csv => render_csv(std::iter::once(ModelRow::from(model))),
+        OutputFormat::Ndjson => render_ndjson(std::iter::once(ModelRow::from(model))),
+    }
+}
+
+/// Renders the problems found when validating the registry in the requested format, along with
+/// the number of models checked.
+pub fn render_problems(checked: usize, problems: &[Problem], format: OutputFormat) -> String {
+    let json_problems = problems.iter().map(|problem| JsonProblem {
+        name: &problem.name,
+        source: &problem.source,
+        message: &problem.message,
+    });
+
+    match format {
+        OutputFormat::Text => {
+            let mut lines: Vec<String> = problems
+                .iter()
+                .map(|problem| {
+                    format!(
+                        "`{}` in {}: {}",
+                        problem.name, problem.source, problem.message
+                    )
+                })
+                .collect();
+            lines.push(match problems.len() {
+                0 => format!(
+                    "Checked {} models, found no problems\n",
+                    format_number(checked)
+                ),
+                1 => format!(
+                    "Checked {} models, found 1 problem\n",
+                    format_number(checked)
+                ),
+                count => format!(
+                    "Checked {} models, found {} problems\n",
+                    format_number(checked),
+                    format_number(count)
+                ),
+            });
+
+            lines.join("\n")
+        }
+        OutputFormat::Json => to_json_output(&JsonValidation {
+            schema_version: SCHEMA_VERSION,
+            checked,
+            problems: json_problems.collect(),
+        }),
+        OutputFormat::Csv => render_csv(json_problems),
+        OutputFormat::Ndjson => render_ndjson(json_problems),
+    }
+}
+
+/// Renders an error as a JSON object for the JSON-based formats, so scripts parsing the output can
+/// also handle failures.  Returns [`None`] for other formats, which rely on the diagnostic printed
+/// to standard error.
+pub fn render_error(error: &miette::Report, format: OutputFormat) -> Option<String> {
+    let json_error = JsonError {
+        schema_version: SCHEMA_VERSION,
+        error: JsonErrorDetail {
+            message: error.to_string(),
+            causes: error.chain().skip(1).map(ToString::to_string).collect(),
+            help: error.help().map(|help| help.to_string()),
+        },
+    };
+
+    let mut output = match format {
+        OutputFormat::Text | OutputFormat::Csv => return None,
+        OutputFormat::Json => serde_json::to_string_pretty(&json_error),
+        OutputFormat::Ndjson => serde_json::to_string(&json_error),
+    }
+    .expect("error should serialise to JSON");
+    output.push('\n');
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use miette::{Context, miette};
+
+    use tokenator::{
+        chat::{ChatCounts, MessageCount},
+        models::ResolvedModel,
+        token::{TokenInfo, TokenizerPin, TokenizerSource},
+    };
+
+    use crate::{
+        cache::CachedTokenizer,
+        files::{InputCount, SkippedInput, SourceCounts, SourceKind},
+        output::{
+            CacheEntry, ChatReport, Comparison, FailedModel, OutputFormat, Report, TokenReport,
+            WatchReport, format_number, render_cache_list, render_chat_report, render_comparison,
+            render_error, render_report, render_tokens, render_watch_report,
+        },
+    };
+
+    fn get_model_fixture() -> ResolvedModel {
+        ResolvedModel {
+            name: "example:latest".to_owned(),
+            tokenizer: TokenizerSource::Hub("example/Example-1-M-state-of-the-art".to_owned()),
+            pin: TokenizerPin::default(),
+            context_length: Some(32_768),
+            max_output_tokens: Some(8_192),
+        }
+    }
+
+    fn get_counts_fixture() -> SourceCounts {
+        SourceCounts {
+            counted: vec![
+                InputCount {
+                    kind: SourceKind::File,
+                    path: Some(PathBuf::from("prompts/system.md")),
+                    tokens: 1_234,
+                    special_tokens: 1,
+                    chars: 5_000,
+                    bytes: 5_010,
+                },
+                InputCount {
+                    kind: SourceKind::Stdin,
+                    path: None,
+                    tokens: 7,
+                    special_tokens: 0,
+                    chars: 20,
+                    bytes: 20,
+                },
+            ],
+            skipped: vec![SkippedInput {
+                path: PathBuf::from("prompts/image.png"),
+                reason: "Error reading file `prompts/image.png`".to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn format_number_generates_expected_output_for_valid_input() {
+        // arrange
+        let number = 42;
+
+        // act
+        let outcome = format_number(number);
+
+        // assert
+        assert_eq!(&outcome, "42");
+
+        // arrange
+        let number = 10_000_000_000;
+
+        // act
+        let outcome = format_number(number);
+
+        // assert
+        assert_eq!(&outcome, "10,000,000,000");
+
+        // arrange
+        let number = 1_000_000;
+
+        // act
+        let outcome = format_number(number);
+
+        // assert
+        assert_eq!(&outcome, "1,000,000");
+
+        // arrange
+        let number = 0;
+
+        // act
+        let outcome = format_number(number);
+
+        // assert
+        assert_eq!(&outcome, "0");
+    }
+
+    #[test]
+    fn render_report_generates_one_line_summary_for_single_input() {
+        // arrange
+        let model = get_model_fixture();
+        let counts = SourceCounts {
+            counted: vec![InputCount {
+                kind: SourceKind::Text,
+                path: None,
+                tokens: 6,
+                special_tokens: 0,
+                chars: 20,
+                bytes: 20,
+            }],
+            skipped: Vec::new(),
+        };
+        let report = Report {
+            model: &model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let outcome = render_report(&report, OutputFormat::Text);
+
+        // assert
+        assert_eq!(
+            outcome,
+            "Prompt token count: 6 (0.02% of 32,768 context, 32,762 tokens of headroom)\n"
+        );
+    }
+
+    #[test]
+    fn render_report_summarises_context_use_only_when_context_length_is_known() {
+        // arrange
+        let small_model = ResolvedModel {
+            context_length: Some(1_024),
+            ..get_model_fixture()
+        };
+        let unknown_model = ResolvedModel {
+            context_length: None,
+            ..get_model_fixture()
+        };
+        let counts = get_single_count_fixture(1_200);
+        let report = |model| Report {
+            model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let small_outcome = render_report(&report(&small_model), OutputFormat::Text);
+        let unknown_outcome = render_report(&report(&unknown_model), OutputFormat::Text);
+
+        // assert
+        assert_eq!(
+            small_outcome,
+            "Prompt token count: 1,200 (117.19% of 1,024 context, exceeds the context by 176 \
+                tokens)\n"
+        );
+        assert_eq!(unknown_outcome, "Prompt token count: 1,200\n");
+    }
+
+    #[test]
+    fn render_report_generates_breakdown_for_multiple_inputs() {
+        // arrange
+        let model = get_model_fixture();
+        let counts = get_counts_fixture();
+        let report = Report {
+            model: &model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let outcome = render_report(&report, OutputFormat::Text);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_watch_report_marks_changes_since_the_last_run() {
+        // arrange
+        let model = get_model_fixture();
+        let previous = get_counts_fixture();
+        let mut counts = get_counts_fixture();
+        counts.counted[0].tokens = 1_300;
+        counts.counted[1] = InputCount {
+            kind: SourceKind::File,
+            path: Some(PathBuf::from("prompts/user.md")),
+            tokens: 12,
+            special_tokens: 0,
+            chars: 40,
+            bytes: 40,
+        };
+        counts.skipped.clear();
+
+        // act
+        let first = render_watch_report(&[WatchReport {
+            model: &model,
+            counts: &previous,
+            previous: None,
+        }]);
+        let outcome = render_watch_report(&[WatchReport {
+            model: &model,
+            counts: &counts,
+            previous: Some(&previous),
+        }]);
+
+        // assert
+        assert!(!first.contains('+'));
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_report_generates_expected_json() {
+        // arrange
+        let model = get_model_fixture();
+        let counts = get_counts_fixture();
+        let report = Report {
+            model: &model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let outcome = render_report(&report, OutputFormat::Json);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_chat_report_generates_expected_json_and_csv() {
+        // arrange
+        let model = get_model_fixture();
+        let counts = ChatCounts {
+            messages: 2,
+            breakdown: Some(vec![
+                MessageCount {
+                    role: "system".to_owned(),
+                    tokens: 1_210,
+                },
+                MessageCount {
+                    role: "user".to_owned(),
+                    tokens: 31,
+                },
+            ]),
+            generation_prompt: Some(3),
+            tokens: 1_244,
+            chars: 5_120,
+            bytes: 5_130,
+        };
+        let report = ChatReport {
+            model: &model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let json_outcome = render_chat_report(&report, OutputFormat::Json);
+        let csv_outcome = render_chat_report(&report, OutputFormat::Csv);
+
+        // assert
+        insta::assert_snapshot!(json_outcome);
+        insta::assert_snapshot!(csv_outcome);
+    }
+
+    #[test]
+    fn render_error_generates_expected_json() {
+        // arrange
+        let error: miette::Result<()> = Err(miette!(
+            help = "Check the file exists",
+            "No such file or directory (os error 2)"
+        ));
+        let error = error
+            .wrap_err("Error opening file `prompt.md`")
+            .unwrap_err();
+
+        // act
+        let outcome = render_error(&error, OutputFormat::Json).unwrap();
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_error_generates_single_line_for_ndjson() {
+        // arrange
+        let error = miette!("No model matching `example-modal`");
+
+        // act
+        let outcome = render_error(&error, OutputFormat::Ndjson).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            "{\"schema_version\":1,\"error\":{\"message\":\"No model matching `example-modal`\",\
+                \"causes\":[],\"help\":null}}\n"
+        );
+        assert!(render_error(&error, OutputFormat::Text).is_none());
+        assert!(render_error(&error, OutputFormat::Csv).is_none());
+    }
+
+    #[test]
+    fn render_report_generates_expected_csv() {
+        // arrange
+        let model = get_model_fixture();
+        let mut counts = get_counts_fixture();
+        counts.counted.push(InputCount {
+            kind: SourceKind::File,
+            path: Some(PathBuf::from("prompts/with, comma.md")),
+            tokens: 0,
+            special_tokens: 0,
+            chars: 0,
+            bytes: 0,
+        });
+        let report = Report {
+            model: &model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let outcome = render_report(&report, OutputFormat::Csv);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_report_generates_expected_ndjson() {
+        // arrange
+        let model = get_model_fixture();
+        let counts = get_counts_fixture();
+        let report = Report {
+            model: &model,
+            revision: "main",
+            counts: &counts,
+        };
+
+        // act
+        let outcome = render_report(&report, OutputFormat::Ndjson);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    fn get_tokens_fixture() -> Vec<TokenInfo> {
+        vec![
+            TokenInfo {
+                id: 1,
+                token: "[BOS]".to_owned(),
+                text: String::new(),
+                bytes: (0, 0),
+                chars: (0, 0),
+                special: true,
+            },
+            TokenInfo {
+                id: 9_906,
+                token: "Héllo".to_owned(),
+                text: "Héllo".to_owned(),
+                bytes: (0, 6),
+                chars: (0, 5),
+                special: false,
+            },
+            TokenInfo {
+                id: 1_917,
+                token: "Ġworld".to_owned(),
+                text: " world".to_owned(),
+                bytes: (6, 12),
+                chars: (5, 11),
+                special: false,
+            },
+            TokenInfo {
+                id: 198,
+                token: "Ċ".to_owned(),
+                text: "\n".to_owned(),
+                bytes: (12, 13),
+                chars: (11, 12),
+                special: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn render_tokens_generates_expected_table() {
+        // arrange
+        let model = get_model_fixture();
+        let tokens = get_tokens_fixture();
+        let report = TokenReport {
+            model: &model,
+            revision: "main",
+            tokens: &tokens,
+        };
+
+        // act
+        let outcome = render_tokens(&report, OutputFormat::Text);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_tokens_generates_expected_json() {
+        // arrange
+        let model = get_model_fixture();
+        let tokens = get_tokens_fixture();
+        let report = TokenReport {
+            model: &model,
+            revision: "main",
+            tokens: &tokens,
+        };
+
+        // act
+        let outcome = render_tokens(&report, OutputFormat::Json);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_tokens_generates_expected_csv() {
+        // arrange
+        let model = get_model_fixture();
+        let tokens = get_tokens_fixture();
+        let report = TokenReport {
+            model: &model,
+            revision: "main",
+            tokens: &tokens,
+        };
+
+        // act
+        let outcome = render_tokens(&report, OutputFormat::Csv);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    fn get_comparison_models_fixture() -> Vec<ResolvedModel> {
+        vec![
+            ResolvedModel {
+                name: "example:latest".to_owned(),
+                tokenizer: TokenizerSource::Hub("example/Example-1-M-state-of-the-art".to_owned()),
+                pin: TokenizerPin::default(),
+                context_length: Some(32_768),
+                max_output_tokens: None,
+            },
+            ResolvedModel {
+                name: "example:100b".to_owned(),
+                tokenizer: TokenizerSource::Hub("example/Example-100-B-most-capable".to_owned()),
+                pin: TokenizerPin::default(),
+                context_length: Some(1_024),
+                max_output_tokens: None,
+            },
+            ResolvedModel {
+                name: "gated:70b".to_owned(),
+                tokenizer: TokenizerSource::Hub("example/Gated-70-B".to_owned()),
+                pin: TokenizerPin::default(),
+                context_length: None,
+                max_output_tokens: None,
+            },
+        ]
+    }
+
+    fn get_single_count_fixture(tokens: usize) -> SourceCounts {
+        SourceCounts {
+            counted: vec![InputCount {
+                kind: SourceKind::Text,
+                path: None,
+                tokens,
+                special_tokens: 0,
+                chars: 5_000,
+                bytes: 5_000,
+            }],
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn render_comparison_generates_expected_table() {
+        // arrange
+        let models = get_comparison_models_fixture();
+        let baseline_counts = get_single_count_fixture(1_200);
+        let other_counts = get_single_count_fixture(1_266);
+        let comparison = Comparison {
+            reports: vec![
+                Report {
+                    model: &models[0],
+                    revision: "main",
+                    counts: &baseline_counts,
+                },
+                Report {
+                    model: &models[1],
+                    revision: "main",
+                    counts: &other_counts,
+                },
+            ],
+            baseline: 0,
+            failed: vec![FailedModel {
+                model: &models[2],
+                reason: "Check Hugging Face configuration".to_owned(),
+            }],
+        };
+
+        // act
+        let outcome = render_comparison(&comparison, OutputFormat::Text);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_comparison_generates_expected_json() {
+        // arrange
+        let models = get_comparison_models_fixture();
+        let baseline_counts = get_single_count_fixture(1_200);
+        let other_counts = get_single_count_fixture(1_140);
+        let comparison = Comparison {
+            reports: vec![
+                Report {
+                    model: &models[0],
+                    revision: "main",
+                    counts: &other_counts,
+                },
+                Report {
+                    model: &models[1],
+                    revision: "main",
+                    counts: &baseline_counts,
+                },
+            ],
+            baseline: 1,
+            failed: Vec::new(),
+        };
+
+        // act
+        let outcome = render_comparison(&comparison, OutputFormat::Json);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+
+    #[test]
+    fn render_cache_list_generates_expected_table() {
+        // arrange
+        let entries = [
+            CacheEntry {
+                name: "example:latest",
+                repo_id: "example/Example-1-M-state-of-the-art".to_owned(),
+                revision: "main",
+                cached: Some(CachedTokenizer {
+                    commit: "0123456789abcdef0123456789abcdef01234567".to_owned(),
+                    path: PathBuf::from(
+                        "models--example--Example-1-M-state-of-the-art/snapshots/\
+                        0123456789abcdef0123456789abcdef01234567/tokenizer.json",
+                    ),
+                    size: 11_422_654,
+                }),
+            },
+            CacheEntry {
+                name: "gated:70b",
+                repo_id: "example/Gated-70-B".to_owned(),
+                revision: "v1.0",
+                cached: None,
+            },
+        ];
+
+        // act
+        let outcome = render_cache_list(&entries, Path::new("/cache"), OutputFormat::Text);
+
+        // assert
+        insta::assert_snapshot!(outcome);
+    }
+}
@@ -1,36 +1,53 @@
-use std::path::Path;
+use std::{io::Read, path::Path};
 
 use miette::{bail, miette};
 
-use crate::utility::read_file;
+use crate::utility::{read_file, read_stdin};
 
-/// Retrieves the prompt text from a file's content or user input.
+/// Largest prompt accepted, in bytes.
+pub const MAX_PROMPT_BYTES: usize = 20_048_000;
+
+/// Returns `true` if the path is `-`, the conventional placeholder for standard input.
+pub fn is_stdin_path<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().as_os_str() == "-"
+}
+
+/// Retrieves the prompt text from a file's content, user input or standard input.  A `file` of
+/// `-` reads from `stdin`, and `stdin` is also used as a fallback when neither `file` nor `prompt`
+/// is given.  Pass `stdin` as [`None`] when standard input is an interactive terminal.
 ///
 /// # Returns
 /// A `miette::Result` containing the prompt text.
 ///
 /// # Errors
 ///
-/// Errors if both `file` and `prompt` are [`None`].
-pub fn get_prompt<P: AsRef<Path>>(file: Option<P>, prompt: Option<&str>) -> miette::Result<String> {
-    let prompt = if let Some(value) = file {
-        read_file(&value).inspect_err(|err| {
+/// Errors if `file`, `prompt` and `stdin` are all [`None`], or if `file` is `-` and `stdin` is
+/// [`None`].
+pub fn get_prompt<P: AsRef<Path>, R: Read>(
+    file: Option<P>,
+    prompt: Option<&str>,
+    stdin: Option<R>,
+) -> miette::Result<String> {
+    let prompt = match (file, prompt, stdin) {
+        (Some(value), _, stdin) if is_stdin_path(&value) => read_stdin(
+            stdin.ok_or_else(|| miette!("Standard input is not available to read the prompt"))?,
+        )?,
+        (Some(value), _, _) => read_file(&value).inspect_err(|err| {
             log::error!(
                 "Error reading prompt file (`{}`): {err:?}",
                 value.as_ref().display()
             );
-        })?
-    } else {
-        prompt
-            .ok_or_else(|| {
-                miette!("Supply a file containing the prompt text or the prompt as a string")
-            })?
-            .to_owned()
+        })?,
+        (None, Some(value), _) => value.to_owned(),
+        (None, None, Some(stdin)) => read_stdin(stdin)?,
+        (None, None, None) => {
+            bail!("Supply a file containing the prompt text or the prompt as a string")
+        }
     };
     if prompt.trim().is_empty() {
         bail!("Missing a prompt value");
     }
-    if prompt.len() > 20_048_000 {
+    if prompt.len() > MAX_PROMPT_BYTES {
         bail!("Prompt context is too large: {}!", prompt.len());
     }
 
@@ -39,14 +56,14 @@ pub fn get_prompt<P: AsRef<Path>>(file: Option<P>, prompt: Option<&str>) -> miet
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{io::Empty, path::PathBuf};
 
     use assert_fs::{
         TempDir,
         prelude::{FileWriteStr, PathChild},
     };
 
-    use crate::prompt::get_prompt;
+    use crate::prompt::{get_prompt, is_stdin_path};
 
     #[test]
     fn get_prompt_returns_prompt_for_file_input() {
@@ -57,7 +74,7 @@ mod tests {
         let temp_data_path = temp_dir.join("prompt.txt");
 
         // act
-        let outcome = get_prompt(Some(temp_data_path), None).unwrap();
+        let outcome = get_prompt(Some(temp_data_path), None, Option::<Empty>::None).unwrap();
 
         // assert
         assert_eq!(outcome, content);
@@ -72,7 +89,12 @@ mod tests {
         let input_prompt = "Why is the sky blue?";
 
         // act
-        let outcome = get_prompt(Option::<PathBuf>::None, Some(input_prompt)).unwrap();
+        let outcome = get_prompt(
+            Option::<PathBuf>::None,
+            Some(input_prompt),
+            Option::<Empty>::None,
+        )
+        .unwrap();
 
         // assert
         assert_eq!(outcome, input_prompt);
@@ -84,7 +106,12 @@ mod tests {
         let input_prompt = "";
 
         // act
-        let outcome = get_prompt(Option::<PathBuf>::None, Some(input_prompt)).unwrap_err();
+        let outcome = get_prompt(
+            Option::<PathBuf>::None,
+            Some(input_prompt),
+            Option::<Empty>::None,
+        )
+        .unwrap_err();
 
         // assert
         let mut chain = outcome.chain();
@@ -105,7 +132,12 @@ mod tests {
         let input_prompt = "Why is the sea blue?";
 
         // act
-        let outcome = get_prompt(Some(temp_data_path), Some(input_prompt)).unwrap();
+        let outcome = get_prompt(
+            Some(temp_data_path),
+            Some(input_prompt),
+            Option::<Empty>::None,
+        )
+        .unwrap();
 
         // assert
         assert_eq!(outcome, content);
@@ -122,7 +154,7 @@ mod tests {
         let _ = temp_dir.child("prompt.txt").write_str(content);
 
         // act
-        let outcome = get_prompt(Option::<PathBuf>::None, None).unwrap_err();
+        let outcome = get_prompt(Option::<PathBuf>::None, None, Option::<Empty>::None).unwrap_err();
 
         // assert
         let mut chain = outcome.chain();
@@ -132,4 +164,93 @@ mod tests {
         );
         assert!(chain.next().is_none());
     }
+
+    #[test]
+    fn get_prompt_returns_prompt_for_stdin_input() {
+        // arrange
+        let content = "Why is the sky blue?";
+
+        // act
+        let outcome = get_prompt(Option::<PathBuf>::None, None, Some(content.as_bytes())).unwrap();
+
+        // assert
+        assert_eq!(outcome, content);
+    }
+
+    #[test]
+    fn get_prompt_returns_stdin_input_for_dash_file() {
+        // arrange
+        let content = "Why is the sky blue?";
+        let input_prompt = "Why is the sea blue?";
+
+        // act
+        let outcome = get_prompt(
+            Some(PathBuf::from("-")),
+            Some(input_prompt),
+            Some(content.as_bytes()),
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!(outcome, content);
+    }
+
+    #[test]
+    fn get_prompt_returns_string_input_prompt_when_both_string_and_stdin_input_are_provided() {
+        // arrange
+        let content = "Why is the sky blue?";
+        let input_prompt = "Why is the sea blue?";
+
+        // act
+        let outcome = get_prompt(
+            Option::<PathBuf>::None,
+            Some(input_prompt),
+            Some(content.as_bytes()),
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!(outcome, input_prompt);
+    }
+
+    #[test]
+    fn get_prompt_handles_empty_stdin_input() {
+        // arrange
+        let content = "  \n";
+
+        // act
+        let outcome =
+            get_prompt(Option::<PathBuf>::None, None, Some(content.as_bytes())).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some("Missing a prompt value".to_owned())
+        );
+        assert!(chain.next().is_none());
+    }
+
+    #[test]
+    fn get_prompt_returns_error_for_dash_file_without_stdin() {
+        // act
+        let outcome =
+            get_prompt(Some(PathBuf::from("-")), None, Option::<Empty>::None).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some("Standard input is not available to read the prompt".to_owned())
+        );
+        assert!(chain.next().is_none());
+    }
+
+    #[test]
+    fn is_stdin_path_matches_dash_only() {
+        assert!(is_stdin_path("-"));
+        assert!(!is_stdin_path("--"));
+        assert!(!is_stdin_path("./-"));
+        assert!(!is_stdin_path("prompt.txt"));
+    }
 }
@@ -0,0 +1,971 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{AppError, RegistryError, UnknownModelError},
+    models::{ResolvedModel, model_name_suggestion},
+    tiktoken::{built_in_names, is_built_in, is_rank_file},
+    token::TokenizerSource,
+};
+
+/// Models built into the binary, so installed binaries do not depend on the source checkout.
+const BUILT_IN_MODELS: &str = include_str!("../data/models.json");
+
+/// Label for the built-in models in messages, in place of a file path.
+const BUILT_IN_LABEL: &str = "<built-in>";
+
+/// Name of the models file, in the user configuration directory and in project directories.
+const MODELS_FILENAME: &str = "models.json";
+
+/// A model, as listed in a models file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ModelEntry {
+    /// Name the model is selected by, on the command line.
+    pub name: String,
+
+    /// Hugging Face repo holding the model tokenizer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hf: Option<String>,
+
+    /// Local `tokenizer.json` file, or a directory holding one, used instead of a Hugging Face
+    /// repo.  Relative paths are relative to the directory holding the models file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+
+    /// Built-in tiktoken encoding, such as `o200k_base`, or a local `.tiktoken` rank file, used
+    /// instead of a Hugging Face repo.  Relative rank file paths are relative to the directory
+    /// holding the models file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiktoken: Option<String>,
+
+    /// Commit SHA, tag or branch of the Hugging Face repo to download the tokenizer from, instead
+    /// of `main`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+
+    /// Expected SHA-256 hash of the tokenizer file, as hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// Maximum number of tokens the model accepts, including the prompt and any output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<usize>,
+
+    /// Maximum number of tokens the model generates in a single response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<usize>,
+}
+
+impl ModelEntry {
+    /// Returns where the model tokenizer is loaded from, preferring a local path, then a tiktoken
+    /// encoding, over a Hugging Face repo.
+    ///
+    /// # Errors
+    /// Errors if the entry has no repo, path or tiktoken encoding.  Entries loaded from models
+    /// files are checked for one when loaded.
+    pub fn tokenizer_source(&self) -> Result<TokenizerSource, AppError> {
+        match (&self.path, &self.tiktoken, &self.hf) {
+            (Some(path), _, _) => Ok(TokenizerSource::Path(path.clone())),
+            (None, Some(encoding), _) => Ok(TokenizerSource::Tiktoken(encoding.clone())),
+            (None, None, Some(repo_id)) => Ok(TokenizerSource::Hub(repo_id.clone())),
+            (None, None, None) => {
+                Err(RegistryError::models_file(missing_source_detail(self)).into())
+            }
+        }
+    }
+
+    /// Returns the number of tokenizer sources the entry lists, which should be exactly one.
+    fn source_count(&self) -> usize {
+        [
+            self.hf.is_some(),
+            self.path.is_some(),
+            self.tiktoken.is_some(),
+        ]
+        .into_iter()
+        .filter(|&listed| listed)
+        .count()
+    }
+}
+
+/// Layer of the registry a models file belongs to.  Layers are listed from lowest to highest
+/// precedence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layer {
+    /// Models built into the binary.
+    BuiltIn,
+
+    /// Models file in the user configuration directory.
+    User,
+
+    /// Models file in the project directory.
+    Project,
+
+    /// Models file given with the `--models-file` flag.
+    ModelsFile,
+}
+
+impl Layer {
+    /// Returns the layer name, as shown in listings.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BuiltIn => "built-in",
+            Self::User => "user",
+            Self::Project => "project",
+            Self::ModelsFile => "models-file",
+        }
+    }
+}
+
+/// Where models are loaded from.  Each layer overrides models with the same name from earlier
+/// layers.
+#[derive(Debug, Default)]
+pub struct ModelSources {
+    /// Start from the models built into the binary.
+    pub built_in: bool,
+
+    /// User models file, which is skipped if it does not exist.
+    pub user_file: Option<PathBuf>,
+
+    /// Project models file, which is skipped if it does not exist.
+    pub project_file: Option<PathBuf>,
+
+    /// Models file given on the command line, which takes precedence over all other layers and
+    /// must exist.
+    pub models_file: Option<PathBuf>,
+}
+
+impl ModelSources {
+    /// Returns the default layers: built-in models, then the user models file, then the nearest
+    /// project models file and finally `models_file`, from the `--models-file` flag.
+    #[must_use]
+    pub fn new(models_file: Option<PathBuf>) -> Self {
+        Self {
+            built_in: true,
+            user_file: user_models_path(),
+            project_file: nearest_project_models_path(),
+            models_file,
+        }
+    }
+}
+
+/// Returns the path of the user models file, `$XDG_CONFIG_HOME/tokenator/models.json`, falling
+/// back to `~/.config/tokenator/models.json` when `XDG_CONFIG_HOME` is not set.
+pub fn user_models_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("tokenator").join(MODELS_FILENAME))
+}
+
+/// Returns the path of the project models file, `.tokenator/models.json`, in `directory`.
+#[must_use]
+pub fn project_models_path(directory: &Path) -> PathBuf {
+    directory.join(".tokenator").join(MODELS_FILENAME)
+}
+
+/// Returns the path of the nearest project models file, searching from the working directory up
+/// through its ancestors.
+fn nearest_project_models_path() -> Option<PathBuf> {
+    let working_directory = std::env::current_dir().ok()?;
+
+    working_directory
+        .ancestors()
+        .map(project_models_path)
+        .find(|path| path.is_file())
+}
+
+/// Returns the message for a model with no repo, path or tiktoken encoding.
+fn missing_source_detail(model: &ModelEntry) -> String {
+    format!(
+        "Model `{}` needs an `hf` repo, a local tokenizer `path` or a `tiktoken` encoding",
+        model.name
+    )
+}
+
+/// Parses models JSON content.
+///
+/// # Errors
+/// Errors if unable to parse the JSON content, or a model has no repo, path or tiktoken encoding.
+fn parse_models(data: &str) -> Result<Vec<ModelEntry>, String> {
+    let models: Vec<ModelEntry> = serde_json::from_str(data).map_err(|error| error.to_string())?;
+    if let Some(model) = models.iter().find(|model| model.source_count() == 0) {
+        return Err(missing_source_detail(model));
+    }
+
+    Ok(models)
+}
+
+/// Reads the models listed in a models JSON file (like `data/models.json`).
+///
+/// # Errors
+/// Errors if:
+/// - unable to read the file; or
+/// - unable to parse the JSON content.
+pub fn read_models_file<P: AsRef<Path>>(path: P) -> Result<Vec<ModelEntry>, AppError> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path).map_err(|error| {
+        RegistryError::models_file(format!(
+            "Unable to read models file `{}`: {error}",
+            path.display()
+        ))
+    })?;
+    let models = parse_models(&data).map_err(|error| {
+        RegistryError::models_file(format!("Invalid models file `{}`: {error}", path.display()))
+    })?;
+    if models.is_empty() {
+        log::warn!("Models file `{}` is empty", path.display());
+    }
+
+    Ok(models)
+}
+
+/// Writes models to a models JSON file, creating the parent directory if needed.
+///
+/// # Errors
+/// Errors if unable to create the directory or write the file.
+fn write_models_file(path: &Path, models: &[ModelEntry]) -> Result<(), AppError> {
+    let unwritable = |error: std::io::Error| RegistryError {
+        advice: "Check the directory exists and is writable, or pass another `--models-file`"
+            .to_owned(),
+        detail: format!("Unable to write models file `{}`: {error}", path.display()),
+    };
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(unwritable)?;
+    }
+    let mut data = serde_json::to_string_pretty(models).expect("models should serialise to JSON");
+    data.push('\n');
+
+    Ok(fs::write(path, data).map_err(unwritable)?)
+}
+
+/// Adds a model to a models file, creating the file if it does not exist.  A model with the same
+/// name already in the file is replaced.
+///
+/// # Returns
+/// A `Result` containing `true` if an existing model was replaced.
+///
+/// # Errors
+/// Errors if unable to read, parse or write the models file.
+pub fn add_model(path: &Path, model: ModelEntry) -> Result<bool, AppError> {
+    let mut models = if path.exists() {
+        read_models_file(path)?
+    } else {
+        Vec::new()
+    };
+    let replaced = if let Some(existing) = models.iter_mut().find(|entry| entry.name == model.name)
+    {
+        *existing = model;
+        true
+    } else {
+        models.push(model);
+        false
+    };
+    write_models_file(path, &models)?;
+
+    Ok(replaced)
+}
+
+/// Removes a model from a models file.
+///
+/// # Errors
+/// Errors if:
+/// - unable to read, parse or write the models file; or
+/// - the models file does not list a model named `name`.
+pub fn remove_model(path: &Path, name: &str) -> Result<(), AppError> {
+    if !path.exists() {
+        return Err(RegistryError {
+            advice: "Check the path, or pass `--project` for the project models file".to_owned(),
+            detail: format!("Models file `{}` does not exist", path.display()),
+        }
+        .into());
+    }
+    let mut models = read_models_file(path)?;
+    let count = models.len();
+    models.retain(|model| model.name != name);
+    if models.len() == count {
+        return Err(RegistryError {
+            advice: "Run `tokenator models list` to see which file lists the model".to_owned(),
+            detail: format!(
+                "No model named `{name}` in models file `{}`",
+                path.display()
+            ),
+        }
+        .into());
+    }
+
+    write_models_file(path, &models)
+}
+
+/// Models loaded from a single registry layer.
+#[derive(Debug)]
+pub struct RegistryFile {
+    pub layer: Layer,
+
+    /// Path of the models file, which is [`None`] for built-in models.
+    pub path: Option<PathBuf>,
+
+    /// Models, in the order listed in the file.
+    pub models: Vec<ModelEntry>,
+}
+
+impl RegistryFile {
+    /// Returns the file path, or a placeholder for built-in models, for use in messages.
+    #[must_use]
+    pub fn label(&self) -> String {
+        self.path.as_ref().map_or_else(
+            || BUILT_IN_LABEL.to_owned(),
+            |path| path.display().to_string(),
+        )
+    }
+}
+
+/// A model in the registry, along with the layer it comes from.
+#[derive(Debug)]
+pub struct RegisteredModel<'a> {
+    pub entry: &'a ModelEntry,
+    pub file: &'a RegistryFile,
+}
+
+/// Every model available, loaded from each layer of models files.
+#[derive(Debug)]
+pub struct Registry {
+    /// Models from each layer, lowest precedence first.
+    pub files: Vec<RegistryFile>,
+}
+
+impl Registry {
+    /// Loads models from each layer in `sources`.
+    ///
+    /// # Errors
+    /// Errors if unable to read or parse any of the models files.
+    ///
+    /// # Panics
+    /// Panics if the models built into the binary are not valid, which the tests check.
+    pub fn load(sources: &ModelSources) -> Result<Self, AppError> {
+        let mut files = Vec::new();
+        if sources.built_in {
+            files.push(RegistryFile {
+                layer: Layer::BuiltIn,
+                path: None,
+                models: parse_models(BUILT_IN_MODELS).expect("built-in models should be valid"),
+            });
+        }
+        let optional_files = [
+            (Layer::User, &sources.user_file),
+            (Layer::Project, &sources.project_file),
+        ]
+        .into_iter()
+        .filter_map(|(layer, path)| Some((layer, path.as_ref()?)))
+        .filter(|(_, path)| path.is_file());
+        let models_file = sources
+            .models_file
+            .as_ref()
+            .map(|path| (Layer::ModelsFile, path));
+        for (layer, path) in optional_files.chain(models_file) {
+            log::debug!("Loading {} models from `{}`", layer.name(), path.display());
+            let mut models = read_models_file(path)?;
+            let directory = path.parent().unwrap_or(Path::new(""));
+            for tokeniser_path in models.iter_mut().filter_map(|model| model.path.as_mut()) {
+                if tokeniser_path.is_relative() {
+                    *tokeniser_path = directory.join(&*tokeniser_path);
+                }
+            }
+            for rank_file in models
+                .iter_mut()
+                .filter_map(|model| model.tiktoken.as_mut())
+                .filter(|encoding| is_rank_file(Path::new(encoding)))
+                .filter(|rank_file| Path::new(rank_file).is_relative())
+            {
+                *rank_file = directory.join(&*rank_file).display().to_string();
+            }
+            files.push(RegistryFile {
+                layer,
+                path: Some(path.clone()),
+                models,
+            });
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Returns every model, sorted by name, with models from higher precedence layers replacing
+    /// models with the same name from lower ones.
+    #[must_use]
+    pub fn models(&self) -> Vec<RegisteredModel<'_>> {
+        let mut models: HashMap<&str, RegisteredModel, ahash::RandomState> = HashMap::default();
+        for file in &self.files {
+            for entry in &file.models {
+                models.insert(&entry.name, RegisteredModel { entry, file });
+            }
+        }
+        let mut models: Vec<RegisteredModel> = models.into_values().collect();
+        models.sort_by(|model_a, model_b| model_a.entry.name.cmp(&model_b.entry.name));
+
+        models
+    }
+
+    /// Returns every model, keyed by name, with models from higher precedence layers replacing
+    /// models with the same name from lower ones.
+    #[must_use]
+    pub fn model_name_map(&self) -> HashMap<String, ModelEntry, ahash::RandomState> {
+        self.files
+            .iter()
+            .flat_map(|file| &file.models)
+            .map(|model| (model.name.clone(), model.clone()))
+            .collect()
+    }
+
+    /// Finds the model called `name`, with models from higher precedence layers replacing models
+    /// with the same name from lower ones.
+    ///
+    /// # Errors
+    /// Errors if no model is called `name`, suggesting the closest match.
+    pub fn resolve(&self, name: &str) -> Result<ResolvedModel, AppError> {
+        self.files
+            .iter()
+            .rev()
+            .flat_map(|file| &file.models)
+            .find(|model| model.name == name)
+            .ok_or_else(|| {
+                let model_name_map = self.model_name_map();
+                AppError::from(UnknownModelError::new(
+                    name,
+                    model_name_suggestion(&model_name_map, name),
+                ))
+            })
+            .and_then(ResolvedModel::try_from)
+    }
+
+    /// Returns labels for each layer loaded, for use in messages.
+    pub fn labels(&self) -> Vec<String> {
+        self.files.iter().map(RegistryFile::label).collect()
+    }
+}
+
+/// A mistake in a models file entry.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// Name of the model, as listed in the models file.
+    pub name: String,
+
+    /// Label for the models file the model is listed in.
+    pub source: String,
+
+    pub message: String,
+}
+
+/// Returns `true` for Hugging Face repo IDs of the form `owner/name`.
+fn is_valid_repo_id(repo_id: &str) -> bool {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_' | '.'))
+    };
+
+    matches!(repo_id.split_once('/'), Some((owner, name)) if valid_part(owner) && valid_part(name))
+}
+
+/// Returns `true` for a SHA-256 hash written as 64 hexadecimal characters.
+fn is_valid_sha256(sha256: &str) -> bool {
+    sha256.len() == 64
+        && sha256
+            .chars()
+            .all(|character| character.is_ascii_hexdigit())
+}
+
+/// Checks a single models file entry for mistakes.
+fn entry_problems(entry: &ModelEntry) -> Vec<String> {
+    let mut problems = Vec::new();
+    if entry.name.trim().is_empty() {
+        problems.push("Name is empty".to_owned());
+    } else if entry.name.trim() != entry.name {
+        problems.push("Name has leading or trailing whitespace".to_owned());
+    } else if entry.name.chars().any(char::is_whitespace) {
+        problems.push("Name contains whitespace".to_owned());
+    }
+    if let Some(repo_id) = entry
+        .hf
+        .as_ref()
+        .filter(|repo_id| !is_valid_repo_id(repo_id))
+    {
+        problems.push(format!(
+            "Repo `{repo_id}` is not a valid Hugging Face repo ID, of the form `owner/name`"
+        ));
+    }
+    if entry.revision.is_some() && entry.hf.is_none() {
+        problems.push("Has a `revision`, which is only used with an `hf` repo".to_owned());
+    }
+    if entry
+        .revision
+        .as_ref()
+        .is_some_and(|revision| revision.trim().is_empty())
+    {
+        problems.push("Revision is empty".to_owned());
+    }
+    if let Some(sha256) = entry
+        .sha256
+        .as_ref()
+        .filter(|sha256| !is_valid_sha256(sha256))
+    {
+        problems.push(format!(
+            "SHA-256 hash `{sha256}` is not 64 hexadecimal characters"
+        ));
+    }
+    if entry.source_count() > 1
+        && let Ok(source) = entry.tokenizer_source()
+    {
+        problems.push(format!(
+            "Has more than one of an `hf` repo, a local `path` and a `tiktoken` encoding, and only \
+            the {} is used",
+            match source {
+                TokenizerSource::Path(_) => "path",
+                TokenizerSource::Tiktoken(_) => "tiktoken encoding",
+                TokenizerSource::Hub(_) => "repo",
+            }
+        ));
+    }
+    if let Some(encoding) = entry
+        .tiktoken
+        .as_ref()
+        .filter(|encoding| !is_built_in(encoding) && !is_rank_file(Path::new(encoding)))
+    {
+        problems.push(format!(
+            "`{encoding}` is not a built-in tiktoken encoding ({}) or a `.tiktoken` rank file",
+            built_in_names()
+        ));
+    }
+    if entry.sha256.is_some() && entry.tiktoken.as_deref().is_some_and(is_built_in) {
+        problems.push(
+            "Has a `sha256`, which is not checked for built-in tiktoken encodings".to_owned(),
+        );
+    }
+    if entry.context_length == Some(0) {
+        problems.push("Context length is zero".to_owned());
+    }
+    if entry.max_output_tokens == Some(0) {
+        problems.push("Maximum output tokens is zero".to_owned());
+    }
+    if let (Some(context_length), Some(max_output_tokens)) =
+        (entry.context_length, entry.max_output_tokens)
+        && max_output_tokens > context_length
+    {
+        problems.push(format!(
+            "Maximum output tokens ({max_output_tokens}) is more than the context length \
+            ({context_length})"
+        ));
+    }
+
+    problems
+}
+
+/// Checks every entry in every layer of the registry for mistakes, such as names with stray
+/// whitespace, malformed repo IDs and names listed more than once in the same file.  Does not
+/// check the repos exist.
+#[must_use]
+pub fn check_entries(registry: &Registry) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for file in &registry.files {
+        for (index, entry) in file.models.iter().enumerate() {
+            let problem = |message| Problem {
+                name: entry.name.clone(),
+                source: file.label(),
+                message,
+            };
+            problems.extend(entry_problems(entry).into_iter().map(problem));
+            if file.models[..index]
+                .iter()
+                .any(|earlier| earlier.name == entry.name)
+            {
+                problems.push(problem("Name is listed more than once".to_owned()));
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteStr, PathChild},
+    };
+
+    use crate::{
+        errors::AppError,
+        models::ResolvedModel,
+        registry::{
+            Layer, ModelEntry, ModelSources, Problem, Registry, add_model, check_entries,
+            read_models_file, remove_model,
+        },
+        token::TokenizerSource,
+    };
+
+    fn model(name: &str, hf: &str) -> ModelEntry {
+        ModelEntry {
+            name: name.to_owned(),
+            hf: Some(hf.to_owned()),
+            path: None,
+            tiktoken: None,
+            revision: None,
+            sha256: None,
+            context_length: None,
+            max_output_tokens: None,
+        }
+    }
+
+    #[test]
+    fn read_models_file_generates_expected_output_from_valid_input() {
+        // arrange
+        let content = r#"[
+  {
+    "name": "example:latest",
+    "hf": "example/Example-1-M-state-of-the-art"
+  },
+  {
+    "name": "example:100b",
+    "hf": "example/Example-100-B-most-capable",
+    "context_length": 131072
+  }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = read_models_file(temp_data_path).unwrap();
+
+        // assert
+        insta::assert_json_snapshot!(outcome);
+    }
+
+    #[test]
+    fn read_models_file_returns_error_if_json_is_not_valid() {
+        // arrange
+        let content = r#"[
+  {
+    "name": "example:latest",
+    "hf": "example/Example-1-M-state-of-the-art"
+  },
+  {
+    "name": "example:100b",
+    "hugging face": "example/Example-100-B-most-capable"
+  }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = read_models_file(&temp_data_path).unwrap_err();
+
+        // assert
+        assert!(matches!(outcome, AppError::Registry(_)));
+        assert_eq!(
+            format!("{outcome}"),
+            format!(
+                "Invalid models file `{}`: Model `example:100b` needs an `hf` repo, a local \
+                tokenizer `path` or a `tiktoken` encoding",
+                temp_data_path.display()
+            )
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn read_models_file_handles_empty_input() {
+        // arrange
+        let content = "[ ]";
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = read_models_file(temp_data_path).unwrap();
+
+        // assert
+        assert!(outcome.is_empty());
+    }
+
+    #[test]
+    fn resolved_model_returns_error_for_entry_without_a_tokenizer_source() {
+        // arrange
+        let entry = ModelEntry {
+            hf: None,
+            ..model("example:latest", "example/Example-1-M-state-of-the-art")
+        };
+
+        // act
+        let outcome = ResolvedModel::try_from(&entry).unwrap_err();
+
+        // assert
+        assert!(matches!(outcome, AppError::Registry(_)));
+        assert_eq!(
+            outcome.to_string(),
+            "Model `example:latest` needs an `hf` repo, a local tokenizer `path` or a `tiktoken` \
+            encoding"
+        );
+    }
+
+    #[test]
+    fn models_lists_each_model_once_from_the_highest_precedence_layer() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("user.json").write_str(
+            r#"[{ "name": "qwen3:8b", "hf": "example/Qwen3-8B-mirror" },
+                { "name": "internal:8b", "hf": "example/Internal-8B" }]"#,
+        );
+        let sources = ModelSources {
+            built_in: true,
+            user_file: Some(temp_dir.join("user.json")),
+            project_file: Some(temp_dir.join("missing.json")),
+            models_file: None,
+        };
+
+        // act
+        let registry = Registry::load(&sources).unwrap();
+        let models = registry.models();
+
+        // assert
+        let summary: Vec<(&str, Option<&str>, Layer)> = models
+            .iter()
+            .filter(|model| {
+                model.entry.name.starts_with("qwen3:") || model.entry.name == "internal:8b"
+            })
+            .map(|model| {
+                (
+                    model.entry.name.as_str(),
+                    model.entry.hf.as_deref(),
+                    model.file.layer,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("internal:8b", Some("example/Internal-8B"), Layer::User),
+                ("qwen3:1.7b", Some("Qwen/Qwen3-1.7B"), Layer::BuiltIn),
+                ("qwen3:8b", Some("example/Qwen3-8B-mirror"), Layer::User),
+            ]
+        );
+        assert_eq!(registry.files.len(), 2);
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn load_resolves_local_paths_relative_to_the_models_file() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child(".tokenator/models.json").write_str(
+            r#"[{ "name": "air-gapped", "path": "../models/air-gapped" },
+                { "name": "absolute", "path": "/opt/models/absolute/tokenizer.json" },
+                { "name": "gpt-4o", "tiktoken": "o200k_base" },
+                { "name": "custom", "tiktoken": "ranks/custom.tiktoken" }]"#,
+        );
+        let sources = ModelSources {
+            project_file: Some(temp_dir.join(".tokenator/models.json")),
+            ..ModelSources::default()
+        };
+
+        // act
+        let registry = Registry::load(&sources).unwrap();
+
+        // assert
+        let sources: Vec<TokenizerSource> = registry.files[0]
+            .models
+            .iter()
+            .map(ModelEntry::tokenizer_source)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                TokenizerSource::Path(temp_dir.join(".tokenator/../models/air-gapped")),
+                TokenizerSource::Path(PathBuf::from("/opt/models/absolute/tokenizer.json")),
+                TokenizerSource::Tiktoken("o200k_base".to_owned()),
+                TokenizerSource::Tiktoken(
+                    temp_dir
+                        .join(".tokenator/ranks/custom.tiktoken")
+                        .display()
+                        .to_string()
+                ),
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn add_model_creates_file_and_replaces_models_with_the_same_name() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.join("config/tokenator/models.json");
+
+        // act
+        let first = add_model(&path, model("internal:8b", "example/Internal-8B")).unwrap();
+        let second = add_model(&path, model("other:1b", "example/Other-1B")).unwrap();
+        let third = add_model(
+            &path,
+            ModelEntry {
+                context_length: Some(8_192),
+                ..model("internal:8b", "example/Internal-8B-v2")
+            },
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!((first, second, third), (false, false, true));
+        assert_eq!(
+            read_models_file(&path).unwrap(),
+            vec![
+                ModelEntry {
+                    context_length: Some(8_192),
+                    ..model("internal:8b", "example/Internal-8B-v2")
+                },
+                model("other:1b", "example/Other-1B"),
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn remove_model_returns_error_for_models_not_in_the_file() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.join("models.json");
+        add_model(&path, model("internal:8b", "example/Internal-8B")).unwrap();
+
+        // act
+        remove_model(&path, "internal:8b").unwrap();
+        let outcome = remove_model(&path, "internal:8b").unwrap_err();
+
+        // assert
+        assert!(read_models_file(&path).unwrap().is_empty());
+        assert_eq!(
+            format!("{outcome}"),
+            format!(
+                "No model named `internal:8b` in models file `{}`",
+                path.display()
+            )
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn check_entries_finds_mistakes_in_models_files() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(
+            r#"[
+  { "name": "mistral-nemo:12b ", "hf": "mistralai/Mistral-Nemo-Instruct-2407" },
+  { "name": "example", "hf": "https://huggingface.co/example/Example" },
+  { "name": "example", "hf": "example/Example", "context_length": 4096, "max_output_tokens": 8192 },
+  { "name": "local", "path": "tokenizer.json", "revision": "v1.0", "sha256": "abc123" },
+  { "name": "gpt", "hf": "openai/gpt", "tiktoken": "o300k_base" },
+  { "name": "gpt-4o", "tiktoken": "o200k_base", "sha256": "0000000000000000000000000000000000000000000000000000000000000000" }
+]"#,
+        );
+        let path = temp_dir.join("models.json");
+        let sources = ModelSources {
+            models_file: Some(path.clone()),
+            ..ModelSources::default()
+        };
+        let registry = Registry::load(&sources).unwrap();
+
+        // act
+        let outcome = check_entries(&registry);
+
+        // assert
+        let problem = |name: &str, message: &str| Problem {
+            name: name.to_owned(),
+            source: path.display().to_string(),
+            message: message.to_owned(),
+        };
+        assert_eq!(
+            outcome,
+            vec![
+                problem(
+                    "mistral-nemo:12b ",
+                    "Name has leading or trailing whitespace"
+                ),
+                problem(
+                    "example",
+                    "Repo `https://huggingface.co/example/Example` is not a valid Hugging Face \
+                        repo ID, of the form `owner/name`"
+                ),
+                problem(
+                    "example",
+                    "Maximum output tokens (8192) is more than the context length (4096)"
+                ),
+                problem("example", "Name is listed more than once"),
+                problem(
+                    "local",
+                    "Has a `revision`, which is only used with an `hf` repo"
+                ),
+                problem(
+                    "local",
+                    "SHA-256 hash `abc123` is not 64 hexadecimal characters"
+                ),
+                problem(
+                    "gpt",
+                    "Has more than one of an `hf` repo, a local `path` and a `tiktoken` encoding, \
+                        and only the tiktoken encoding is used"
+                ),
+                problem(
+                    "gpt",
+                    "`o300k_base` is not a built-in tiktoken encoding (`o200k_base`, \
+                        `cl100k_base`, `p50k_base`, `r50k_base`) or a `.tiktoken` rank file"
+                ),
+                problem(
+                    "gpt-4o",
+                    "Has a `sha256`, which is not checked for built-in tiktoken encodings"
+                ),
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn check_entries_finds_no_mistakes_in_built_in_models() {
+        // arrange
+        let sources = ModelSources {
+            built_in: true,
+            ..ModelSources::default()
+        };
+        let registry = Registry::load(&sources).unwrap();
+
+        // act
+        let outcome = check_entries(&registry);
+
+        // assert
+        assert_eq!(outcome, Vec::new());
+    }
+}
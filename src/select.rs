@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+
+use inquire::Select;
+use miette::{Context, IntoDiagnostic, bail};
+use tokenator::{
+    models::ResolvedModel,
+    registry::{ModelEntry, ModelSources, Registry},
+};
+
+/// Prompts the user to select a model name from a list.
+///
+/// # Returns
+/// A `miette::Result` containing the user-selected model.
+fn get_user_selected_repo_id(
+    model_name_map: &HashMap<String, ModelEntry, ahash::RandomState>,
+) -> miette::Result<ResolvedModel> {
+    debug_assert!(!model_name_map.is_empty());
+    let mut options: Vec<&String> = model_name_map.keys().collect();
+    options.sort();
+
+    let choice = Select::new("Which model are you using?", options)
+        .prompt()
+        .into_diagnostic()
+        .wrap_err("Getting user model choice")?;
+
+    let model = model_name_map
+        .get(choice)
+        .expect("Choice should be in the model name map");
+
+    Ok(ResolvedModel::try_from(model)?)
+}
+
+/// Retrieves the repository IDs based on the model names.  Prompts the user to pick a model if no
+/// names are given, and returns every model, sorted by name, if `all_models` is `true`.
+///
+/// # Returns
+/// A `miette::Result` containing the model names and repository IDs, in the order given.  Makes a
+/// suggestion if a given model name does not exist.
+///
+/// # Errors
+/// Errors if:
+/// - unable to read or parse any of the models files; or
+/// - any of `model_names` does not match any existing models.
+pub fn get_repo_ids(
+    model_names: &[String],
+    all_models: bool,
+    sources: &ModelSources,
+) -> miette::Result<Vec<ResolvedModel>> {
+    let registry = Registry::load(sources)?;
+    let model_name_map = registry.model_name_map();
+    if model_name_map.is_empty() {
+        bail!(
+            "Error: no models in model file `{}`",
+            registry.labels().join("`, `")
+        );
+    }
+    if all_models {
+        let mut models = model_name_map
+            .values()
+            .map(ResolvedModel::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        models.sort_by(|model_a, model_b| model_a.name.cmp(&model_b.name));
+
+        return Ok(models);
+    }
+    if model_names.is_empty() {
+        // No model name provided; prompt the user for model name
+        return Ok(vec![get_user_selected_repo_id(&model_name_map)?]);
+    }
+
+    let mut models: Vec<ResolvedModel> = Vec::with_capacity(model_names.len());
+    for model_name in model_names {
+        if !models.iter().any(|model| &model.name == model_name) {
+            models.push(registry.resolve(model_name)?);
+        }
+    }
+
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteStr, PathChild},
+    };
+    use tokenator::{
+        models::ResolvedModel,
+        registry::ModelSources,
+        token::{TokenizerPin, TokenizerSource},
+    };
+
+    use crate::select::get_repo_ids;
+
+    #[test]
+    fn get_repo_ids_generates_expected_result_with_valid_input() {
+        // arrange
+        let content = r#"[
+  {
+    "name": "example-model",
+    "hf": "example/Example-Model",
+    "context_length": 32768,
+    "max_output_tokens": 8192
+  },
+  {
+    "name": "nothing-to-do-with-the-other-one",
+    "hf": "example/TheOtherExample"
+  }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = get_repo_ids(
+            &[String::from("example-model")],
+            false,
+            &ModelSources {
+                models_file: Some(temp_data_path),
+                ..ModelSources::default()
+            },
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![ResolvedModel {
+                name: "example-model".to_owned(),
+                tokenizer: TokenizerSource::Hub("example/Example-Model".to_owned()),
+                pin: TokenizerPin::default(),
+                context_length: Some(32_768),
+                max_output_tokens: Some(8_192),
+            }]
+        );
+    }
+
+    #[test]
+    fn get_repo_ids_generates_expected_error_with_invalid_input() {
+        // arrange
+        let content = r#"[
+  {
+    "name": "example-model",
+    "hf": "example/Example-Model"
+  },
+  {
+    "name": "nothing-to-do-with-the-other-one",
+    "hf": "example/TheOtherExample"
+  }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = get_repo_ids(
+            &[String::from("example-modal")],
+            false,
+            &ModelSources {
+                models_file: Some(temp_data_path),
+                ..ModelSources::default()
+            },
+        )
+        .unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some("No model matching `example-modal`, did you mean `example-model`?".to_owned())
+        );
+        assert!(chain.next().is_none());
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn get_repo_ids_generates_expected_error_with_empty_model_name_map() {
+        // arrange
+        let content = "[ ]";
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = get_repo_ids(
+            &[String::from("example-modal")],
+            false,
+            &ModelSources {
+                models_file: Some(temp_data_path.clone()),
+                ..ModelSources::default()
+            },
+        )
+        .unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some(format!(
+                "Error: no models in model file `{}`",
+                temp_data_path.display()
+            ))
+        );
+        assert!(chain.next().is_none());
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn get_repo_ids_returns_each_named_model_once_in_order() {
+        // arrange
+        let content = r#"[
+  { "name": "example-model", "hf": "example/Example-Model" },
+  { "name": "example-model:7b", "hf": "example/Example-7-B" },
+  { "name": "nothing-to-do-with-the-other-one", "hf": "example/TheOtherExample" }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+        let model_names = vec![
+            String::from("example-model:7b"),
+            String::from("example-model"),
+            String::from("example-model:7b"),
+        ];
+
+        // act
+        let outcome = get_repo_ids(
+            &model_names,
+            false,
+            &ModelSources {
+                models_file: Some(temp_data_path),
+                ..ModelSources::default()
+            },
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![
+                ResolvedModel {
+                    name: "example-model:7b".to_owned(),
+                    tokenizer: TokenizerSource::Hub("example/Example-7-B".to_owned()),
+                    pin: TokenizerPin::default(),
+                    context_length: None,
+                    max_output_tokens: None,
+                },
+                ResolvedModel {
+                    name: "example-model".to_owned(),
+                    tokenizer: TokenizerSource::Hub("example/Example-Model".to_owned()),
+                    pin: TokenizerPin::default(),
+                    context_length: None,
+                    max_output_tokens: None,
+                },
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn get_repo_ids_returns_all_models_sorted_by_name() {
+        // arrange
+        let content = r#"[
+  { "name": "nothing-to-do-with-the-other-one", "hf": "example/TheOtherExample" },
+  { "name": "example-model", "hf": "example/Example-Model" }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("models.json").write_str(content);
+        let temp_data_path = temp_dir.join("models.json");
+
+        // act
+        let outcome = get_repo_ids(
+            &[],
+            true,
+            &ModelSources {
+                models_file: Some(temp_data_path),
+                ..ModelSources::default()
+            },
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!(
+            outcome
+                .iter()
+                .map(|model| model.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["example-model", "nothing-to-do-with-the-other-one"]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn get_repo_ids_includes_built_in_models() {
+        // arrange
+        let sources = ModelSources {
+            built_in: true,
+            ..ModelSources::default()
+        };
+
+        // act
+        let outcome = get_repo_ids(&[String::from("qwen3:8b")], false, &sources).unwrap();
+
+        // assert
+        assert_eq!(outcome[0].repo_id(), Some("Qwen/Qwen3-8B"));
+    }
+
+    #[test]
+    fn get_repo_ids_overrides_models_by_name_from_later_layers() {
+        // arrange
+        let user_content = r#"[
+  { "name": "example-model", "hf": "example/Example-Model" },
+  { "name": "internal-fine-tune", "hf": "example/Internal-Fine-Tune" }
+]
+"#;
+        let models_file_content = r#"[
+  { "name": "example-model", "hf": "example/Example-Model-v2", "context_length": 8192 }
+]
+"#;
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("user.json").write_str(user_content);
+        let _ = temp_dir.child("flag.json").write_str(models_file_content);
+        let sources = ModelSources {
+            built_in: true,
+            user_file: Some(temp_dir.join("user.json")),
+            project_file: Some(temp_dir.join("missing.json")),
+            models_file: Some(temp_dir.join("flag.json")),
+        };
+        let model_names = vec![
+            String::from("example-model"),
+            String::from("internal-fine-tune"),
+            String::from("qwen3:8b"),
+        ];
+
+        // act
+        let outcome = get_repo_ids(&model_names, false, &sources).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome
+                .iter()
+                .map(|model| (model.repo_id(), model.context_length))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some("example/Example-Model-v2"), Some(8_192)),
+                (Some("example/Internal-Fine-Tune"), None),
+                (Some("Qwen/Qwen3-8B"), Some(32_768)),
+            ]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn get_repo_ids_returns_error_for_missing_models_file() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let models_file = temp_dir.join("missing.json");
+        let sources = ModelSources {
+            built_in: true,
+            models_file: Some(models_file.clone()),
+            ..ModelSources::default()
+        };
+
+        // act
+        let outcome = get_repo_ids(&[String::from("qwen3:8b")], false, &sources).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert!(chain.next().is_some_and(|val| {
+            format!("{val}").starts_with(&format!(
+                "Unable to read models file `{}`: ",
+                models_file.display()
+            ))
+        }));
+        assert!(chain.next().is_none());
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,515 @@
+use ahash::AHashMap;
+use tokenizers::{
+    AddedToken, DecoderWrapper, ModelWrapper, NormalizerWrapper, Tokenizer,
+    decoders::{byte_fallback::ByteFallback, fuse::Fuse, sequence::Sequence as DecoderSequence},
+    models::{bpe::BPE, unigram::Unigram},
+    normalizers::{
+        Precompiled, Replace, Sequence as NormalizerSequence, Strip, replace::ReplacePattern,
+    },
+    pre_tokenizers::metaspace::{Metaspace, PrependScheme},
+    processors::template::TemplateProcessing,
+};
+
+/// Character SentencePiece uses in place of spaces.
+const SPACE_REPLACEMENT: char = '▁';
+
+/// Piece types, as numbered in the SentencePiece `ModelProto` schema.
+const PIECE_TYPE_NORMAL: u64 = 1;
+const PIECE_TYPE_CONTROL: u64 = 3;
+const PIECE_TYPE_USER_DEFINED: u64 = 4;
+
+/// Segmentation algorithm a SentencePiece model was trained with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ModelType {
+    Unigram,
+    Bpe,
+}
+
+/// A single vocabulary entry from a SentencePiece model.
+#[derive(Clone, Debug, PartialEq)]
+struct Piece {
+    text: String,
+    score: f32,
+    kind: u64,
+}
+
+/// The parts of a SentencePiece `ModelProto` needed to rebuild the tokenizer.
+#[derive(Debug, PartialEq)]
+struct SentencePieceModel {
+    pieces: Vec<Piece>,
+    model_type: ModelType,
+    byte_fallback: bool,
+    unk_id: usize,
+    bos_id: Option<usize>,
+    eos_id: Option<usize>,
+    precompiled_charsmap: Vec<u8>,
+    add_dummy_prefix: bool,
+    remove_extra_whitespaces: bool,
+}
+
+/// A value read from a protobuf message.
+enum Field<'a> {
+    Varint(u64),
+    Fixed32(u32),
+    Bytes(&'a [u8]),
+}
+
+/// Minimal reader for the protobuf wire format, covering the field types SentencePiece models
+/// use.
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn read_varint(&mut self) -> Result<u64, String> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or("Unexpected end of data in varint")?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err("Varint is longer than 64 bits".to_owned())
+    }
+
+    fn read_slice(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or("Field is longer than the remaining data")?;
+        let slice = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(slice)
+    }
+
+    /// Reads the next field number and value, or returns [`None`] at the end of the message.
+    /// `fixed64` fields are skipped, since no field tokenator reads uses them.
+    fn next_field(&mut self) -> Result<Option<(u64, Field<'a>)>, String> {
+        loop {
+            if self.position >= self.data.len() {
+                return Ok(None);
+            }
+            let key = self.read_varint()?;
+            let number = key >> 3;
+            let field = match key & 0x7 {
+                0 => Field::Varint(self.read_varint()?),
+                1 => {
+                    self.read_slice(8)?;
+                    continue;
+                }
+                2 => {
+                    let length = usize::try_from(self.read_varint()?)
+                        .map_err(|_| "Field length does not fit in memory")?;
+                    Field::Bytes(self.read_slice(length)?)
+                }
+                5 => {
+                    let bytes = self.read_slice(4)?;
+                    Field::Fixed32(u32::from_le_bytes(
+                        bytes.try_into().expect("slice should be four bytes long"),
+                    ))
+                }
+                wire_type => return Err(format!("Unsupported protobuf wire type {wire_type}")),
+            };
+
+            return Ok(Some((number, field)));
+        }
+    }
+}
+
+/// Converts an `int32` id field, where negative values mean the token is not used.
+fn optional_id(value: u64) -> Option<usize> {
+    // Negative `int32` values are sign-extended to ten byte varints
+    i32::try_from(value.cast_signed())
+        .ok()
+        .and_then(|id| usize::try_from(id).ok())
+}
+
+fn parse_piece(data: &[u8]) -> Result<Piece, String> {
+    let mut piece = Piece {
+        text: String::new(),
+        score: 0.0,
+        kind: PIECE_TYPE_NORMAL,
+    };
+    let mut reader = ProtoReader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match (number, field) {
+            (1, Field::Bytes(bytes)) => {
+                piece.text = String::from_utf8(bytes.to_vec())
+                    .map_err(|_| "Piece is not valid UTF-8".to_owned())?;
+            }
+            (2, Field::Fixed32(bits)) => piece.score = f32::from_bits(bits),
+            (3, Field::Varint(kind)) => piece.kind = kind,
+            _ => {}
+        }
+    }
+
+    Ok(piece)
+}
+
+/// Parses a serialised SentencePiece `ModelProto`, as saved in `tokenizer.model` files.
+fn parse_model(data: &[u8]) -> Result<SentencePieceModel, String> {
+    let mut model = SentencePieceModel {
+        pieces: Vec::new(),
+        model_type: ModelType::Unigram,
+        byte_fallback: false,
+        unk_id: 0,
+        bos_id: Some(1),
+        eos_id: Some(2),
+        precompiled_charsmap: Vec::new(),
+        add_dummy_prefix: true,
+        remove_extra_whitespaces: true,
+    };
+    let mut reader = ProtoReader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match (number, field) {
+            (1, Field::Bytes(bytes)) => model.pieces.push(parse_piece(bytes)?),
+            (2, Field::Bytes(bytes)) => {
+                let mut trainer = ProtoReader::new(bytes);
+                while let Some((number, field)) = trainer.next_field()? {
+                    match (number, field) {
+                        (3, Field::Varint(1)) => model.model_type = ModelType::Unigram,
+                        (3, Field::Varint(2)) => model.model_type = ModelType::Bpe,
+                        (3, Field::Varint(other)) => {
+                            return Err(format!("Unsupported SentencePiece model type {other}"));
+                        }
+                        (35, Field::Varint(value)) => model.byte_fallback = value != 0,
+                        (40, Field::Varint(value)) => {
+                            model.unk_id =
+                                optional_id(value).ok_or("Model has no unknown token")?;
+                        }
+                        (41, Field::Varint(value)) => model.bos_id = optional_id(value),
+                        (42, Field::Varint(value)) => model.eos_id = optional_id(value),
+                        _ => {}
+                    }
+                }
+            }
+            (3, Field::Bytes(bytes)) => {
+                let mut normalizer = ProtoReader::new(bytes);
+                while let Some((number, field)) = normalizer.next_field()? {
+                    match (number, field) {
+                        (2, Field::Bytes(charsmap)) => {
+                            model.precompiled_charsmap = charsmap.to_vec();
+                        }
+                        (3, Field::Varint(value)) => model.add_dummy_prefix = value != 0,
+                        (4, Field::Varint(value)) => model.remove_extra_whitespaces = value != 0,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if model.pieces.is_empty() {
+        return Err("Model has no pieces".to_owned());
+    }
+    if model.unk_id >= model.pieces.len() {
+        return Err("Unknown token id is outside the vocabulary".to_owned());
+    }
+
+    Ok(model)
+}
+
+/// Recovers BPE merges from the vocabulary, since SentencePiece models only store pieces.  Every
+/// piece which splits into two other pieces is a merge, ranked by the id of the merged piece,
+/// matching the conversion in the Hugging Face `transformers` library.
+fn bpe_merges(vocab: &AHashMap<String, u32>) -> Vec<(String, String)> {
+    let mut merges: Vec<(u32, u32, u32, String, String)> = vocab
+        .iter()
+        .flat_map(|(piece, &merged_id)| {
+            piece.char_indices().skip(1).filter_map(move |(split, _)| {
+                let (left, right) = piece.split_at(split);
+                let left_id = *vocab.get(left)?;
+                let right_id = *vocab.get(right)?;
+                Some((
+                    merged_id,
+                    left_id,
+                    right_id,
+                    left.to_owned(),
+                    right.to_owned(),
+                ))
+            })
+        })
+        .collect();
+    merges.sort_unstable_by_key(|(merged_id, left_id, right_id, ..)| {
+        (*merged_id, *left_id, *right_id)
+    });
+
+    merges
+        .into_iter()
+        .map(|(.., left, right)| (left, right))
+        .collect()
+}
+
+/// Builds a `tokenizers` model equivalent to the SentencePiece model.
+fn build_model(model: &SentencePieceModel) -> tokenizers::Result<ModelWrapper> {
+    match model.model_type {
+        ModelType::Unigram => {
+            let vocab = model
+                .pieces
+                .iter()
+                .map(|piece| (piece.text.clone(), f64::from(piece.score)))
+                .collect();
+
+            Ok(Unigram::from(vocab, Some(model.unk_id), model.byte_fallback)?.into())
+        }
+        ModelType::Bpe => {
+            let vocab: AHashMap<String, u32> = model
+                .pieces
+                .iter()
+                .enumerate()
+                .map(|(id, piece)| Ok((piece.text.clone(), u32::try_from(id)?)))
+                .collect::<Result<_, std::num::TryFromIntError>>()?;
+            let merges = bpe_merges(&vocab);
+
+            Ok(BPE::builder()
+                .vocab_and_merges(vocab, merges)
+                .unk_token(model.pieces[model.unk_id].text.clone())
+                .fuse_unk(true)
+                .byte_fallback(model.byte_fallback)
+                .build()?
+                .into())
+        }
+    }
+}
+
+/// Adds a template so encoding with special tokens matches the Hugging Face slow tokenizers:
+/// models with a beginning-of-sequence token prepend it, like Llama and Gemma, while models with
+/// only an end-of-sequence token append it, like T5.
+fn build_post_processor(
+    model: &SentencePieceModel,
+) -> tokenizers::Result<Option<TemplateProcessing>> {
+    let special = |id: Option<usize>| {
+        id.and_then(|id| model.pieces.get(id))
+            .filter(|piece| piece.kind == PIECE_TYPE_CONTROL)
+            .map(|piece| (piece.text.clone(), id.and_then(|id| u32::try_from(id).ok())))
+    };
+    let (template, token) = match (special(model.bos_id), special(model.eos_id)) {
+        (Some((bos, Some(id))), _) => (format!("{bos} $A"), (bos, id)),
+        (None, Some((eos, Some(id)))) => (format!("$A {eos}"), (eos, id)),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(
+        TemplateProcessing::builder()
+            .try_single(template.as_str())?
+            .special_tokens(vec![token])
+            .build()?,
+    ))
+}
+
+/// Loads a SentencePiece `tokenizer.model` file as a `tokenizers` tokenizer, so it counts and
+/// tokenizes like tokenizers loaded from `tokenizer.json` files.
+///
+/// # Errors
+/// Errors if the data is not a valid SentencePiece model.
+pub fn tokenizer_from_model(data: &[u8]) -> tokenizers::Result<Tokenizer> {
+    let model =
+        parse_model(data).map_err(|error| format!("Invalid SentencePiece model: {error}"))?;
+    let mut tokenizer = Tokenizer::new(build_model(&model)?);
+
+    let mut normalizers: Vec<NormalizerWrapper> = Vec::new();
+    if !model.precompiled_charsmap.is_empty() {
+        normalizers.push(Precompiled::from(&model.precompiled_charsmap)?.into());
+    }
+    if model.remove_extra_whitespaces {
+        normalizers.push(Strip::new(true, true).into());
+        normalizers.push(Replace::new(ReplacePattern::Regex(" {2,}".to_owned()), " ")?.into());
+    }
+    if !normalizers.is_empty() {
+        tokenizer.with_normalizer(Some(NormalizerSequence::new(normalizers)))?;
+    }
+
+    let prepend_scheme = if model.add_dummy_prefix {
+        PrependScheme::First
+    } else {
+        PrependScheme::Never
+    };
+    // Unigram pieces never span whitespace, while BPE models, such as Llama, may merge runs of
+    // spaces
+    let split = model.model_type == ModelType::Unigram;
+    tokenizer.with_pre_tokenizer(Some(Metaspace::new(
+        SPACE_REPLACEMENT,
+        prepend_scheme,
+        split,
+    )));
+    let metaspace: DecoderWrapper = Metaspace::new(SPACE_REPLACEMENT, prepend_scheme, split).into();
+    tokenizer.with_decoder(Some(if model.byte_fallback {
+        DecoderSequence::new(vec![
+            ByteFallback::new().into(),
+            Fuse::new().into(),
+            metaspace,
+        ])
+        .into()
+    } else {
+        metaspace
+    }));
+    tokenizer.with_post_processor(build_post_processor(&model)?);
+
+    // Control pieces, such as `<s>`, are special tokens, while user-defined pieces are always
+    // kept whole, but count as content
+    let added_tokens = |kind: u64, special: bool| -> Vec<AddedToken> {
+        model
+            .pieces
+            .iter()
+            .filter(|piece| piece.kind == kind)
+            .map(|piece| AddedToken::from(piece.text.clone(), special))
+            .collect()
+    };
+    tokenizer.add_special_tokens(added_tokens(PIECE_TYPE_CONTROL, true))?;
+    tokenizer.add_tokens(added_tokens(PIECE_TYPE_USER_DEFINED, false))?;
+
+    Ok(tokenizer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sentencepiece::{ModelType, Piece, parse_model, tokenizer_from_model};
+
+    /// Encodes a protobuf varint.
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = u8::try_from(value & 0x7f).unwrap();
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    /// Encodes a length-delimited protobuf field.
+    fn bytes_field(number: u64, data: &[u8]) -> Vec<u8> {
+        let mut field = varint(number << 3 | 2);
+        field.extend(varint(data.len() as u64));
+        field.extend_from_slice(data);
+        field
+    }
+
+    /// Encodes a varint protobuf field.
+    fn varint_field(number: u64, value: u64) -> Vec<u8> {
+        let mut field = varint(number << 3);
+        field.extend(varint(value));
+        field
+    }
+
+    /// Encodes a SentencePiece piece, with its score and type.
+    fn piece(text: &str, score: f32, kind: u64) -> Vec<u8> {
+        let mut data = bytes_field(1, text.as_bytes());
+        data.extend(varint(2 << 3 | 5));
+        data.extend(score.to_le_bytes());
+        data.extend(varint_field(3, kind));
+        bytes_field(1, &data)
+    }
+
+    /// Builds a small Llama-style BPE model, with `<unk>`, `<s>` and `</s>` tokens, and pieces
+    /// spelling out `▁hello ▁world`.
+    fn get_bpe_model_fixture() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(piece("<unk>", 0.0, 2));
+        data.extend(piece("<s>", 0.0, 3));
+        data.extend(piece("</s>", 0.0, 3));
+        for (index, text) in [
+            "▁", "h", "e", "l", "o", "w", "r", "d", "▁h", "ll", "▁he", "▁hell", "▁hello", "or",
+            "▁w", "▁wor", "ld", "▁world",
+        ]
+        .iter()
+        .enumerate()
+        {
+            #[allow(clippy::cast_precision_loss)]
+            data.extend(piece(text, -(index as f32), 1));
+        }
+        // trainer spec: BPE model type, with an unknown token id of 0
+        let mut trainer = varint_field(3, 2);
+        trainer.extend(varint_field(40, 0));
+        data.extend(bytes_field(2, &trainer));
+        // normalizer spec: keep whitespace as is
+        data.extend(bytes_field(3, &varint_field(4, 0)));
+
+        data
+    }
+
+    #[test]
+    fn parse_model_reads_pieces_and_settings() {
+        // arrange
+        let data = get_bpe_model_fixture();
+
+        // act
+        let model = parse_model(&data).unwrap();
+
+        // assert
+        assert_eq!(model.pieces.len(), 21);
+        assert_eq!(
+            model.pieces[4],
+            Piece {
+                text: "h".to_owned(),
+                score: -1.0,
+                kind: 1,
+            }
+        );
+        assert_eq!(model.model_type, ModelType::Bpe);
+        assert_eq!(
+            (model.unk_id, model.bos_id, model.eos_id),
+            (0, Some(1), Some(2))
+        );
+        assert!(model.add_dummy_prefix);
+        assert!(!model.remove_extra_whitespaces);
+    }
+
+    #[test]
+    fn parse_model_returns_error_for_invalid_data() {
+        // arrange
+        let data = [0x0a, 0xff];
+
+        // act
+        let outcome = parse_model(&data).unwrap_err();
+
+        // assert
+        assert_eq!(outcome, "Unexpected end of data in varint");
+    }
+
+    #[test]
+    fn parse_model_skips_long_runs_of_fixed64_fields() {
+        // arrange
+        let mut data = Vec::new();
+        for _ in 0..200_000 {
+            data.extend(varint(99 << 3 | 1));
+            data.extend(0_u64.to_le_bytes());
+        }
+        data.extend(get_bpe_model_fixture());
+
+        // act
+        let model = parse_model(&data).unwrap();
+
+        // assert
+        assert_eq!(model.pieces.len(), 21);
+    }
+
+    #[test]
+    fn tokenizer_from_model_encodes_like_sentencepiece() {
+        // arrange
+        let data = get_bpe_model_fixture();
+        let tokenizer = tokenizer_from_model(&data).unwrap();
+
+        // act
+        let encoding = tokenizer.encode("hello world", true).unwrap();
+
+        // assert
+        assert_eq!(encoding.get_tokens(), ["<s>", "▁hello", "▁world"]);
+        assert_eq!(encoding.get_special_tokens_mask(), [1, 0, 0]);
+    }
+}
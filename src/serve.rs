@@ -0,0 +1,401 @@
+use std::{io::Read, num::NonZeroUsize, thread};
+
+use miette::miette;
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tokenator::{AppError, CounterCache, HubOptions, Registry};
+
+use crate::{
+    files::{InputCount, SourceCounts, SourceKind},
+    output::{
+        OutputFormat, Report, TokenReport, render_error, render_model_list, render_report,
+        render_tokens,
+    },
+    prompt::MAX_PROMPT_BYTES,
+};
+
+/// Largest request body accepted: the largest prompt, with room for the rest of the JSON request.
+const MAX_BODY_BYTES: usize = MAX_PROMPT_BYTES + 64 * 1024;
+
+/// Options for the `serve` subcommand.
+pub struct ServeOptions<'a> {
+    pub host: &'a str,
+    pub port: u16,
+
+    /// Most tokenizers to keep loaded at once.
+    pub max_tokenizers: NonZeroUsize,
+
+    /// Models to load before accepting requests.
+    pub preload: &'a [String],
+}
+
+/// Body of `POST /count` and `POST /tokenize` requests.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CountRequest {
+    /// Name of the model, as listed by `GET /models`.
+    model: String,
+
+    text: String,
+
+    /// Add the special tokens the tokenizer wraps prompts in, as the CLI does unless given
+    /// `--no-special-tokens`.
+    #[serde(default = "add_special_tokens_default")]
+    add_special_tokens: bool,
+}
+
+fn add_special_tokens_default() -> bool {
+    true
+}
+
+/// A response, before it is sent.
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    body: String,
+}
+
+impl Reply {
+    fn ok(body: String) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, error: &miette::Report) -> Self {
+        Self {
+            status,
+            body: render_error(error, OutputFormat::Json).expect("JSON errors should render"),
+        }
+    }
+
+    /// Returns the reply for an error from the library, with the status matching the error.
+    fn app_error(error: AppError) -> Self {
+        let status = match &error {
+            AppError::UnknownModel(_) => 404,
+            AppError::HfApi(_) | AppError::NotCached(_) => 502,
+            _ => 500,
+        };
+
+        Self::error(status, &miette::Report::new(error))
+    }
+}
+
+/// Models and loaded tokenizers, shared by every request.
+struct ServerState {
+    counters: CounterCache,
+}
+
+impl ServerState {
+    /// Parses a count or tokenize request body, checking the text is within the prompt size
+    /// limit.
+    fn parse_request(body: &[u8]) -> Result<CountRequest, Reply> {
+        let request: CountRequest = serde_json::from_slice(body).map_err(|error| {
+            Reply::error(
+                400,
+                &miette!(
+                    help = "Send a JSON object with `model` and `text` strings, and an optional \
+                    `add_special_tokens` boolean",
+                    "Invalid request body: {error}"
+                ),
+            )
+        })?;
+        if request.text.len() > MAX_PROMPT_BYTES {
+            return Err(Reply::error(
+                413,
+                &miette!("Prompt context is too large: {}!", request.text.len()),
+            ));
+        }
+
+        Ok(request)
+    }
+
+    fn count(&self, body: &[u8]) -> Result<Reply, Reply> {
+        let request = Self::parse_request(body)?;
+        let counter = self
+            .counters
+            .get(&request.model)
+            .map_err(Reply::app_error)?;
+        let tokens = counter
+            .tokeniser()
+            .count(&request.text, request.add_special_tokens)
+            .map_err(Reply::app_error)?;
+        let counts = SourceCounts {
+            counted: vec![InputCount {
+                kind: SourceKind::Text,
+                path: None,
+                tokens: tokens.tokens,
+                special_tokens: tokens.special,
+                chars: request.text.chars().count(),
+                bytes: request.text.len(),
+            }],
+            skipped: Vec::new(),
+        };
+        let model = counter.model();
+        let report = Report {
+            model,
+            revision: model.pin.revision(),
+            counts: &counts,
+        };
+
+        Ok(Reply::ok(render_report(&report, OutputFormat::Json)))
+    }
+
+    fn tokenize(&self, body: &[u8]) -> Result<Reply, Reply> {
+        let request = Self::parse_request(body)?;
+        let counter = self
+            .counters
+            .get(&request.model)
+            .map_err(Reply::app_error)?;
+        let tokens = counter
+            .tokenize(&request.text, request.add_special_tokens)
+            .map_err(Reply::app_error)?;
+        let model = counter.model();
+        let report = TokenReport {
+            model,
+            revision: model.pin.revision(),
+            tokens: &tokens,
+        };
+
+        Ok(Reply::ok(render_tokens(&report, OutputFormat::Json)))
+    }
+
+    /// Routes a request to its handler.  The query string, if any, is ignored.
+    fn handle(&self, method: &Method, url: &str, body: &[u8]) -> Reply {
+        let path = url.split_once('?').map_or(url, |(path, _)| path);
+        let outcome = match (method, path) {
+            (Method::Get, "/health") => Ok(Reply::ok(
+                serde_json::json!({
+                    "status": "ok",
+                    "loaded": self.counters.loaded(),
+                })
+                .to_string(),
+            )),
+            (Method::Get, "/models") => Ok(Reply::ok(render_model_list(
+                &self.counters.registry().models(),
+                OutputFormat::Json,
+            ))),
+            (Method::Post, "/count") => self.count(body),
+            (Method::Post, "/tokenize") => self.tokenize(body),
+            (_, "/health" | "/models" | "/count" | "/tokenize") => Err(Reply::error(
+                405,
+                &miette!("`{method}` is not supported for `{path}`"),
+            )),
+            _ => Err(Reply::error(
+                404,
+                &miette!(
+                    help = "Use `GET /health`, `GET /models`, `POST /count` or `POST /tokenize`",
+                    "No endpoint at `{path}`"
+                ),
+            )),
+        };
+
+        outcome.unwrap_or_else(|reply| reply)
+    }
+
+    /// Reads the request body, rejecting bodies over [`MAX_BODY_BYTES`], then handles the request
+    /// and sends the reply.
+    fn respond(&self, mut request: Request) {
+        let too_large = || {
+            Reply::error(
+                413,
+                &miette!("Request body is over the {MAX_BODY_BYTES} byte limit"),
+            )
+        };
+        let reply = if request
+            .body_length()
+            .is_some_and(|length| length > MAX_BODY_BYTES)
+        {
+            too_large()
+        } else {
+            let mut body = Vec::new();
+            let limit = u64::try_from(MAX_BODY_BYTES + 1).unwrap_or(u64::MAX);
+            match request.as_reader().take(limit).read_to_end(&mut body) {
+                Ok(length) if length > MAX_BODY_BYTES => too_large(),
+                Ok(_) => self.handle(request.method(), request.url(), &body),
+                Err(error) => Reply::error(400, &miette!("Unable to read the request: {error}")),
+            }
+        };
+        log::info!("{} {} {}", request.method(), request.url(), reply.status);
+
+        let content_type = Header::from_bytes("Content-Type", "application/json")
+            .expect("content type header should be valid");
+        let response = Response::from_string(reply.body)
+            .with_status_code(reply.status)
+            .with_header(content_type);
+        if let Err(error) = request.respond(response) {
+            log::warn!("Unable to send the response: {error}");
+        }
+    }
+}
+
+/// Runs the HTTP server until the process is stopped, handling requests on a thread for each
+/// available CPU.  Tokenizers load on their first request, or at start up for `preload` models,
+/// and stay loaded, up to `max_tokenizers` of them, so later requests skip the load.
+///
+/// # Errors
+/// Errors if unable to load a preloaded model, or to listen on the address.
+pub fn run_server(
+    options: &ServeOptions,
+    registry: Registry,
+    hub: HubOptions,
+) -> miette::Result<()> {
+    let state = ServerState {
+        counters: CounterCache::new(registry, hub, options.max_tokenizers),
+    };
+    for name in options.preload {
+        state.counters.get(name)?;
+    }
+    let address = format!("{}:{}", options.host, options.port);
+    let server = Server::http(&address)
+        .map_err(|error| miette!("Unable to listen on `{address}`: {error}"))?;
+    eprintln!("Listening on http://{address}");
+
+    let workers = thread::available_parallelism().map_or(4, NonZeroUsize::get);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    state.respond(request);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use assert_fs::{TempDir, prelude::PathChild};
+    use tiny_http::Method;
+    use tokenator::{CounterCache, HubOptions, ModelSources, Registry};
+
+    use crate::{prompt::MAX_PROMPT_BYTES, serve::ServerState};
+
+    fn get_state_fixture(temp_dir: &TempDir) -> ServerState {
+        let models_file = temp_dir.child("models.json");
+        std::fs::write(
+            &models_file,
+            format!(
+                r#"[
+  {{ "name": "local", "path": "{}", "context_length": 100 }},
+  {{ "name": "gpt-4o", "tiktoken": "o200k_base" }}
+]"#,
+                std::path::absolute("tests/fixtures/tokenizer.json")
+                    .unwrap()
+                    .display()
+            ),
+        )
+        .unwrap();
+        let registry = Registry::load(&ModelSources {
+            models_file: Some(models_file.path().to_path_buf()),
+            ..ModelSources::default()
+        })
+        .unwrap();
+
+        ServerState {
+            counters: CounterCache::new(registry, HubOptions::default(), NonZeroUsize::MIN),
+        }
+    }
+
+    #[test]
+    fn count_returns_the_json_report_and_keeps_the_tokenizer_loaded() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let state = get_state_fixture(&temp_dir);
+
+        // act
+        let reply = state.handle(
+            &Method::Post,
+            "/count",
+            br#"{"model": "local", "text": "Why is the sky blue?"}"#,
+        );
+        let without_special = state.handle(
+            &Method::Post,
+            "/count?pretty",
+            br#"{"model": "local", "text": "Why is the sky blue?", "add_special_tokens": false}"#,
+        );
+        let health = state.handle(&Method::Get, "/health", b"");
+
+        // assert
+        let report: serde_json::Value = serde_json::from_str(&reply.body).unwrap();
+        let without_special: serde_json::Value =
+            serde_json::from_str(&without_special.body).unwrap();
+        assert_eq!(reply.status, 200);
+        assert_eq!(report["model"]["name"], "local");
+        assert_eq!(report["total"]["tokens"], 7);
+        assert_eq!(report["total"]["special_tokens"], 1);
+        assert_eq!(report["total"]["context_percent"], 7.0);
+        assert_eq!(without_special["total"]["tokens"], 6);
+        assert_eq!(health.body, r#"{"loaded":["local"],"status":"ok"}"#);
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn tokenize_and_models_return_the_cli_json_output() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let state = get_state_fixture(&temp_dir);
+
+        // act
+        let tokens = state.handle(
+            &Method::Post,
+            "/tokenize",
+            br#"{"model": "gpt-4o", "text": "hi there"}"#,
+        );
+        let models = state.handle(&Method::Get, "/models", b"");
+
+        // assert
+        let tokens: serde_json::Value = serde_json::from_str(&tokens.body).unwrap();
+        let models: serde_json::Value = serde_json::from_str(&models.body).unwrap();
+        assert_eq!(tokens["tokens"][1]["text"], " there");
+        assert_eq!(models["models"][1]["name"], "local");
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn handle_returns_error_statuses_for_bad_requests() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let state = get_state_fixture(&temp_dir);
+        let oversized = format!(
+            r#"{{"model": "local", "text": "{}"}}"#,
+            "a".repeat(MAX_PROMPT_BYTES + 1)
+        );
+
+        // act
+        let statuses = [
+            state.handle(&Method::Post, "/count", b"{\"text\": \"hi\"}"),
+            state.handle(
+                &Method::Post,
+                "/count",
+                br#"{"model": "lokal", "text": "hi"}"#,
+            ),
+            state.handle(&Method::Get, "/count", b""),
+            state.handle(&Method::Get, "/counts", b""),
+            state.handle(&Method::Post, "/count", oversized.as_bytes()),
+        ]
+        .map(|reply| reply.status);
+        let unknown_model = state.handle(
+            &Method::Post,
+            "/count",
+            br#"{"model": "lokal", "text": "hi"}"#,
+        );
+
+        // assert
+        assert_eq!(statuses, [400, 404, 405, 404, 413]);
+        let error: serde_json::Value = serde_json::from_str(&unknown_model.body).unwrap();
+        assert_eq!(
+            error["error"]["message"],
+            "No model matching `lokal`, did you mean `local`?"
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+}
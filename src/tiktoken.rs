@@ -0,0 +1,293 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rustc_hash::FxHashMap;
+use tiktoken_rs::{CoreBPE, O200K_BASE_PAT_STR, Rank};
+
+use crate::{
+    errors::{AppError, TokenizerError, TokenizerFileError},
+    token::{TokenCount, TokenInfo, TokenizerBackend, token_info},
+};
+
+/// File extension of tiktoken rank files.
+pub const RANK_FILE_EXTENSION: &str = "tiktoken";
+
+/// Split pattern shared by the GPT-2 era `r50k_base` and `p50k_base` encodings.
+const R50K_BASE_PAT_STR: &str =
+    r"'(?:[sdmt]|ll|ve|re)| ?\p{L}++| ?\p{N}++| ?[^\s\p{L}\p{N}]++|\s++$|\s+(?!\S)|\s";
+
+/// Split pattern of the `cl100k_base` encoding.
+const CL100K_BASE_PAT_STR: &str = r"'(?i:[sdmt]|ll|ve|re)|[^\r\n\p{L}\p{N}]?+\p{L}++|\p{N}{1,3}+| ?[^\s\p{L}\p{N}]++[\r\n]*+|\s++$|\s*[\r\n]|\s+(?!\S)|\s";
+
+/// An `OpenAI` encoding, with the pre-tokenizer split pattern and special tokens its rank file
+/// is used with.
+struct Encoding {
+    name: &'static str,
+    pattern: &'static str,
+    special_tokens: &'static [(&'static str, Rank)],
+}
+
+/// Encodings built into the binary.  Their rank files are vendored by `tiktoken-rs`, so they work
+/// offline, without downloading anything.
+const ENCODINGS: [Encoding; 4] = [
+    Encoding {
+        name: "o200k_base",
+        pattern: O200K_BASE_PAT_STR,
+        special_tokens: &[("<|endoftext|>", 199_999), ("<|endofprompt|>", 200_018)],
+    },
+    Encoding {
+        name: "cl100k_base",
+        pattern: CL100K_BASE_PAT_STR,
+        special_tokens: &[
+            ("<|endoftext|>", 100_257),
+            ("<|fim_prefix|>", 100_258),
+            ("<|fim_middle|>", 100_259),
+            ("<|fim_suffix|>", 100_260),
+            ("<|endofprompt|>", 100_276),
+        ],
+    },
+    Encoding {
+        name: "p50k_base",
+        pattern: R50K_BASE_PAT_STR,
+        special_tokens: &[("<|endoftext|>", 50_256)],
+    },
+    Encoding {
+        name: "r50k_base",
+        pattern: R50K_BASE_PAT_STR,
+        special_tokens: &[("<|endoftext|>", 50_256)],
+    },
+];
+
+/// Returns the built-in encoding called `name`.
+fn built_in(name: &str) -> Option<&'static Encoding> {
+    ENCODINGS.iter().find(|encoding| encoding.name == name)
+}
+
+/// Returns `true` when `encoding` names one of the built-in encodings, rather than a rank file.
+#[must_use]
+pub fn is_built_in(encoding: &str) -> bool {
+    built_in(encoding).is_some()
+}
+
+/// Returns `true` for paths of tiktoken rank files, going by the file extension.
+#[must_use]
+pub fn is_rank_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == RANK_FILE_EXTENSION)
+}
+
+/// Returns the names of the built-in encodings, for messages.
+#[must_use]
+pub fn built_in_names() -> String {
+    ENCODINGS
+        .iter()
+        .map(|encoding| format!("`{}`", encoding.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses a tiktoken rank file, where each line holds a base64 encoded token and its rank.
+fn parse_rank_file(content: &str) -> Result<FxHashMap<Vec<u8>, Rank>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let (token, rank) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("Line {line_number} is not a token and rank pair"))?;
+            let token = STANDARD
+                .decode(token)
+                .map_err(|error| format!("Line {line_number} has an invalid token: {error}"))?;
+            let rank = rank
+                .trim()
+                .parse()
+                .map_err(|error| format!("Line {line_number} has an invalid rank: {error}"))?;
+
+            Ok((token, rank))
+        })
+        .collect()
+}
+
+/// A tokenizer for an OpenAI-style byte-level BPE encoding, as used by `tiktoken`.
+pub struct TiktokenBackend {
+    bpe: CoreBPE,
+}
+
+impl TiktokenBackend {
+    /// Loads a built-in encoding, such as `o200k_base`, or a local `.tiktoken` rank file.  Rank
+    /// files named after a built-in encoding, such as `cl100k_base.tiktoken`, use that encoding's
+    /// split pattern and special tokens, while other rank files use the `o200k_base` split pattern
+    /// and have no special tokens.
+    ///
+    /// # Errors
+    /// Errors if `encoding` is neither a built-in encoding nor a readable, valid rank file.
+    pub fn load(encoding: &str) -> Result<Self, AppError> {
+        if let Some(built_in) = built_in(encoding) {
+            log::info!("Loading built-in `{encoding}` tiktoken encoding");
+            let bpe = match built_in.name {
+                "o200k_base" => tiktoken_rs::o200k_base(),
+                "cl100k_base" => tiktoken_rs::cl100k_base(),
+                "p50k_base" => tiktoken_rs::p50k_base(),
+                _ => tiktoken_rs::r50k_base(),
+            }
+            .map_err(|error| tokenizer_error(&error.to_string()))?;
+            return Ok(Self { bpe });
+        }
+
+        let path = Path::new(encoding);
+        let content = fs::read_to_string(path).map_err(|_| TokenizerFileError::missing(path))?;
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy());
+        let (pattern, special_tokens) =
+            if let Some(encoding) = stem.and_then(|stem| built_in(&stem)) {
+                (encoding.pattern, encoding.special_tokens)
+            } else {
+                log::info!(
+                    "`{}` is not named after a built-in encoding, so using the `o200k_base` split \
+                pattern, without special tokens",
+                    path.display()
+                );
+                (O200K_BASE_PAT_STR, &[][..])
+            };
+        log::info!("Loading tiktoken rank file `{}`", path.display());
+        let ranks = parse_rank_file(&content).map_err(|error| {
+            tokenizer_error(&format!("Invalid rank file `{}`: {error}", path.display()))
+        })?;
+        let distinct_ranks: HashSet<Rank> = ranks.values().copied().collect();
+        if distinct_ranks.len() != ranks.len() {
+            return Err(tokenizer_error(&format!(
+                "Invalid rank file `{}`: some tokens share a rank",
+                path.display()
+            )));
+        }
+        let special_tokens = special_tokens
+            .iter()
+            .map(|&(token, rank)| (token.to_owned(), rank))
+            .collect();
+        let bpe = CoreBPE::new(ranks, special_tokens, pattern)
+            .map_err(|error| tokenizer_error(&error.to_string()))?;
+
+        Ok(Self { bpe })
+    }
+}
+
+/// Wraps a tiktoken error message, so it is reported like other tokenizer errors.
+fn tokenizer_error(message: &str) -> AppError {
+    TokenizerError::from(tokenizers::tokenizer::Error::from(message)).into()
+}
+
+impl TokenizerBackend for TiktokenBackend {
+    /// tiktoken encodings never wrap prompts in special tokens, so `add_special_tokens` has no
+    /// effect, and there are never any special tokens.
+    fn count(&self, prompt: &str, _add_special_tokens: bool) -> Result<TokenCount, AppError> {
+        Ok(TokenCount {
+            tokens: self.bpe.encode_ordinary(prompt).len(),
+            special: 0,
+        })
+    }
+
+    /// Encodes the prompt as ordinary text, so special token strings in the prompt, such as
+    /// `<|endoftext|>`, count as text, as they do in chat API requests.
+    fn tokenize(
+        &self,
+        prompt: &str,
+        _add_special_tokens: bool,
+    ) -> Result<Vec<TokenInfo>, AppError> {
+        let char_starts: Vec<usize> = prompt.char_indices().map(|(index, _)| index).collect();
+        let mut start = 0;
+
+        self.bpe
+            .encode_ordinary(prompt)
+            .into_iter()
+            .map(|id| {
+                let bytes = self
+                    .bpe
+                    .decode_bytes(&[id])
+                    .map_err(|error| tokenizer_error(&error.to_string()))?;
+                let end = start + bytes.len();
+                let token = token_info(
+                    prompt,
+                    &char_starts,
+                    id,
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                    (start, end),
+                    false,
+                );
+                start = end;
+
+                Ok(token)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteStr, PathChild},
+    };
+
+    use crate::{
+        tiktoken::{TiktokenBackend, is_built_in, parse_rank_file},
+        token::{TokenizerBackend, count_tokens},
+    };
+
+    #[test]
+    fn built_in_encodings_count_expected_tokens() {
+        // arrange
+        let o200k_base = TiktokenBackend::load("o200k_base").unwrap();
+        let cl100k_base = TiktokenBackend::load("cl100k_base").unwrap();
+
+        // act
+        let o200k_count = count_tokens(&o200k_base, "Why is the sky blue?", true).unwrap();
+        let cl100k_count = count_tokens(&cl100k_base, "Why is the sky blue?", true).unwrap();
+
+        // assert
+        assert_eq!(o200k_count.tokens, 6);
+        assert_eq!(cl100k_count.tokens, 6);
+        assert_eq!(o200k_count.special, 0);
+        assert!(is_built_in("o200k_base"));
+        assert!(!is_built_in("o200k_base.tiktoken"));
+    }
+
+    #[test]
+    fn rank_files_load_and_tokenize_with_offsets() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        // "h", "i", " ", "hi" and " hi", base64 encoded
+        let _ = temp_dir
+            .child("tiny.tiktoken")
+            .write_str("aA== 0\naQ== 1\nIA== 2\naGk= 3\nIGhp 4\n");
+        let path = temp_dir.path().join("tiny.tiktoken");
+        let backend = TiktokenBackend::load(&path.to_string_lossy()).unwrap();
+
+        // act
+        let tokens = backend.tokenize("hi hi", true).unwrap();
+
+        // assert
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.id, token.text.as_str(), token.bytes))
+                .collect::<Vec<_>>(),
+            [(3, "hi", (0, 2)), (4, " hi", (2, 5))]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn parse_rank_file_reports_the_invalid_line() {
+        // arrange
+        let content = "aA== 0\naQ==\n";
+
+        // act
+        let outcome = parse_rank_file(content).unwrap_err();
+
+        // assert
+        assert_eq!(outcome, "Line 2 is not a token and rank pair");
+    }
+}
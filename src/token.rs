@@ -1,79 +1,1348 @@
-use hf_hub::{Repo, RepoType, api::sync::ApiBuilder};
-use tokenizers::Tokenizer;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Once,
+};
 
-use crate::errors::{AppError, HfApiError, TokenizerError};
+use hf_hub::{
+    Cache, Repo, RepoType,
+    api::sync::{ApiBuilder, ApiRepo},
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokenizers::{
+    AddedToken, Encoding, Tokenizer, models::bpe::BPE, pre_tokenizers::byte_level::ByteLevel,
+};
 
-/// Creates a tokenizer instance based on the repository ID. `hf_hub` caches `tokenizer.json`
-/// files, so they should only be downloaded once for each model.
+use crate::{
+    chat::TOKENIZER_CONFIG_FILENAME,
+    errors::{
+        AppError, HashMismatchError, HfApiError, ModelFileError, NotCachedError, TokenizerError,
+        TokenizerFileError,
+    },
+    sentencepiece::tokenizer_from_model,
+    tiktoken::{TiktokenBackend, built_in_names, is_built_in, is_rank_file},
+};
+
+/// Repo revision tokenizer files are downloaded from, unless the model is pinned to another.
+pub const DEFAULT_REVISION: &str = "main";
+
+/// Name of the tokenizer file, in Hugging Face repos and local model directories.
+pub const TOKENIZER_FILENAME: &str = "tokenizer.json";
+
+/// Name of the SentencePiece model file, used by repos without a `tokenizer.json` file.
+pub const SENTENCEPIECE_FILENAME: &str = "tokenizer.model";
+
+/// Names of the vocabulary and merges files of slow byte-level BPE tokenizers, such as GPT-2.
+pub const VOCAB_FILENAME: &str = "vocab.json";
+pub const MERGES_FILENAME: &str = "merges.txt";
+
+/// Names of the files listing added and special tokens for slow tokenizers, next to the
+/// vocabulary and merges files.
+pub const ADDED_TOKENS_FILENAME: &str = "added_tokens.json";
+pub const SPECIAL_TOKENS_MAP_FILENAME: &str = "special_tokens_map.json";
+
+/// Keys naming special tokens in special tokens maps and tokenizer configurations.
+const SPECIAL_TOKEN_KEYS: [&str; 7] = [
+    "bos_token",
+    "eos_token",
+    "unk_token",
+    "sep_token",
+    "pad_token",
+    "cls_token",
+    "mask_token",
+];
+
+/// Name of the model configuration file, in Hugging Face repos and local model directories.
+pub const CONFIG_FILENAME: &str = "config.json";
+
+/// Environment variables which switch on offline mode, like the `--offline` flag.  `HF_HUB_OFFLINE`
+/// is shared with other Hugging Face tools.
+const OFFLINE_VARIABLES: [&str; 2] = ["TOKENATOR_OFFLINE", "HF_HUB_OFFLINE"];
+
+/// Options for fetching files from Hugging Face repos.
+#[derive(Clone, Debug, Default)]
+pub struct HubOptions {
+    /// Only use files already in the `hf_hub` cache, without contacting Hugging Face.
+    pub offline: bool,
+
+    /// `hf_hub` cache directory, overriding `$HF_HOME/hub` and `~/.cache/huggingface/hub`.
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl HubOptions {
+    /// Creates options for a run, which is offline with the `--offline` flag, or when either the
+    /// `TOKENATOR_OFFLINE` or `HF_HUB_OFFLINE` environment variable is set to `1`, `true`, `yes`
+    /// or `on`.
+    #[must_use]
+    pub fn new(offline: bool, cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            offline: offline
+                || OFFLINE_VARIABLES
+                    .iter()
+                    .any(|name| std::env::var(name).is_ok_and(|value| is_truthy(&value))),
+            cache_dir,
+        }
+    }
+
+    /// Returns the `hf_hub` cache, in the `--cache-dir` directory when given.
+    pub fn cache(&self) -> Cache {
+        self.cache_dir
+            .as_ref()
+            .map_or_else(Cache::from_env, |cache_dir| Cache::new(cache_dir.clone()))
+    }
+}
+
+/// Returns `true` for environment variable values switching a setting on.
+fn is_truthy(value: &str) -> bool {
+    ["1", "true", "yes", "on"]
+        .iter()
+        .any(|truthy| value.trim().eq_ignore_ascii_case(truthy))
+}
+
+/// Where a model tokenizer is loaded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenizerSource {
+    /// Hugging Face repo ID, such as `Qwen/Qwen3-8B`.
+    Hub(String),
+
+    /// Local `tokenizer.json` file, or a directory holding one.
+    Path(PathBuf),
+
+    /// Built-in tiktoken encoding, such as `o200k_base`, or a local `.tiktoken` rank file.
+    Tiktoken(String),
+}
+
+impl TokenizerSource {
+    /// Returns the source for a local tokenizer path, which is a tiktoken rank file for paths
+    /// ending `.tiktoken`.
+    #[must_use]
+    pub fn local(path: PathBuf) -> Self {
+        if is_rank_file(&path) {
+            Self::Tiktoken(path.display().to_string())
+        } else {
+            Self::Path(path)
+        }
+    }
+}
+
+impl fmt::Display for TokenizerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hub(repo_id) => write!(f, "{repo_id}"),
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Tiktoken(encoding) => write!(f, "{encoding}"),
+        }
+    }
+}
+
+/// Repo revision and expected tokenizer file hash for a model, so token counts stay the same when
+/// the upstream tokenizer changes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenizerPin {
+    /// Commit SHA, tag or branch to download files from, or [`DEFAULT_REVISION`] when [`None`].
+    pub revision: Option<String>,
+
+    /// Expected SHA-256 hash of the tokenizer file, as hex.
+    pub sha256: Option<String>,
+}
+
+impl TokenizerPin {
+    /// Returns the repo revision to download files from.
+    #[must_use]
+    pub fn revision(&self) -> &str {
+        self.revision.as_deref().unwrap_or(DEFAULT_REVISION)
+    }
+
+    /// Switches to another revision, dropping the expected hash, which only applies to the
+    /// original revision.
+    pub fn override_revision(&mut self, revision: &str) {
+        if self.revision() != revision {
+            self.revision = Some(revision.to_owned());
+            self.sha256 = None;
+        }
+    }
+}
+
+/// File formats a tokenizer can be loaded from, in order of preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenizerFormat {
+    /// `tokenizer.json`, as saved by the Hugging Face `tokenizers` library.
+    Json,
+
+    /// SentencePiece `tokenizer.model`, converted when loaded.
+    SentencePiece,
+
+    /// Slow tokenizer `vocab.json` and `merges.txt` files, converted when loaded.
+    VocabMerges,
+}
+
+impl TokenizerFormat {
+    /// Formats to try, in order, when a repo or directory might hold more than one.
+    pub const ALL: [Self; 3] = [Self::Json, Self::SentencePiece, Self::VocabMerges];
+
+    /// Returns the names of the files making up the tokenizer.  The first is the one checked
+    /// against pinned hashes.
+    #[must_use]
+    pub fn filenames(self) -> &'static [&'static str] {
+        match self {
+            Self::Json => &[TOKENIZER_FILENAME],
+            Self::SentencePiece => &[SENTENCEPIECE_FILENAME],
+            Self::VocabMerges => &[VOCAB_FILENAME, MERGES_FILENAME],
+        }
+    }
+
+    /// Returns the names of files adding tokens to the tokenizer, which are used when they exist.
+    #[must_use]
+    pub fn optional_filenames(self) -> &'static [&'static str] {
+        match self {
+            Self::Json | Self::SentencePiece => &[],
+            Self::VocabMerges => &[
+                ADDED_TOKENS_FILENAME,
+                SPECIAL_TOKENS_MAP_FILENAME,
+                TOKENIZER_CONFIG_FILENAME,
+            ],
+        }
+    }
+
+    /// Picks the format for a local tokenizer file from its name, defaulting to JSON.
+    fn from_path(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "model")
+        {
+            Self::SentencePiece
+        } else if path.file_name().is_some_and(|name| name == VOCAB_FILENAME) {
+            Self::VocabMerges
+        } else {
+            Self::Json
+        }
+    }
+}
+
+/// Files a tokenizer is loaded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TokenizerFiles {
+    format: TokenizerFormat,
+
+    /// File paths, in the same order as [`TokenizerFormat::filenames`].
+    paths: Vec<PathBuf>,
+}
+
+impl TokenizerFiles {
+    /// Returns the path of the main tokenizer file.
+    fn primary(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    /// Loads the tokenizer, converting SentencePiece and slow tokenizer files.
+    fn load(&self) -> Result<Tokenizer, AppError> {
+        let tokenizer = match self.format {
+            TokenizerFormat::Json => Tokenizer::from_file(self.primary()),
+            TokenizerFormat::SentencePiece => {
+                let data = fs::read(self.primary())
+                    .map_err(|_| TokenizerFileError::missing(self.primary()))?;
+                tokenizer_from_model(&data)
+            }
+            TokenizerFormat::VocabMerges => tokenizer_from_vocab_and_merges(
+                &self.paths[0].to_string_lossy(),
+                &self.paths[1].to_string_lossy(),
+                read_added_tokens(self.primary())?,
+            ),
+        };
+
+        Ok(tokenizer.map_err(TokenizerError::from)?)
+    }
+}
+
+/// Builds a byte-level BPE tokenizer, like GPT-2, from slow tokenizer `vocab.json` and
+/// `merges.txt` files, along with its added tokens.
+fn tokenizer_from_vocab_and_merges(
+    vocab: &str,
+    merges: &str,
+    added_tokens: Vec<AddedToken>,
+) -> tokenizers::Result<Tokenizer> {
+    let byte_level = ByteLevel::new(false, true, true);
+    let mut tokenizer = Tokenizer::new(BPE::from_file(vocab, merges).build()?);
+    tokenizer
+        .with_pre_tokenizer(Some(byte_level))
+        .with_post_processor(Some(byte_level))
+        .with_decoder(Some(byte_level));
+    tokenizer.add_tokens(added_tokens)?;
+
+    Ok(tokenizer)
+}
+
+/// Returns the special token strings named in a special tokens map or tokenizer configuration.
+/// Each is either the token itself, or an added token object with the token as its `content`.
+fn special_token_names(map: &Value) -> Vec<String> {
+    let content = |value: &Value| {
+        value
+            .as_str()
+            .or_else(|| value.get("content")?.as_str())
+            .map(str::to_owned)
+    };
+
+    SPECIAL_TOKEN_KEYS
+        .iter()
+        .filter_map(|key| content(map.get(key)?))
+        .chain(
+            map.get("additional_special_tokens")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(content),
+        )
+        .collect()
+}
+
+/// Reads the added tokens for a slow tokenizer from the files next to `vocab`, which the
+/// vocabulary and merges leave out.  `added_tokens.json` maps tokens to ids, the tokenizer
+/// configuration lists tokens with their settings under `added_tokens_decoder`, and both it and
+/// `special_tokens_map.json` name the special tokens.  Tokens are returned in id order, so tokens
+/// missing from the vocabulary get the ids they have in the repo.  Missing files, and files which
+/// are not valid JSON, are skipped.
 ///
-/// # Returns
-/// A `miette::Result` containing the tokenizer.
-pub fn create_tokeniser(repo_id: &str) -> Result<Tokenizer, AppError> {
-    let token = std::env::var("HUGGING_FACE_ACCESS_TOKEN").ok().or_else(|| {
-        log::warn!(
-            "`HUGGING_FACE_ACCESS_TOKEN` environment variable is not defined, using Hugging \
-            Face API without an access token.  Performance may vary."
+/// # Errors
+/// Errors if one of the files exists, but cannot be read.
+fn read_added_tokens(vocab: &Path) -> Result<Vec<AddedToken>, AppError> {
+    let read_json = |filename: &str| -> Result<Option<Value>, AppError> {
+        let path = vocab.with_file_name(filename);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content =
+            fs::read_to_string(&path).map_err(|error| ModelFileError::unreadable(&path, error))?;
+
+        Ok(serde_json::from_str(&content)
+            .inspect_err(|error| log::warn!("Skipping `{}`: {error}", path.display()))
+            .ok())
+    };
+    let added_tokens = read_json(ADDED_TOKENS_FILENAME)?;
+    let special_tokens_map = read_json(SPECIAL_TOKENS_MAP_FILENAME)?;
+    let config = read_json(TOKENIZER_CONFIG_FILENAME)?;
+
+    // Tokens, with their ids, where known
+    let mut tokens: BTreeMap<String, (Option<u64>, AddedToken)> = BTreeMap::new();
+    for (content, id) in added_tokens.iter().filter_map(Value::as_object).flatten() {
+        tokens.insert(
+            content.clone(),
+            (id.as_u64(), AddedToken::from(content.clone(), false)),
         );
+    }
+    let decoder = config
+        .as_ref()
+        .and_then(|config| config.get("added_tokens_decoder")?.as_object());
+    for (id, token) in decoder.into_iter().flatten() {
+        let Some(content) = token.get("content").and_then(Value::as_str) else {
+            continue;
+        };
+        let flag = |key: &str| token.get(key).and_then(Value::as_bool);
+        let special = flag("special").unwrap_or(false);
+        let added = AddedToken::from(content, special)
+            .single_word(flag("single_word").unwrap_or(false))
+            .lstrip(flag("lstrip").unwrap_or(false))
+            .rstrip(flag("rstrip").unwrap_or(false))
+            .normalized(flag("normalized").unwrap_or(!special));
+        tokens.insert(content.to_owned(), (id.parse().ok(), added));
+    }
+    for content in [&special_tokens_map, &config]
+        .into_iter()
+        .flatten()
+        .flat_map(special_token_names)
+    {
+        let (_, token) = tokens
+            .entry(content.clone())
+            .or_insert_with(|| (None, AddedToken::from(content, true)));
+        token.special = true;
+    }
+
+    let mut tokens: Vec<_> = tokens.into_values().collect();
+    tokens.sort_by_key(|(id, _)| id.unwrap_or(u64::MAX));
+
+    Ok(tokens.into_iter().map(|(_, token)| token).collect())
+}
+
+/// Finds the tokenizer files for a local path.  A directory may hold files in any of the
+/// [`TokenizerFormat::ALL`] formats, while the format of a file is picked from its name.
+fn local_tokenizer_files(path: &Path) -> Result<TokenizerFiles, TokenizerFileError> {
+    let files_for = |format: TokenizerFormat, dir: &Path| TokenizerFiles {
+        format,
+        paths: format
+            .filenames()
+            .iter()
+            .map(|filename| dir.join(filename))
+            .collect(),
+    };
+    let files = if path.is_dir() {
+        TokenizerFormat::ALL
+            .into_iter()
+            .map(|format| files_for(format, path))
+            .find(|files| files.paths.iter().all(|path| path.is_file()))
+            .ok_or_else(|| TokenizerFileError::missing(&path.join(TOKENIZER_FILENAME)))?
+    } else {
+        let format = TokenizerFormat::from_path(path);
+        let mut files = files_for(format, path.parent().unwrap_or(Path::new("")));
+        files.paths[0] = path.to_path_buf();
+        files
+    };
+    if let Some(missing) = files.paths.iter().find(|path| !path.is_file()) {
+        return Err(TokenizerFileError::missing(missing));
+    }
+
+    Ok(files)
+}
+
+/// Returns the path of a model file, such as the model configuration, next to a local tokenizer.
+#[must_use]
+pub fn local_model_file(path: &Path, filename: &str) -> PathBuf {
+    if path.is_dir() {
+        path.join(filename)
+    } else {
+        path.with_file_name(filename)
+    }
+}
+
+/// Ensures the missing access token warning is only logged once per run.
+static MISSING_TOKEN_WARNING: Once = Once::new();
+
+/// Returns a handle for downloading files from the model repo, using the Hugging Face access
+/// token, when one is set.
+fn model_repo(repo_id: &str, revision: &str, hub: &HubOptions) -> Result<ApiRepo, AppError> {
+    let token = std::env::var("HUGGING_FACE_ACCESS_TOKEN").ok().or_else(|| {
+        MISSING_TOKEN_WARNING.call_once(|| {
+            log::warn!(
+                "`HUGGING_FACE_ACCESS_TOKEN` environment variable is not defined, using Hugging \
+                Face API without an access token.  Performance may vary."
+            );
+        });
 
         None
     });
-    let api = ApiBuilder::new()
-        .with_token(token)
-        .build()
-        .map_err(HfApiError::from)?;
-    let repo = api.repo(Repo::with_revision(
+    let mut builder = ApiBuilder::from_env().with_token(token);
+    if let Some(cache_dir) = &hub.cache_dir {
+        builder = builder.with_cache_dir(cache_dir.clone());
+    }
+    let api = builder.build().map_err(HfApiError::from)?;
+
+    Ok(api.repo(Repo::with_revision(
         repo_id.to_owned(),
         RepoType::Model,
-        "main".to_owned(),
-    ));
-    let tokeniser_filename = repo.get("tokenizer.json").map_err(HfApiError::from)?;
+        revision.to_owned(),
+    )))
+}
+
+/// Returns the path of a file from the model repo, downloading it unless already cached.  Offline,
+/// only the `hf_hub` cache is used.
+///
+/// # Errors
+/// Errors if unable to download the file or, offline, the file is not cached.
+pub fn get_repo_file(
+    repo_id: &str,
+    revision: &str,
+    filename: &str,
+    hub: &HubOptions,
+) -> Result<PathBuf, AppError> {
+    if hub.offline {
+        return get_cached_repo_file(&hub.cache(), repo_id, revision, filename);
+    }
+
+    Ok(model_repo(repo_id, revision, hub)?
+        .get(filename)
+        .map_err(HfApiError::from)?)
+}
+
+/// Returns the path of a file from the model repo in the `hf_hub` cache.
+fn get_cached_repo_file(
+    cache: &Cache,
+    repo_id: &str,
+    revision: &str,
+    filename: &str,
+) -> Result<PathBuf, AppError> {
+    let repo = Repo::with_revision(repo_id.to_owned(), RepoType::Model, revision.to_owned());
 
-    Ok(Tokenizer::from_file(tokeniser_filename).map_err(TokenizerError::from)?)
+    cache
+        .repo(repo)
+        .get(filename)
+        .ok_or_else(|| NotCachedError::new(repo_id, filename, cache.path()).into())
+}
+
+/// Returns `true` for errors meaning a repo file does not exist, or, offline, was never cached,
+/// so another tokenizer format might be available.
+#[must_use]
+pub fn is_missing_file(error: &AppError) -> bool {
+    match error {
+        AppError::NotCached(_) => true,
+        AppError::HfApi(error) => error.is_not_found(),
+        _ => false,
+    }
+}
+
+/// Finds the tokenizer files for a model repo, downloading them unless already cached.  Repos
+/// without a `tokenizer.json` file fall back to the other [`TokenizerFormat::ALL`] formats.
+///
+/// # Errors
+/// Errors if unable to download the files, returning the error for `tokenizer.json` when the repo
+/// has no tokenizer files at all.
+fn get_repo_tokenizer_files(
+    repo_id: &str,
+    revision: &str,
+    hub: &HubOptions,
+) -> Result<TokenizerFiles, AppError> {
+    let mut first_error = None;
+    for format in TokenizerFormat::ALL {
+        let paths = format
+            .filenames()
+            .iter()
+            .map(|filename| get_repo_file(repo_id, revision, filename, hub))
+            .collect::<Result<Vec<_>, _>>();
+        match paths {
+            Ok(paths) => {
+                // Download optional files, so they are next to the tokenizer files when loading
+                for filename in format.optional_filenames() {
+                    match get_repo_file(repo_id, revision, filename, hub) {
+                        Ok(_) => {}
+                        Err(error) if is_missing_file(&error) => {
+                            log::debug!("No `{filename}` in `{repo_id}`: {error}");
+                        }
+                        Err(error) => return Err(error),
+                    }
+                }
+
+                return Ok(TokenizerFiles { format, paths });
+            }
+            Err(error) if is_missing_file(&error) => {
+                log::debug!("No {format:?} tokenizer files in `{repo_id}`: {error}");
+                first_error.get_or_insert(error);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    Err(first_error.expect("every format should have been tried"))
+}
+
+/// Downloads the tokenizer files for a model repo, unless already cached, so it can be used
+/// offline.
+///
+/// # Returns
+/// A `Result` containing the path of the main tokenizer file.
+///
+/// # Errors
+/// Errors if unable to download the files, or the repo has no tokenizer files.
+pub fn fetch_repo_tokenizer(
+    repo_id: &str,
+    revision: &str,
+    hub: &HubOptions,
+) -> Result<PathBuf, AppError> {
+    let files = get_repo_tokenizer_files(repo_id, revision, hub)?;
+
+    Ok(files.primary().to_path_buf())
+}
+
+/// Checks the SHA-256 hash of the tokenizer file for `source` matches `expected`.
+fn verify_sha256(source: &TokenizerSource, path: &Path, expected: &str) -> Result<(), AppError> {
+    let content = fs::read(path).map_err(|_| TokenizerFileError::missing(path))?;
+    let actual = format!("{:x}", Sha256::digest(&content));
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(HashMismatchError::new(source, expected, &actual).into())
+    }
 }
 
-/// Counts the number of tokens in a prompt.
+/// Creates a tokenizer instance from a Hugging Face repo, local file or tiktoken encoding.
+/// `hf_hub` caches tokenizer files, so they should only be downloaded once for each model.  Repos
+/// and directories without a `tokenizer.json` file fall back to a SentencePiece `tokenizer.model`
+/// file, then to slow tokenizer `vocab.json` and `merges.txt` files.  When `pin` includes a hash,
+/// the main tokenizer file, or tiktoken rank file, is checked against it before loading.
 ///
 /// # Returns
-/// A `miette::Result` containing the number of tokens.
+/// A `Result` containing the tokenizer.
 ///
-/// # Errors if unable to encode the prompt.
-pub fn count_tokens(tokeniser: &Tokenizer, prompt: &str) -> Result<usize, AppError> {
-    let add_special_tokens = true;
-    let tokens = tokeniser
-        .encode_fast(prompt, add_special_tokens)
-        .map_err(TokenizerError::from)?
+/// # Errors
+/// Errors if unable to download or load the tokenizer files, or they do not match the pinned hash.
+pub fn create_tokeniser(
+    source: &TokenizerSource,
+    pin: &TokenizerPin,
+    hub: &HubOptions,
+) -> Result<Box<dyn TokenizerBackend>, AppError> {
+    let files = match source {
+        TokenizerSource::Hub(repo_id) => get_repo_tokenizer_files(repo_id, pin.revision(), hub)?,
+        TokenizerSource::Path(path) => local_tokenizer_files(path)?,
+        TokenizerSource::Tiktoken(encoding) => {
+            if let Some(expected) = &pin.sha256
+                && !is_built_in(encoding)
+            {
+                verify_sha256(source, Path::new(encoding), expected)?;
+            }
+            return Ok(Box::new(TiktokenBackend::load(encoding)?));
+        }
+    };
+    log::info!(
+        "Loading `{source}` tokenizer from `{}`",
+        files
+            .paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("` and `")
+    );
+    if let Some(expected) = &pin.sha256 {
+        verify_sha256(source, files.primary(), expected)?;
+    }
+
+    Ok(Box::new(files.load()?))
+}
+
+/// Checks the model repo exists and has tokenizer files, in any of the [`TokenizerFormat::ALL`]
+/// formats, without downloading them, or that the local tokenizer files exist.  Offline, checks
+/// the `hf_hub` cache holds the tokenizer files instead.
+///
+/// # Errors
+/// Errors if unable to fetch the repo details, or the repo or directory has no tokenizer files.
+pub fn check_tokeniser_available(
+    source: &TokenizerSource,
+    revision: &str,
+    hub: &HubOptions,
+) -> Result<(), AppError> {
+    match source {
+        TokenizerSource::Hub(repo_id) if hub.offline => {
+            get_repo_tokenizer_files(repo_id, revision, hub)?;
+        }
+        TokenizerSource::Hub(repo_id) => {
+            let info = model_repo(repo_id, revision, hub)?
+                .info()
+                .map_err(HfApiError::from)?;
+            let has_file = |filename: &&str| {
+                info.siblings
+                    .iter()
+                    .any(|sibling| sibling.rfilename == *filename)
+            };
+            if !TokenizerFormat::ALL
+                .iter()
+                .any(|format| format.filenames().iter().all(has_file))
+            {
+                return Err(TokenizerFileError {
+                    advice: "Check the repo is for a model, rather than a dataset or space, and \
+                        try another revision"
+                        .to_owned(),
+                    detail: format!(
+                        "Repo `{repo_id}` has no `{TOKENIZER_FILENAME}`, \
+                        `{SENTENCEPIECE_FILENAME}` or `{VOCAB_FILENAME}` and `{MERGES_FILENAME}` \
+                        files"
+                    ),
+                }
+                .into());
+            }
+        }
+        TokenizerSource::Path(path) => {
+            local_tokenizer_files(path)?;
+        }
+        TokenizerSource::Tiktoken(encoding) => {
+            if !is_built_in(encoding) && !Path::new(encoding).is_file() {
+                return Err(TokenizerFileError {
+                    advice: "Use a built-in encoding, or the path of a `.tiktoken` rank file"
+                        .to_owned(),
+                    detail: format!(
+                        "`{encoding}` is not a built-in tiktoken encoding ({}) or a rank file",
+                        built_in_names()
+                    ),
+                }
+                .into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the context length from the content of a model `config.json` file.  Most models use
+/// `max_position_embeddings`, though older GPT-style models use `n_positions`, and multimodal
+/// models nest the language model settings under `text_config`.
+///
+/// # Returns
+/// The context length, or [`None`] if the config does not include one.
+fn parse_context_length(config: &str) -> Option<usize> {
+    let config: serde_json::Value = serde_json::from_str(config).ok()?;
+    let lookup = |config: &serde_json::Value| {
+        ["max_position_embeddings", "n_positions"]
+            .iter()
+            .find_map(|key| config.get(key)?.as_u64())
+    };
+    let context_length = lookup(&config).or_else(|| lookup(config.get("text_config")?))?;
+
+    usize::try_from(context_length).ok()
+}
+
+/// Reads the context length from the model `config.json` file.  For Hugging Face repos, the
+/// config file is downloaded and, like tokenizer files, cached by `hf_hub`.  For local tokenizers,
+/// the config file is looked for next to the tokenizer file.
+///
+/// # Returns
+/// A `Result` containing the context length, or [`None`] if the config does not include one, or
+/// there is no local config file.
+///
+/// # Errors
+/// Errors if unable to download or read the config file.
+pub fn fetch_context_length(
+    source: &TokenizerSource,
+    revision: &str,
+    hub: &HubOptions,
+) -> Result<Option<usize>, AppError> {
+    let config_filename = match source {
+        TokenizerSource::Hub(repo_id) => get_repo_file(repo_id, revision, CONFIG_FILENAME, hub)?,
+        TokenizerSource::Path(path) => {
+            let config_filename = local_model_file(path, CONFIG_FILENAME);
+            if !config_filename.is_file() {
+                return Ok(None);
+            }
+            config_filename
+        }
+        // tiktoken encodings have no model config, so rely on the models file
+        TokenizerSource::Tiktoken(_) => return Ok(None),
+    };
+    let config = fs::read_to_string(&config_filename)
+        .map_err(|error| ModelFileError::unreadable(&config_filename, error))?;
+
+    Ok(parse_context_length(&config))
+}
+
+/// A single token from an encoded prompt.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenInfo {
+    /// Token ID in the tokeniser vocabulary.
+    pub id: u32,
+
+    /// Token string, as it appears in the tokeniser vocabulary.
+    pub token: String,
+
+    /// Slice of the prompt the token covers, which is empty for added special tokens.
+    pub text: String,
+
+    /// Start and end byte offsets of the token in the prompt.
+    pub bytes: (usize, usize),
+
+    /// Start and end character offsets of the token in the prompt.
+    pub chars: (usize, usize),
+
+    /// `true` for special tokens, such as beginning or end of sequence markers.
+    pub special: bool,
+}
+
+/// Builds the details for a token covering the `bytes` range of the prompt.  `char_starts` holds
+/// the byte offset of each character in the prompt, for working out character offsets.
+pub fn token_info(
+    prompt: &str,
+    char_starts: &[usize],
+    id: u32,
+    token: String,
+    (start, end): (usize, usize),
+    special: bool,
+) -> TokenInfo {
+    let char_offset =
+        |byte_offset: usize| char_starts.partition_point(|&start| start < byte_offset);
+
+    TokenInfo {
+        id,
+        token,
+        text: prompt
+            .as_bytes()
+            .get(start..end)
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default()
+            .into_owned(),
+        bytes: (start, end),
+        chars: (char_offset(start), char_offset(end)),
+        special,
+    }
+}
+
+/// Number of tokens in an encoded prompt, and how many of them are special tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenCount {
+    /// Every token, including special tokens.
+    pub tokens: usize,
+
+    /// Special tokens, such as beginning or end of sequence markers, whether added by the
+    /// tokenizer or written in the prompt.
+    pub special: usize,
+}
+
+/// A tokenizer implementation, such as a Hugging Face `tokenizers` tokenizer or a tiktoken
+/// encoding, which prompts are counted and tokenized with.
+pub trait TokenizerBackend: Send + Sync {
+    /// Counts the number of tokens in a prompt.  With `add_special_tokens`, the tokenizer adds any
+    /// special tokens it wraps prompts in, such as a beginning of sequence marker.
+    ///
+    /// # Errors
+    /// Errors if unable to encode the prompt.
+    fn count(&self, prompt: &str, add_special_tokens: bool) -> Result<TokenCount, AppError>;
+
+    /// Encodes a prompt and returns every token, along with its position in the prompt.  With
+    /// `add_special_tokens`, the tokens include any special tokens the tokenizer wraps prompts in.
+    ///
+    /// # Errors
+    /// Errors if unable to encode the prompt.
+    fn tokenize(&self, prompt: &str, add_special_tokens: bool) -> Result<Vec<TokenInfo>, AppError>;
+}
+
+/// Returns a flag for each token in the encoding, which is `true` for special tokens.  The
+/// encoding only marks special tokens added by the post-processor, so added tokens flagged as
+/// special, such as `<|im_start|>` written in a chat prompt, are looked up too.
+fn special_token_flags<'a>(
+    tokenizer: &'a Tokenizer,
+    encoding: &'a Encoding,
+) -> impl Iterator<Item = bool> + 'a {
+    let added_tokens = tokenizer.get_added_vocabulary().get_added_tokens_decoder();
+
+    encoding
         .get_ids()
-        .to_vec();
+        .iter()
+        .zip(encoding.get_special_tokens_mask())
+        .map(|(id, &special)| {
+            special == 1 || added_tokens.get(id).is_some_and(|token| token.special)
+        })
+}
+
+impl TokenizerBackend for Tokenizer {
+    fn count(&self, prompt: &str, add_special_tokens: bool) -> Result<TokenCount, AppError> {
+        let encoding = self
+            .encode_fast(prompt, add_special_tokens)
+            .map_err(TokenizerError::from)?;
+
+        Ok(TokenCount {
+            tokens: encoding.len(),
+            special: special_token_flags(self, &encoding)
+                .filter(|&special| special)
+                .count(),
+        })
+    }
+
+    fn tokenize(&self, prompt: &str, add_special_tokens: bool) -> Result<Vec<TokenInfo>, AppError> {
+        let encoding = self
+            .encode(prompt, add_special_tokens)
+            .map_err(TokenizerError::from)?;
+        let char_starts: Vec<usize> = prompt.char_indices().map(|(index, _)| index).collect();
 
-    Ok(tokens.len())
+        Ok(encoding
+            .get_ids()
+            .iter()
+            .zip(encoding.get_tokens())
+            .zip(encoding.get_offsets())
+            .zip(special_token_flags(self, &encoding))
+            .map(|(((&id, token), &bytes), special)| {
+                token_info(prompt, &char_starts, id, token.to_owned(), bytes, special)
+            })
+            .collect())
+    }
+}
+
+/// Counts the number of tokens in a prompt, and how many of them are special tokens.  With
+/// `add_special_tokens`, the count includes any special tokens the tokenizer wraps prompts in.
+///
+/// # Returns
+/// A `Result` containing the token counts.
+///
+/// # Errors
+/// Errors if unable to encode the prompt.
+pub fn count_tokens(
+    tokeniser: &dyn TokenizerBackend,
+    prompt: &str,
+    add_special_tokens: bool,
+) -> Result<TokenCount, AppError> {
+    tokeniser.count(prompt, add_special_tokens)
+}
+
+/// Encodes a prompt and returns every token, along with its position in the prompt.
+///
+/// # Returns
+/// A `Result` containing the tokens, in order.
+///
+/// # Errors
+/// Errors if unable to encode the prompt.
+pub fn tokenize(
+    tokeniser: &dyn TokenizerBackend,
+    prompt: &str,
+    add_special_tokens: bool,
+) -> Result<Vec<TokenInfo>, AppError> {
+    tokeniser.tokenize(prompt, add_special_tokens)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::token::{count_tokens, create_tokeniser};
+    use std::path::{Path, PathBuf};
+
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteStr, PathChild},
+    };
+    use hf_hub::Cache;
+    use tokenizers::Tokenizer;
+
+    use crate::{
+        errors::{AppError, EXIT_HF_API, EXIT_TOKENIZER, exit_code},
+        token::{
+            HubOptions, TokenCount, TokenInfo, TokenizerFiles, TokenizerFormat, TokenizerPin,
+            TokenizerSource, count_tokens, create_tokeniser, get_cached_repo_file,
+            get_repo_tokenizer_files, is_truthy, local_tokenizer_files, parse_context_length,
+            tokenize,
+        },
+    };
 
     #[test]
     fn create_tokeniser_returns_expected_value() {
         // arrange
-        let repo_id = "Qwen/Qwen3-1.7B";
+        let source = TokenizerSource::Hub("Qwen/Qwen3-1.7B".to_owned());
 
         // act
-        let tokeniser = create_tokeniser(repo_id);
+        let tokeniser = create_tokeniser(&source, &TokenizerPin::default(), &HubOptions::default());
 
         // assert
         assert!(tokeniser.is_ok());
     }
 
+    #[test]
+    fn create_tokeniser_loads_local_files_and_directories() {
+        // arrange
+        let file = TokenizerSource::Path(PathBuf::from("tests/fixtures/tokenizer.json"));
+        let directory = TokenizerSource::Path(PathBuf::from("tests/fixtures"));
+
+        // act
+        let file_count = count_tokens(
+            create_tokeniser(&file, &TokenizerPin::default(), &HubOptions::default())
+                .unwrap()
+                .as_ref(),
+            "Why is the sky blue?",
+            true,
+        );
+        let directory_count = count_tokens(
+            create_tokeniser(&directory, &TokenizerPin::default(), &HubOptions::default())
+                .unwrap()
+                .as_ref(),
+            "Why is the sky blue?",
+            false,
+        );
+
+        // assert
+        assert_eq!(
+            file_count.unwrap(),
+            TokenCount {
+                tokens: 7,
+                special: 1
+            }
+        );
+        assert_eq!(
+            directory_count.unwrap(),
+            TokenCount {
+                tokens: 6,
+                special: 0
+            }
+        );
+    }
+
+    #[test]
+    fn create_tokeniser_returns_error_for_missing_local_file() {
+        // arrange
+        let source = TokenizerSource::Path(PathBuf::from("tests"));
+
+        // act
+        let outcome = create_tokeniser(&source, &TokenizerPin::default(), &HubOptions::default())
+            .map(drop)
+            .unwrap_err();
+
+        // assert
+        assert_eq!(
+            format!("{outcome}"),
+            format!(
+                "No tokenizer file at `{}`",
+                Path::new("tests").join("tokenizer.json").display()
+            )
+        );
+        assert_eq!(outcome.exit_code(), EXIT_TOKENIZER);
+    }
+
+    #[test]
+    fn local_tokenizer_files_falls_back_to_other_formats() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir.child("spm/tokenizer.model").write_str("");
+        let _ = temp_dir.child("gpt/vocab.json").write_str("{}");
+        let _ = temp_dir.child("gpt/merges.txt").write_str("");
+        let _ = temp_dir.child("incomplete/vocab.json").write_str("{}");
+
+        // act
+        let sentencepiece = local_tokenizer_files(&temp_dir.path().join("spm")).unwrap();
+        let vocab_merges = local_tokenizer_files(&temp_dir.path().join("gpt")).unwrap();
+        let vocab_file =
+            local_tokenizer_files(&temp_dir.path().join("gpt").join("vocab.json")).unwrap();
+        let outcome = local_tokenizer_files(&temp_dir.path().join("incomplete").join("vocab.json"))
+            .unwrap_err();
+
+        // assert
+        assert_eq!(
+            sentencepiece,
+            TokenizerFiles {
+                format: TokenizerFormat::SentencePiece,
+                paths: vec![temp_dir.path().join("spm").join("tokenizer.model")],
+            }
+        );
+        let expected = TokenizerFiles {
+            format: TokenizerFormat::VocabMerges,
+            paths: vec![
+                temp_dir.path().join("gpt").join("vocab.json"),
+                temp_dir.path().join("gpt").join("merges.txt"),
+            ],
+        };
+        assert_eq!(vocab_merges, expected);
+        assert_eq!(vocab_file, expected);
+        assert_eq!(
+            format!("{outcome}"),
+            format!(
+                "No tokenizer file at `{}`",
+                temp_dir
+                    .path()
+                    .join("incomplete")
+                    .join("merges.txt")
+                    .display()
+            )
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn create_tokeniser_loads_vocab_and_merges_files() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir
+            .child("vocab.json")
+            .write_str(r#"{ "h": 0, "i": 1, "hi": 2, "Ġ": 3, "Ġhi": 4 }"#);
+        let _ = temp_dir
+            .child("merges.txt")
+            .write_str("#version: 0.2\nh i\nĠ hi\n");
+        let source = TokenizerSource::Path(temp_dir.path().to_path_buf());
+        let tokeniser =
+            create_tokeniser(&source, &TokenizerPin::default(), &HubOptions::default()).unwrap();
+
+        // act
+        let outcome = tokenize(tokeniser.as_ref(), "hi hi", true).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome
+                .iter()
+                .map(|token| token.token.as_str())
+                .collect::<Vec<_>>(),
+            ["hi", "Ġhi"]
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn create_tokeniser_adds_special_and_added_tokens_to_vocab_and_merges_files() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let _ = temp_dir
+            .child("vocab.json")
+            .write_str(r#"{ "h": 0, "i": 1, "hi": 2, "Ġ": 3, "Ġhi": 4, "<|endoftext|>": 5 }"#);
+        let _ = temp_dir
+            .child("merges.txt")
+            .write_str("#version: 0.2\nh i\nĠ hi\n");
+        let _ = temp_dir
+            .child("added_tokens.json")
+            .write_str(r#"{ "<|sep|>": 6 }"#);
+        let _ = temp_dir
+            .child("special_tokens_map.json")
+            .write_str(r#"{ "eos_token": { "content": "<|endoftext|>" } }"#);
+        let source = TokenizerSource::Path(temp_dir.path().to_path_buf());
+        let tokeniser =
+            create_tokeniser(&source, &TokenizerPin::default(), &HubOptions::default()).unwrap();
+
+        // act
+        let outcome = tokenize(tokeniser.as_ref(), "hi<|sep|>hi<|endoftext|>", true).unwrap();
+        let count = count_tokens(tokeniser.as_ref(), "hi<|sep|>hi<|endoftext|>", true).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome
+                .iter()
+                .map(|token| (token.token.as_str(), token.id, token.special))
+                .collect::<Vec<_>>(),
+            [
+                ("hi", 2, false),
+                ("<|sep|>", 6, false),
+                ("hi", 2, false),
+                ("<|endoftext|>", 5, true)
+            ]
+        );
+        assert_eq!(
+            count,
+            TokenCount {
+                tokens: 4,
+                special: 1
+            }
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn get_repo_tokenizer_files_falls_back_to_a_cached_sentencepiece_model() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let repo_dir = temp_dir.child("models--example--Example-SPM-1-B");
+        let _ = repo_dir.child("refs/main").write_str("0123abcd");
+        let _ = repo_dir
+            .child("snapshots/0123abcd/tokenizer.model")
+            .write_str("");
+        let hub = HubOptions {
+            offline: true,
+            cache_dir: Some(temp_dir.path().to_path_buf()),
+        };
+
+        // act
+        let files = get_repo_tokenizer_files("example/Example-SPM-1-B", "main", &hub).unwrap();
+        let outcome = get_repo_tokenizer_files("example/Missing-1-B", "main", &hub)
+            .map_err(miette::Report::from)
+            .unwrap_err();
+
+        // assert
+        assert_eq!(files.format, TokenizerFormat::SentencePiece);
+        assert_eq!(
+            files.paths,
+            [repo_dir.path().join("snapshots/0123abcd/tokenizer.model")]
+        );
+        assert!(
+            format!("{outcome}").contains("`tokenizer.json` for `example/Missing-1-B` was never")
+        );
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn create_tokeniser_checks_the_pinned_hash() {
+        // arrange
+        let source = TokenizerSource::Path(PathBuf::from("tests/fixtures/tokenizer.json"));
+        let matching = TokenizerPin {
+            revision: None,
+            sha256: Some(
+                "078046FEEE4EC8D14AE7F8E9427C8A1471BDD348E793D2AC2A2F55218BAD7D59".to_owned(),
+            ),
+        };
+        let mismatched = TokenizerPin {
+            revision: None,
+            sha256: Some("0".repeat(64)),
+        };
+
+        // act
+        let matching_outcome = create_tokeniser(&source, &matching, &HubOptions::default());
+        let outcome = create_tokeniser(&source, &mismatched, &HubOptions::default())
+            .map(drop)
+            .map_err(miette::Report::from)
+            .unwrap_err();
+
+        // assert
+        assert!(matching_outcome.is_ok());
+        assert_eq!(
+            format!("{outcome}"),
+            format!(
+                "Tokenizer for `tests/fixtures/tokenizer.json` has SHA-256 hash \
+                `078046feee4ec8d14ae7f8e9427c8a1471bdd348e793d2ac2a2f55218bad7d59`, but the \
+                models file expects `{}`",
+                "0".repeat(64)
+            )
+        );
+        assert!(matches!(
+            outcome.downcast_ref::<AppError>(),
+            Some(AppError::HashMismatch(_))
+        ));
+        assert_eq!(exit_code(&outcome), EXIT_TOKENIZER);
+    }
+
+    #[test]
+    fn override_revision_drops_the_hash_for_other_revisions() {
+        // arrange
+        let pin = TokenizerPin {
+            revision: Some("v1.0".to_owned()),
+            sha256: Some("0".repeat(64)),
+        };
+        let mut same = pin.clone();
+        let mut other = pin.clone();
+
+        // act
+        same.override_revision("v1.0");
+        other.override_revision("v2.0");
+
+        // assert
+        assert_eq!(same, pin);
+        assert_eq!(
+            other,
+            TokenizerPin {
+                revision: Some("v2.0".to_owned()),
+                sha256: None,
+            }
+        );
+        assert_eq!(TokenizerPin::default().revision(), "main");
+    }
+
+    #[test]
+    fn get_cached_repo_file_finds_files_in_the_cache() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let repo_dir = temp_dir.child("models--example--Example-1-M-state-of-the-art");
+        let _ = repo_dir.child("refs/v1.0").write_str("0123abcd");
+        let _ = repo_dir
+            .child("snapshots/0123abcd/tokenizer.json")
+            .write_str("{}");
+        let cache = Cache::new(temp_dir.path().to_path_buf());
+
+        // act
+        let path = get_cached_repo_file(
+            &cache,
+            "example/Example-1-M-state-of-the-art",
+            "v1.0",
+            "tokenizer.json",
+        )
+        .unwrap();
+
+        // assert
+        assert_eq!(
+            path,
+            repo_dir
+                .path()
+                .join("snapshots")
+                .join("0123abcd")
+                .join("tokenizer.json")
+        );
+    }
+
+    #[test]
+    fn get_cached_repo_file_returns_not_cached_error_for_missing_files() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        let cache = Cache::new(temp_dir.path().to_path_buf());
+
+        // act
+        let outcome = get_cached_repo_file(&cache, "example/Gated-70-B", "main", "tokenizer.json")
+            .map_err(miette::Report::from)
+            .unwrap_err();
+
+        // assert
+        assert_eq!(
+            format!("{outcome}"),
+            format!(
+                "Running offline and `tokenizer.json` for `example/Gated-70-B` was never \
+                downloaded to the Hugging Face cache at `{}`",
+                temp_dir.path().display()
+            )
+        );
+        assert!(matches!(
+            outcome.downcast_ref::<AppError>(),
+            Some(AppError::NotCached(_))
+        ));
+        assert_eq!(exit_code(&outcome), EXIT_HF_API);
+    }
+
+    #[test]
+    fn is_truthy_accepts_common_true_values() {
+        // arrange
+        let values = ["1", "true", "YES", " on ", "0", "false", "off", ""];
+
+        // act
+        let outcome = values.map(is_truthy);
+
+        // assert
+        assert_eq!(
+            outcome,
+            [true, true, true, true, false, false, false, false]
+        );
+    }
+
     #[test]
     fn count_tokens_returns_expected_value() {
         // arrange
-        let repo_id = "Qwen/Qwen3-1.7B";
-        let tokeniser = create_tokeniser(repo_id).unwrap();
+        let source = TokenizerSource::Hub("Qwen/Qwen3-1.7B".to_owned());
+        let tokeniser =
+            create_tokeniser(&source, &TokenizerPin::default(), &HubOptions::default()).unwrap();
 
         // act
-        let count = count_tokens(&tokeniser, "Why is the sky blue?").unwrap();
+        let count = count_tokens(tokeniser.as_ref(), "Why is the sky blue?", true).unwrap();
 
         // assert
-        assert_eq!(count, 6);
+        assert_eq!(count.tokens, 6);
+    }
+
+    #[test]
+    fn parse_context_length_reads_top_level_and_text_config_values() {
+        // arrange
+        let config =
+            r#"{ "architectures": ["Qwen3ForCausalLM"], "max_position_embeddings": 40960 }"#;
+        let multimodal_config = r#"{ "text_config": { "max_position_embeddings": 131072 } }"#;
+        let gpt_config = r#"{ "n_positions": 1024 }"#;
+
+        // act
+        let outcome =
+            [config, multimodal_config, gpt_config, "{}", "not json"].map(parse_context_length);
+
+        // assert
+        assert_eq!(
+            outcome,
+            [Some(40_960), Some(131_072), Some(1_024), None, None]
+        );
+    }
+
+    #[test]
+    fn tokenize_returns_expected_tokens_and_offsets() {
+        // arrange
+        let tokeniser = Tokenizer::from_file("tests/fixtures/tokenizer.json").unwrap();
+
+        // act
+        let outcome = tokenize(&tokeniser, "Héllo world!", true).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            vec![
+                TokenInfo {
+                    id: 1,
+                    token: "[BOS]".to_owned(),
+                    text: String::new(),
+                    bytes: (0, 0),
+                    chars: (0, 0),
+                    special: true,
+                },
+                TokenInfo {
+                    id: 0,
+                    token: "[UNK]".to_owned(),
+                    text: "Héllo".to_owned(),
+                    bytes: (0, 6),
+                    chars: (0, 5),
+                    special: false,
+                },
+                TokenInfo {
+                    id: 11,
+                    token: "world".to_owned(),
+                    text: "world".to_owned(),
+                    bytes: (7, 12),
+                    chars: (6, 11),
+                    special: false,
+                },
+                TokenInfo {
+                    id: 12,
+                    token: "!".to_owned(),
+                    text: "!".to_owned(),
+                    bytes: (12, 13),
+                    chars: (11, 12),
+                    special: false,
+                },
+            ]
+        );
     }
 }
@@ -1,7 +1,14 @@
-use std::{fs, io, path::Path};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
 
 use miette::{Context, IntoDiagnostic, bail};
 
+/// Maximum size, in bytes, of a prompt file or piped input.
+const MAX_FILE_SIZE: u64 = 10_485_760;
+
 /// Reads the content of a file into a string.
 ///
 /// # Errors
@@ -30,7 +37,7 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> miette::Result<String> {
         .into_diagnostic()
         .wrap_err(format!("Error opening file `{}`", path.as_ref().display()))?;
     let filesize = metadata.len();
-    if filesize > 10_485_760 {
+    if filesize > MAX_FILE_SIZE {
         bail!("File is too large.")
     }
     if filesize == 0 {
@@ -62,13 +69,39 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> miette::Result<String> {
         .wrap_err(format!("Error reading file `{}`", path.as_ref().display()))
 }
 
+/// Reads standard input (or any other reader) into a string.  Reading stops once the input
+/// exceeds the maximum file size, so piping in a huge stream does not exhaust memory.
+///
+/// # Errors
+/// Errors if the input is not valid UTF-8, cannot be read, or is too large.
+pub fn read_stdin<R: Read>(reader: R) -> miette::Result<String> {
+    let mut buffer = Vec::new();
+    reader
+        .take(MAX_FILE_SIZE + 1)
+        .read_to_end(&mut buffer)
+        .into_diagnostic()
+        .wrap_err("Error reading standard input")?;
+    if buffer.len() as u64 > MAX_FILE_SIZE {
+        bail!("Standard input is too large.")
+    }
+    if buffer.is_empty() {
+        log::warn!("Standard input is empty.");
+    }
+    String::from_utf8(buffer)
+        .inspect_err(|_| {
+            log::error!("Unable to read standard input.  Check it only contains valid UTF-8 data.");
+        })
+        .into_diagnostic()
+        .wrap_err("Error reading standard input")
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
 
     use assert_fs::prelude::{FileWriteBin, FileWriteStr as _, PathChild as _};
 
-    use crate::utility::read_file;
+    use crate::utility::{read_file, read_stdin};
 
     #[test]
     fn read_file_handles_valid_input() {
@@ -213,4 +246,54 @@ mod tests {
         // cleanup
         temp_dir.close().unwrap();
     }
+
+    #[test]
+    fn read_stdin_handles_valid_input() {
+        // arrange
+        let content = "This is a valid UTF-8 string.";
+
+        // act
+        let result = read_stdin(content.as_bytes()).unwrap();
+
+        // assert
+        assert_eq!(result, content);
+    }
+
+    #[test]
+    fn read_stdin_returns_error_if_input_contains_non_utf8_bytes() {
+        // arrange
+        let content: &[u8] = &[0xF8, 0x82, 0x80];
+
+        // act
+        let outcome = read_stdin(content).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some("Error reading standard input".to_owned())
+        );
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some("invalid utf-8 sequence of 1 bytes from index 0".to_owned())
+        );
+        assert!(chain.next().is_none());
+    }
+
+    #[test]
+    fn read_stdin_returns_error_if_input_is_too_large() {
+        // arrange
+        let content = vec![b'a'; 10_485_761];
+
+        // act
+        let outcome = read_stdin(content.as_slice()).unwrap_err();
+
+        // assert
+        let mut chain = outcome.chain();
+        assert_eq!(
+            chain.next().map(|val| format!("{val}")),
+            Some("Standard input is too large.".to_owned())
+        );
+        assert!(chain.next().is_none());
+    }
 }
@@ -0,0 +1,246 @@
+use tokenator::token::TokenInfo;
+
+/// 256-colour palette background codes, cycled through to mark consecutive tokens.
+const BACKGROUND_COLOURS: [u8; 5] = [153, 186, 217, 151, 223];
+
+/// ANSI escape sequence to reset all styling.
+const RESET: &str = "\x1b[0m";
+
+/// ANSI escape sequence for dimmed, reversed text, used for special and byte-fallback tokens.
+const MARKER_STYLE: &str = "\x1b[2;7m";
+
+/// How a token is drawn.
+enum Segment<'a> {
+    /// Token covering a slice of the prompt.
+    Text(&'a str),
+
+    /// Special or byte-fallback token, drawn as its vocabulary string.
+    Marker(&'a str),
+}
+
+/// Returns `true` for byte-fallback tokens, such as `<0xE2>`, which some tokenisers use for
+/// characters missing from the vocabulary.
+fn is_byte_fallback(token: &str) -> bool {
+    token.len() == 6
+        && token.starts_with("<0x")
+        && token.ends_with('>')
+        && token
+            .get(3..5)
+            .is_some_and(|hex| hex.chars().all(|char| char.is_ascii_hexdigit()))
+}
+
+/// Replaces whitespace and control characters with visible symbols.  Newlines are kept after the
+/// symbol, so the visualisation keeps the prompt's line structure.
+pub fn show_whitespace(text: &str) -> String {
+    text.chars()
+        .map(|char| match char {
+            ' ' => "·".to_owned(),
+            '\t' => "→".to_owned(),
+            '\n' => "↵\n".to_owned(),
+            '\r' => "␍".to_owned(),
+            _ if char.is_control() => char.escape_default().to_string(),
+            _ => char.to_string(),
+        })
+        .collect()
+}
+
+/// Draws a single token.  With `colour`, the token gets a background colour from the palette,
+/// which is reset before any newline, so colours do not bleed to the line end.  Without `colour`,
+/// tokens are wrapped in square brackets instead.
+fn render_segment(segment: &Segment, index: usize, colour: bool) -> String {
+    match (segment, colour) {
+        (Segment::Text(text), true) => {
+            let style = format!(
+                "\x1b[38;5;16;48;5;{}m",
+                BACKGROUND_COLOURS[index % BACKGROUND_COLOURS.len()]
+            );
+            show_whitespace(text)
+                .split_inclusive('\n')
+                .map(|line| match line.strip_suffix('\n') {
+                    Some(line) => format!("{style}{line}{RESET}\n"),
+                    None => format!("{style}{line}{RESET}"),
+                })
+                .collect()
+        }
+        (Segment::Marker(token), true) => format!("{MARKER_STYLE}{token}{RESET}"),
+        (Segment::Text(text), false) => format!("[{}]", show_whitespace(text)),
+        (Segment::Marker(token), false) => format!("[{token}]"),
+    }
+}
+
+/// Reprints the prompt with each token boundary marked.  Text which no token covers, such as
+/// whitespace some tokenisers drop, is printed unmarked, while special tokens and byte-fallback
+/// tokens are printed as their vocabulary strings, since they do not map neatly onto prompt text.
+pub fn render_visualisation(prompt: &str, tokens: &[TokenInfo], colour: bool) -> String {
+    let mut output = String::new();
+    let mut position = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        let (start, end) = token.bytes;
+        if let Some(gap) = prompt.get(position..start) {
+            output.push_str(gap);
+        }
+        let segment = if token.special || is_byte_fallback(&token.token) || start < position {
+            Segment::Marker(&token.token)
+        } else {
+            Segment::Text(prompt.get(start..end).unwrap_or_default())
+        };
+        position = position.max(end);
+        output.push_str(&render_segment(&segment, index, colour));
+    }
+    output.push_str(prompt.get(position..).unwrap_or_default());
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use tokenizers::Tokenizer;
+
+    use tokenator::token::{TokenInfo, tokenize};
+
+    use crate::visualise::{is_byte_fallback, render_visualisation};
+
+    fn get_tokens_fixture() -> Vec<TokenInfo> {
+        vec![
+            TokenInfo {
+                id: 1,
+                token: "<s>".to_owned(),
+                text: String::new(),
+                bytes: (0, 0),
+                chars: (0, 0),
+                special: true,
+            },
+            TokenInfo {
+                id: 9_906,
+                token: "Hello".to_owned(),
+                text: "Hello".to_owned(),
+                bytes: (0, 5),
+                chars: (0, 5),
+                special: false,
+            },
+            TokenInfo {
+                id: 1_917,
+                token: "▁world".to_owned(),
+                text: " world".to_owned(),
+                bytes: (5, 11),
+                chars: (5, 11),
+                special: false,
+            },
+            TokenInfo {
+                id: 13,
+                token: "<0x0A>".to_owned(),
+                text: "\n".to_owned(),
+                bytes: (11, 12),
+                chars: (11, 12),
+                special: false,
+            },
+            TokenInfo {
+                id: 230,
+                token: "<0xE2>".to_owned(),
+                text: "✓".to_owned(),
+                bytes: (12, 15),
+                chars: (12, 13),
+                special: false,
+            },
+            TokenInfo {
+                id: 159,
+                token: "<0x9C>".to_owned(),
+                text: "✓".to_owned(),
+                bytes: (12, 15),
+                chars: (12, 13),
+                special: false,
+            },
+            TokenInfo {
+                id: 150,
+                token: "<0x93>".to_owned(),
+                text: "✓".to_owned(),
+                bytes: (12, 15),
+                chars: (12, 13),
+                special: false,
+            },
+            TokenInfo {
+                id: 29_871,
+                token: "\t!".to_owned(),
+                text: "\t!".to_owned(),
+                bytes: (15, 17),
+                chars: (13, 15),
+                special: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn is_byte_fallback_matches_byte_tokens_only() {
+        assert!(is_byte_fallback("<0x0A>"));
+        assert!(is_byte_fallback("<0xe2>"));
+        assert!(!is_byte_fallback("<0xZZ>"));
+        assert!(!is_byte_fallback("<s>"));
+        assert!(!is_byte_fallback("0x0A"));
+    }
+
+    #[test]
+    fn render_visualisation_generates_plain_markers() {
+        // arrange
+        let prompt = "Hello world\n✓\t!";
+        let tokens = get_tokens_fixture();
+
+        // act
+        let outcome = render_visualisation(prompt, &tokens, false);
+
+        // assert
+        assert_eq!(
+            outcome,
+            "[<s>][Hello][·world][<0x0A>][<0xE2>][<0x9C>][<0x93>][→!]\n"
+        );
+    }
+
+    #[test]
+    fn render_visualisation_generates_coloured_output() {
+        // arrange
+        let prompt = "Hi\nthere";
+        let tokens = vec![
+            TokenInfo {
+                id: 1,
+                token: "Hi\n".to_owned(),
+                text: "Hi\n".to_owned(),
+                bytes: (0, 3),
+                chars: (0, 3),
+                special: false,
+            },
+            TokenInfo {
+                id: 2,
+                token: "there".to_owned(),
+                text: "there".to_owned(),
+                bytes: (3, 8),
+                chars: (3, 8),
+                special: false,
+            },
+        ];
+
+        // act
+        let outcome = render_visualisation(prompt, &tokens, true);
+
+        // assert
+        assert_eq!(
+            outcome,
+            "\x1b[38;5;16;48;5;153mHi↵\x1b[0m\n\x1b[38;5;16;48;5;186mthere\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn render_visualisation_keeps_text_not_covered_by_tokens() {
+        // arrange
+        let tokeniser = Tokenizer::from_file("tests/fixtures/tokenizer.json").unwrap();
+        let prompt = "Why is  the sky blue?";
+        let tokens = tokenize(&tokeniser, prompt, true).unwrap();
+
+        // act
+        let outcome = render_visualisation(prompt, &tokens, false);
+
+        // assert
+        assert_eq!(outcome, "[[BOS]][Why] [is]  [the] [sky] [blue][?]\n");
+    }
+}
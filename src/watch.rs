@@ -0,0 +1,303 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use miette::{IntoDiagnostic, WrapErr, bail};
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
+use tokenator::{HubOptions, ResolvedModel, TokenCounter};
+
+use crate::{
+    budget::Budget,
+    files::{
+        PromptSource, SourceCounts, WalkOptions, count_sources, glob_root, is_glob_pattern,
+        read_sources, resolve_inputs,
+    },
+    output::{WatchReport, render_watch_report},
+    prompt::is_stdin_path,
+};
+
+/// Time to wait after the last change before recounting, so saving several files at once, or an
+/// editor writing a file in several steps, only recounts once.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Options for `--watch` runs.
+pub struct WatchOptions<'a> {
+    pub files: &'a [PathBuf],
+    pub inputs: &'a [String],
+    pub walk_options: &'a WalkOptions,
+    pub add_special_tokens: bool,
+    pub budget: Budget,
+}
+
+/// A file or directory to watch for changes.
+#[derive(Debug, PartialEq, Eq)]
+struct WatchTarget {
+    /// Absolute path, so it matches the paths in change events.
+    path: PathBuf,
+
+    /// Directory the watcher is registered on.  Files are watched through their parent
+    /// directory, since editors often save by replacing the file, which ends a watch on the file
+    /// itself.
+    directory: PathBuf,
+    mode: RecursiveMode,
+}
+
+/// Returns what to watch for each file, directory or glob pattern argument.  Directories and
+/// glob pattern roots are watched recursively, so new files are picked up.
+fn watch_targets(arguments: &[String]) -> miette::Result<Vec<WatchTarget>> {
+    if arguments.is_empty() {
+        bail!("`--watch` needs files, directories or glob patterns to watch");
+    }
+    let mut targets = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+        if is_stdin_path(argument) {
+            bail!("`--watch` cannot watch standard input");
+        }
+        let path = Path::new(argument);
+        let (path, is_file) = if path.is_dir() {
+            (path.to_path_buf(), false)
+        } else if path.exists() {
+            (path.to_path_buf(), true)
+        } else if is_glob_pattern(argument) {
+            (glob_root(argument), false)
+        } else {
+            bail!(
+                help = "Pass the files or directories holding the prompt to watch them",
+                "`--watch` needs files, directories or glob patterns to watch, and `{argument}` \
+                is none of these"
+            );
+        };
+        let path = std::path::absolute(&path)
+            .into_diagnostic()
+            .wrap_err(format!("Resolving `{}`", path.display()))?;
+        let target = match path.parent().filter(|_| is_file) {
+            Some(parent) => WatchTarget {
+                directory: parent.to_path_buf(),
+                path,
+                mode: RecursiveMode::NonRecursive,
+            },
+            None => WatchTarget {
+                directory: path.clone(),
+                path,
+                mode: RecursiveMode::Recursive,
+            },
+        };
+        targets.push(target);
+    }
+
+    Ok(targets)
+}
+
+/// Returns `true` if a change to `path` could change the counts, as it is a watched file, or is
+/// under a watched directory.
+fn is_watched(path: &Path, targets: &[WatchTarget]) -> bool {
+    targets.iter().any(|target| path.starts_with(&target.path))
+}
+
+/// Reads the inputs afresh, picking up added and removed files, and counts them with each
+/// counter.
+fn count_inputs(
+    counters: &[TokenCounter],
+    options: &WatchOptions,
+) -> miette::Result<Vec<SourceCounts>> {
+    let sources = resolve_inputs(options.files, options.inputs, options.walk_options)?;
+    let loaded = read_sources(&sources, io::empty())?;
+
+    counters
+        .iter()
+        .map(|counter| {
+            count_sources(counter.tokeniser(), &loaded, options.add_special_tokens)
+                .map_err(Into::into)
+        })
+        .collect()
+}
+
+/// Counts the inputs and prints the report, with changes since the previous run, unless the counts
+/// are the same as last time.  Errors, including prompts over budget, are printed, rather than
+/// ending the run, so the watch carries on once the inputs are fixed.
+fn recount(
+    counters: &[TokenCounter],
+    options: &WatchOptions,
+    previous: &mut Option<Vec<SourceCounts>>,
+) {
+    let counts = match count_inputs(counters, options) {
+        Ok(counts) => counts,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            return;
+        }
+    };
+    if previous.as_ref() == Some(&counts) {
+        log::debug!("Counts unchanged, skipping the report");
+        return;
+    }
+
+    let reports: Vec<WatchReport> = counters
+        .iter()
+        .zip(&counts)
+        .enumerate()
+        .map(|(index, (counter, counts))| WatchReport {
+            model: counter.model(),
+            counts,
+            previous: previous.as_ref().map(|previous| &previous[index]),
+        })
+        .collect();
+    if previous.is_some() {
+        println!();
+    }
+    print!("{}", render_watch_report(&reports));
+    for report in &reports {
+        if let Err(error) = options
+            .budget
+            .check(report.model, report.counts.total_tokens())
+        {
+            eprintln!("Error: {error:?}");
+        }
+    }
+
+    *previous = Some(counts);
+}
+
+/// Counts the inputs, then watches them, recounting whenever they change, until the process is
+/// stopped.  Each model tokenizer is loaded once, up front, so recounts are quick.
+///
+/// # Errors
+/// Errors if an argument cannot be watched, a tokenizer cannot be loaded, or the file watcher
+/// fails to start.
+pub fn run_watch(
+    models: &[ResolvedModel],
+    options: &WatchOptions,
+    hub: &HubOptions,
+) -> miette::Result<()> {
+    let arguments: Vec<String> = options
+        .files
+        .iter()
+        .map(|file| file.to_string_lossy().into_owned())
+        .chain(options.inputs.iter().cloned())
+        .collect();
+    let targets = watch_targets(&arguments)?;
+    // A lone input matching no files is counted as prompt text, even if it looks like a pattern
+    if resolve_inputs(options.files, options.inputs, options.walk_options)?
+        .iter()
+        .any(|source| matches!(source, PromptSource::Text(_)))
+    {
+        bail!("`--watch` needs files, directories or glob patterns to watch, not prompt text");
+    }
+    let counters = models
+        .iter()
+        .map(|model| TokenCounter::load(model.clone(), hub))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, sender)
+        .into_diagnostic()
+        .wrap_err("Starting the file watcher")?;
+    for target in &targets {
+        debouncer
+            .watcher()
+            .watch(&target.directory, target.mode)
+            .into_diagnostic()
+            .wrap_err(format!("Watching `{}`", target.directory.display()))?;
+    }
+    eprintln!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        arguments.join(", ")
+    );
+
+    let mut previous = None;
+    recount(&counters, options, &mut previous);
+    for outcome in receiver {
+        match outcome {
+            Ok(events) if events.iter().any(|event| is_watched(&event.path, &targets)) => {
+                recount(&counters, options, &mut previous);
+            }
+            Ok(_) => {}
+            Err(error) => log::warn!("File watcher error: {error}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::{
+        TempDir,
+        prelude::{FileWriteStr, PathChild},
+    };
+    use notify_debouncer_mini::notify::RecursiveMode;
+
+    use crate::watch::{WatchTarget, is_watched, watch_targets};
+
+    #[test]
+    fn watch_targets_watches_directories_and_glob_roots_recursively() {
+        // arrange
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("prompts/system.md")
+            .write_str("Why is the sky blue?")
+            .unwrap();
+        let directory = temp_dir.join("prompts").display().to_string();
+        let file = temp_dir.join("prompts/system.md").display().to_string();
+        let pattern = temp_dir.join("prompts/**/*.md").display().to_string();
+
+        // act
+        let outcome = watch_targets(&[directory, file.clone(), pattern]).unwrap();
+        let file_only = watch_targets(&[file]).unwrap();
+
+        // assert
+        assert_eq!(
+            outcome,
+            [
+                WatchTarget {
+                    path: temp_dir.join("prompts"),
+                    directory: temp_dir.join("prompts"),
+                    mode: RecursiveMode::Recursive
+                },
+                WatchTarget {
+                    path: temp_dir.join("prompts/system.md"),
+                    directory: temp_dir.join("prompts"),
+                    mode: RecursiveMode::NonRecursive
+                },
+                WatchTarget {
+                    path: temp_dir.join("prompts"),
+                    directory: temp_dir.join("prompts"),
+                    mode: RecursiveMode::Recursive
+                }
+            ]
+        );
+        assert!(is_watched(&temp_dir.join("prompts/new/user.md"), &outcome));
+        assert!(!is_watched(&temp_dir.join("notes.md"), &outcome));
+        assert!(!is_watched(&temp_dir.join("prompts/user.md"), &file_only));
+
+        // cleanup
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn watch_targets_rejects_standard_input_and_prompt_text() {
+        // arrange
+        let arguments = [
+            vec![],
+            vec!["-".to_owned()],
+            vec!["Why is the sky blue".to_owned()],
+        ];
+
+        // act
+        let outcome = arguments.map(|arguments| watch_targets(&arguments).unwrap_err().to_string());
+
+        // assert
+        assert_eq!(
+            outcome,
+            [
+                "`--watch` needs files, directories or glob patterns to watch",
+                "`--watch` cannot watch standard input",
+                "`--watch` needs files, directories or glob patterns to watch, and `Why is the \
+                sky blue` is none of these"
+            ]
+        );
+    }
+}